@@ -1,41 +1,130 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-struct Philosopher {
-    name: String,
-    left: usize,
-    right: usize,
-}
+mod arbitrator_philosopher;
+pub mod chandy_misra_table;
+mod fairness;
+mod philosopher_protocol;
+use arbitrator_philosopher::Philosopher;
+use philosopher_protocol::PhilosopherProtocol;
 
 impl Philosopher {
-    fn new(name: &str, left: usize, right: usize) -> Philosopher {
-        Philosopher {
-            name: name.to_string(),
-            left,
-            right,
+    /// returns whether the philosopher got to eat before `shutdown` was set
+    fn eat(&mut self, table: &Table, shutdown: &AtomicBool) -> Result<bool, ForkError> {
+        println!("{} is asking waiter.", self.name);
+        Meal::new(self, table, false).have(shutdown)
+    }
+
+    /// Two-phase locking, the waiter hands out both forks together or none of them
+    fn eat_two_phase(&mut self, table: &Table, shutdown: &AtomicBool) -> Result<bool, ForkError> {
+        println!("{} is asking waiter for both forks.", self.name);
+        Meal::new(self, table, true).have(shutdown)
+    }
+}
+
+/// A philosopher at the table for a meal. The waiter hands out the forks as they are asked for
+/// in `try_take`, and `release` returns whatever it got.
+struct Meal<'a> {
+    philosopher: &'a Philosopher,
+    table: &'a Table,
+    /// ask for both forks at once
+    two_phase: bool,
+    left: bool,
+    right: bool,
+}
+
+impl<'a> Meal<'a> {
+    fn new(philosopher: &'a Philosopher, table: &'a Table, two_phase: bool) -> Self {
+        Meal {
+            philosopher,
+            table,
+            two_phase,
+            left: false,
+            right: false,
         }
     }
 
-    fn eat(&mut self, table: &Table) {
-        println!("{} is asking waiter.", self.name);
-        'eat: loop {
-            if table.take_fork(Fork::Left(self.left)) {
-                loop {
-                    // try to take second fork
-                    if table.take_fork(Fork::Right(self.right)) {
-                        println!("{} is eating.", self.name);
-
-                        thread::sleep(Duration::from_millis(1000));
-
-                        println!("{} is done eating.", self.name);
-                        table.return_fork(self.left);
-                        table.return_fork(self.right);
-                        break 'eat;
-                    }
-                }
+    /// the steps of the protocol, returns whether the meal happened before `shutdown` was set
+    fn have(mut self, shutdown: &AtomicBool) -> Result<bool, ForkError> {
+        self.request()?;
+        while !self.can_proceed() {
+            if shutdown.load(Ordering::Relaxed) {
+                self.release()?;
+                return Ok(false);
             }
+            self.try_take()?;
+            // let whoever holds the forks get on with eating, or the contention counts measure
+            // how long we spun rather than how long the forks were taken
+            thread::yield_now();
         }
+        self.acquire()?;
+        self.use_resources()?;
+        self.release()?;
+        Ok(true)
+    }
+
+    /// asks the waiter for the forks still missing, the left one before the right one
+    fn try_take(&mut self) -> Result<(), ForkError> {
+        // a philosopher is seated at its left fork
+        let (left, right) = (self.philosopher.left, self.philosopher.right);
+        if self.two_phase {
+            if !self.left && self.table.try_take_both_forks(left, right)? {
+                self.left = true;
+                self.right = true;
+            }
+        } else if !self.left {
+            self.left = self.table.take_fork(left, Fork::Left(left))?;
+        } else if !self.right {
+            // try to take second fork
+            self.right = self.table.take_fork(left, Fork::Right(right))?;
+        }
+        Ok(())
+    }
+}
+
+impl PhilosopherProtocol for Meal<'_> {
+    type Error = ForkError;
+
+    /// the waiter is asked in `try_take`
+    fn request(&mut self) -> Result<(), ForkError> {
+        Ok(())
+    }
+
+    /// the waiter handed out both forks
+    fn can_proceed(&self) -> bool {
+        self.left && self.right
+    }
+
+    /// the waiter handed out both forks in `try_take`
+    fn acquire(&mut self) -> Result<(), ForkError> {
+        Ok(())
+    }
+
+    fn use_resources(&mut self) -> Result<(), ForkError> {
+        println!("{} is eating.", self.philosopher.name);
+
+        thread::sleep(self.table.eating);
+
+        println!("{} is done eating.", self.philosopher.name);
+        self.table.record_meal(self.philosopher.left);
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), ForkError> {
+        // a philosopher is seated at its left fork
+        let (left, right) = (self.philosopher.left, self.philosopher.right);
+        let held: Vec<_> = [
+            (std::mem::take(&mut self.left), left),
+            (std::mem::take(&mut self.right), right),
+        ]
+        .iter()
+        .filter_map(|&(held, fork)| Some(fork).filter(|_| held))
+        .collect();
+        self.table.return_all(left, &held)
     }
 }
 
@@ -43,24 +132,106 @@ enum Fork {
     Left(usize),
     Right(usize),
 }
-type Forks = Vec<bool>;
+
+/// The fork tracker caught the waiter handing out a fork it should not have
+#[derive(Debug, PartialEq)]
+enum ForkError {
+    /// `seat` got `fork` while `owner` still has it
+    TakenTwice {
+        fork: ForkId,
+        seat: Seat,
+        owner: Seat,
+    },
+    /// `seat` returns `fork`, but the tracker has it at `owner`
+    NotOwner {
+        fork: ForkId,
+        seat: Seat,
+        owner: Option<Seat>,
+    },
+    /// the waiter has `fork` somewhere else than the tracker
+    Disagreement { fork: ForkId },
+}
+
+impl fmt::Display for ForkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForkError::TakenTwice { fork, seat, owner } => f.write_fmt(format_args!(
+                "fork {} taken by {} while {} owns it",
+                fork, seat, owner
+            )),
+            ForkError::NotOwner { fork, seat, owner } => f.write_fmt(format_args!(
+                "{} returns fork {}, but {:?} owns it",
+                seat, fork, owner
+            )),
+            ForkError::Disagreement { fork } => {
+                f.write_fmt(format_args!("waiter and tracker disagree on fork {}", fork))
+            }
+        }
+    }
+}
+type ForkId = usize;
+/// a philosopher is seated at its left fork
+type Seat = usize;
+/// seat of the philosopher holding each fork
+type Forks = Vec<Option<Seat>>;
 struct Table {
     waiter: Mutex<Forks>,
+    /// who took which fork, kept apart from the waiter to catch it handing out a fork twice
+    fork_owners: Mutex<HashMap<ForkId, Seat>>,
+    /// per fork, how often it was asked for while taken
+    contention: Vec<AtomicU64>,
+    /// `blocked[i][j]`, how often seat `j` asked for a fork seat `i` was holding
+    blocked: Vec<Vec<AtomicU64>>,
+    /// per seat, how often the philosopher sitting there ate
+    meals: Vec<AtomicU64>,
+    /// how long a meal takes
+    eating: Duration,
 }
 
 impl Table {
+    /// table with `n` forks, none of them taken, and meals of a second
+    fn with_n_seats(n: usize) -> Self {
+        Table {
+            waiter: Mutex::new((0..n).map(|_| None).collect()),
+            fork_owners: Mutex::new(HashMap::new()),
+            contention: (0..n).map(|_| AtomicU64::new(0)).collect(),
+            blocked: (0..n)
+                .map(|_| (0..n).map(|_| AtomicU64::new(0)).collect())
+                .collect(),
+            meals: (0..n).map(|_| AtomicU64::new(0)).collect(),
+            eating: Duration::from_millis(1000),
+        }
+    }
+
+    /// one seat per philosopher
+    fn num_philosophers(&self) -> usize {
+        self.meals.len()
+    }
+
+    fn num_forks(&self) -> usize {
+        self.waiter.lock().unwrap().len()
+    }
+
+    /// philosophers holding both their forks
+    fn num_eating(&self) -> usize {
+        eating_at(&self.waiter.lock().unwrap())
+    }
+
     /// Strategy:
     /// Hand out free forks when requested, but when only one free fork available give priority
     /// to fork for a right hand (by convention the second hand that asks for a fork)
-    fn take_fork(&self, which: Fork) -> bool {
+    fn take_fork(&self, seat: Seat, which: Fork) -> Result<bool, ForkError> {
         let mut forks = self.waiter.lock().unwrap();
-        let forks_available = forks.iter().filter(|x| !*x).count();
-        let mut reserve_fork = |fork: usize| {
-            if !forks[fork] {
-                forks[fork] = true;
-                true
-            } else {
-                false
+        let forks_available = forks.iter().filter(|x| x.is_none()).count();
+        let mut reserve_fork = |fork: usize| match forks[fork] {
+            None => {
+                self.owned_by(fork, seat)?;
+                forks[fork] = Some(seat);
+                Ok(true)
+            }
+            Some(holder) => {
+                self.contended(fork, holder, seat);
+                Ok(false)
             }
         };
         match which {
@@ -69,47 +240,661 @@ impl Table {
                 if forks_available > 1 {
                     reserve_fork(fork)
                 } else {
-                    false
+                    Ok(false)
                 }
             }
         }
     }
 
-    fn return_fork(&self, fork: usize) {
+    /// Take all `wanted` forks if none of them is taken, otherwise take nothing. Nobody ever
+    /// holds part of what they need, so nobody waits while blocking someone else.
+    fn reserve_all(&self, seat: Seat, wanted: &[ForkId]) -> Result<bool, ForkError> {
         let mut forks = self.waiter.lock().unwrap();
-        forks[fork] = false;
+        let taken: Vec<_> = wanted
+            .iter()
+            .filter_map(|&fork| forks[fork].map(|holder| (fork, holder)))
+            .collect();
+        if !taken.is_empty() {
+            for (fork, holder) in taken {
+                self.contended(fork, holder, seat);
+            }
+            return Ok(false);
+        }
+        for &fork in wanted {
+            self.owned_by(fork, seat)?;
+            forks[fork] = Some(seat);
+        }
+        Ok(true)
+    }
+
+    /// Both forks of a philosopher under a single lock, or neither of them
+    fn try_take_both_forks(&self, left: ForkId, right: ForkId) -> Result<bool, ForkError> {
+        self.reserve_all(left, &[left, right])
+    }
+
+    fn contended(&self, fork: ForkId, holder: Seat, asking: Seat) {
+        self.contention[fork].fetch_add(1, Ordering::Relaxed);
+        self.blocked[holder][asking].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn owned_by(&self, fork: ForkId, seat: Seat) -> Result<(), ForkError> {
+        let mut owners = self.fork_owners.lock().unwrap();
+        match owners.get(&fork) {
+            Some(&owner) => Err(ForkError::TakenTwice { fork, seat, owner }),
+            None => {
+                owners.insert(fork, seat);
+                Ok(())
+            }
+        }
+    }
+
+    /// seat that took `fork` and has not returned it yet
+    fn fork_owner(&self, fork: ForkId) -> Option<Seat> {
+        self.fork_owners.lock().unwrap().get(&fork).copied()
+    }
+
+    /// Put all `returned` forks back under a single lock, so nobody sees `seat` holding only
+    /// part of them
+    fn return_all(&self, seat: Seat, returned: &[ForkId]) -> Result<(), ForkError> {
+        let mut forks = self.waiter.lock().unwrap();
+        for &fork in returned {
+            let owner = self.fork_owner(fork);
+            if owner != Some(seat) {
+                return Err(ForkError::NotOwner { fork, seat, owner });
+            }
+            if forks[fork] != owner {
+                return Err(ForkError::Disagreement { fork });
+            }
+            self.fork_owners.lock().unwrap().remove(&fork);
+            forks[fork] = None;
+        }
+        Ok(())
+    }
+
+    /// a philosopher is seated at its left fork
+    fn record_meal(&self, seat: usize) {
+        self.meals[seat].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Jain's fairness index over the meals of every seat, see `fairness::jain_index`
+    fn fairness_score(&self) -> f64 {
+        let meals: Vec<u64> = self
+            .meals
+            .iter()
+            .map(|m| m.load(Ordering::Relaxed))
+            .collect();
+        fairness::jain_index(&meals)
+    }
+
+    /// Possibly stale copy of the forks for monitoring, `None` when the waiter is busy.
+    /// Does not block, so polling it does not add to the contention of the philosophers.
+    fn try_peek_forks(&self) -> Option<Forks> {
+        self.waiter.try_lock().ok().map(|forks| forks.clone())
+    }
+
+    /// Put every fork back on the table to run again.
+    ///
+    /// Needs `&mut self`, so no philosopher can still be using the table.
+    fn reset(&mut self) {
+        let forks = self.waiter.get_mut().unwrap();
+        forks.iter_mut().for_each(|holder| *holder = None);
+        self.fork_owners.get_mut().unwrap().clear();
+        assert!(self.all_forks_available());
+    }
+
+    /// nobody holds a fork, as before the first philosopher sat down
+    fn all_forks_available(&self) -> bool {
+        self.waiter
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|holder| holder.is_none())
+    }
+
+    /// `matrix[i][j]` counts how often seat `j` was turned away because seat `i` held the fork
+    fn contention_matrix(&self) -> Vec<Vec<u64>> {
+        self.blocked
+            .iter()
+            .map(|row| row.iter().map(|c| c.load(Ordering::Relaxed)).collect())
+            .collect()
+    }
+
+    /// Contention count of every fork, most contended first
+    fn fork_histogram(&self) -> Vec<(ForkId, u64)> {
+        let mut histogram: Vec<_> = self
+            .contention
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .enumerate()
+            .collect();
+        histogram.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        histogram
     }
 }
 
-fn main() {
-    let philosophers = vec![
-        Philosopher::new("Judith Butler", 0, 1),
-        Philosopher::new("Gilles Deleuze", 1, 2),
-        Philosopher::new("Karl Marx", 2, 3),
-        Philosopher::new("Emma Goldman", 3, 4),
-        Philosopher::new("Michel Foucault", 4, 0),
-    ];
-    let table = Arc::new(Table {
-        waiter: Mutex::new(
-            (0..philosophers.len())
-                .into_iter()
-                .map(|_| false)
-                .collect::<Forks>(),
-        ),
-    });
+/// Seats holding two of `forks`. Half the taken forks, unless someone holds a single fork while
+/// waiting for the second.
+fn eating_at(forks: &Forks) -> usize {
+    let mut held = HashMap::new();
+    for seat in forks.iter().flatten() {
+        *held.entry(seat).or_insert(0) += 1;
+    }
+    held.values().filter(|&&count| count == 2).count()
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::with_n_seats(5)
+    }
+}
+
+struct SimulationReport {
+    meals: usize,
+    /// what went wrong with the forks of the philosophers that did not get to eat
+    errors: Vec<ForkError>,
+}
+
+struct SimulationTimeout {
+    report_so_far: SimulationReport,
+}
+
+/// Lets every philosopher eat once, gives up after `timeout` in case the table hangs.
+/// With `two_phase` the philosophers ask for both forks at once.
+fn run_with_timeout(
+    philosophers: Vec<Philosopher>,
+    table: &Arc<Table>,
+    timeout: Duration,
+    two_phase: bool,
+) -> Result<SimulationReport, SimulationTimeout> {
+    assert!(table.all_forks_available(), "forks taken before the start");
+    let n = philosophers.len();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let meals = Arc::new(AtomicUsize::new(0));
+    // philosophers done eating, the last one to finish wakes up the main thread
+    let finished = Arc::new((Mutex::new(0), Condvar::new()));
 
     let handles: Vec<_> = philosophers
         .into_iter()
         .map(|mut p| {
             let table = table.clone();
+            let shutdown = shutdown.clone();
+            let meals = meals.clone();
+            let finished = finished.clone();
 
             thread::spawn(move || {
-                p.eat(&table);
+                let ate = if two_phase {
+                    p.eat_two_phase(&table, &shutdown)
+                } else {
+                    p.eat(&table, &shutdown)
+                };
+                if ate == Ok(true) {
+                    meals.fetch_add(1, Ordering::Relaxed);
+                }
+                let (count, all_done) = &*finished;
+                let mut count = count.lock().unwrap();
+                *count += 1;
+                if *count == n {
+                    all_done.notify_one();
+                }
+                ate.map(|_| ())
             })
         })
         .collect();
 
-    for h in handles {
-        h.join().unwrap();
+    let deadline = Instant::now() + timeout;
+    let (count, all_done) = &*finished;
+    let mut count = count.lock().unwrap();
+    let mut last_seen = None;
+    while *count < n {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        // wake up regularly to show the forks
+        let wait = (deadline - now).min(Duration::from_millis(100));
+        count = all_done.wait_timeout(count, wait).unwrap().0;
+        if let Some(forks) = table.try_peek_forks() {
+            let taken = forks.iter().filter(|holder| holder.is_some()).count();
+            let eating = eating_at(&forks);
+            // two-phase philosophers never hold a single fork
+            if two_phase {
+                assert_eq!(eating, taken / 2, "{:?} taken, not in pairs", forks);
+            } else {
+                assert!(eating <= taken / 2, "{:?} taken, more eating", forks);
+            }
+            if last_seen.as_ref() != Some(&forks) {
+                println!("forks taken: {:?}", forks);
+                last_seen = Some(forks);
+            }
+        }
+    }
+    let timed_out = *count < n;
+    drop(count);
+
+    shutdown.store(true, Ordering::Relaxed);
+    let errors = handles
+        .into_iter()
+        .filter_map(|h| h.join().unwrap().err())
+        .collect();
+    let report = SimulationReport {
+        meals: meals.load(Ordering::Relaxed),
+        errors,
+    };
+    if timed_out {
+        Err(SimulationTimeout {
+            report_so_far: report,
+        })
+    } else {
+        Ok(report)
+    }
+}
+
+fn main() {
+    let seat_philosophers = || {
+        vec![
+            Philosopher::new("Judith Butler", 0, 1),
+            Philosopher::new("Gilles Deleuze", 1, 2),
+            Philosopher::new("Karl Marx", 2, 3),
+            Philosopher::new("Emma Goldman", 3, 4),
+            Philosopher::new("Michel Foucault", 4, 0),
+        ]
+    };
+    let mut table = Arc::new(Table::with_n_seats(seat_philosophers().len()));
+    assert_eq!(table.num_philosophers(), seat_philosophers().len());
+    // one fork between each pair of neighbours
+    assert_eq!(table.num_forks(), table.num_philosophers());
+
+    // the last round uses two-phase locking
+    for round in 1..=3 {
+        let two_phase = round == 3;
+        println!("round {}", round);
+        let timeout = Duration::from_secs(10);
+        let report = match run_with_timeout(seat_philosophers(), &table, timeout, two_phase) {
+            Ok(report) => {
+                println!("all done, {} meals", report.meals);
+                report
+            }
+            Err(timeout) => {
+                println!("timed out after {} meals", timeout.report_so_far.meals);
+                timeout.report_so_far
+            }
+        };
+        for error in report.errors {
+            println!("waiter mixed up the forks: {}", error);
+        }
+        // every philosopher thread has been joined, nobody else holds the table
+        Arc::get_mut(&mut table)
+            .expect("philosophers still at the table")
+            .reset();
+        assert_eq!(table.num_eating(), 0, "eating after everybody left");
+    }
+
+    println!("fairness {:.3}", table.fairness_score());
+    for (fork, count) in table.fork_histogram() {
+        println!("fork {} was contended {} times", fork, count);
+    }
+    println!("seat in the way (rows) of seat (columns)");
+    for (i, row) in table.contention_matrix().iter().enumerate() {
+        let counts: Vec<_> = row.iter().map(|c| format!("{:>9}", c)).collect();
+        println!("{:>4} {}", i, counts.join(""));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peeking_does_not_wait_for_the_waiter() {
+        let table = Table::with_n_seats(3);
+        assert!(table.take_fork(1, Fork::Right(2)).unwrap());
+        let waiter = table.waiter.lock().unwrap();
+        assert_eq!(table.try_peek_forks(), None);
+        drop(waiter);
+        assert_eq!(table.try_peek_forks(), Some(vec![None, None, Some(1)]));
+    }
+
+    #[test]
+    fn histogram_puts_the_most_contended_fork_first() {
+        let table = Table::with_n_seats(3);
+        assert!(table.take_fork(0, Fork::Right(1)).unwrap());
+        assert!(!table.take_fork(1, Fork::Right(1)).unwrap());
+        assert!(!table.take_fork(1, Fork::Right(1)).unwrap());
+        assert!(table.take_fork(2, Fork::Right(2)).unwrap());
+        assert!(!table.take_fork(1, Fork::Right(2)).unwrap());
+        assert_eq!(table.fork_histogram(), vec![(1, 2), (2, 1), (0, 0)]);
+        assert_eq!(table.contention_matrix()[0][1], 2);
+    }
+
+    #[test]
+    fn reset_puts_every_fork_back() {
+        let mut table = Table::with_n_seats(3);
+        assert!(table.take_fork(0, Fork::Left(0)).unwrap());
+        assert!(table.take_fork(0, Fork::Right(1)).unwrap());
+        assert_eq!(table.num_eating(), 1);
+        table.reset();
+        assert!(table.all_forks_available());
+        assert_eq!(table.fork_owner(0), None);
+        // taken again without the owner tracking complaining
+        assert!(table.take_fork(2, Fork::Right(0)).unwrap());
+    }
+
+    /// Every philosopher in its own thread eating and thinking, as many meals as paired with it or
+    /// until the table had `meals_in_all`. Returns what the waiter lent out meanwhile, or `None`
+    /// when they were not done within a minute.
+    fn eat_meals(
+        seated: Vec<(Philosopher, u64)>,
+        table: &Arc<Table>,
+        two_phase: bool,
+        meals_in_all: u64,
+    ) -> Option<Vec<Forks>> {
+        let n = seated.len();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = seated
+            .into_iter()
+            .map(|(mut p, meals)| {
+                let (table, shutdown, done) = (table.clone(), shutdown.clone(), done.clone());
+                thread::spawn(move || {
+                    let eaten = || table.meals.iter().map(|m| m.load(Ordering::Relaxed));
+                    for _ in 0..meals {
+                        if eaten().sum::<u64>() >= meals_in_all {
+                            break;
+                        }
+                        let ate = if two_phase {
+                            p.eat_two_phase(&table, &shutdown)
+                        } else {
+                            p.eat(&table, &shutdown)
+                        };
+                        if !ate.unwrap() {
+                            break;
+                        }
+                        // thinks as long as it ate
+                        thread::sleep(table.eating);
+                    }
+                    done.fetch_add(1, Ordering::Relaxed);
+                })
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut lent = Vec::new();
+        while done.load(Ordering::Relaxed) < n && Instant::now() < deadline {
+            lent.extend(table.try_peek_forks());
+            thread::sleep(Duration::from_millis(1));
+        }
+        let finished = done.load(Ordering::Relaxed) == n;
+        shutdown.store(true, Ordering::Relaxed);
+        for h in handles {
+            h.join().unwrap();
+        }
+        Some(lent).filter(|_| finished)
+    }
+
+    /// `n` philosophers around a ring, each between the forks of its seat and the next one
+    fn ring(n: usize) -> Vec<Philosopher> {
+        (0..n)
+            .map(|i| Philosopher::new(&format!("philosopher {}", i), i, (i + 1) % n))
+            .collect()
+    }
+
+    fn two_philosophers() -> Vec<Philosopher> {
+        vec![
+            Philosopher::new("Judith Butler", 0, 1),
+            Philosopher::new("Gilles Deleuze", 1, 0),
+        ]
+    }
+
+    #[test]
+    fn everyone_eats_before_the_timeout() {
+        let table = Arc::new(Table::with_n_seats(2));
+        match run_with_timeout(two_philosophers(), &table, Duration::from_secs(10), true) {
+            Ok(report) => assert_eq!(report.meals, 2),
+            Err(timeout) => panic!("timed out after {} meals", timeout.report_so_far.meals),
+        }
+    }
+
+    #[test]
+    fn chandy_misra_philosophers_move_over_to_the_waiter() {
+        let names = [
+            "Judith Butler",
+            "Gilles Deleuze",
+            "Karl Marx",
+            "Emma Goldman",
+            "Michel Foucault",
+        ];
+        let config = chandy_misra_table::SimulationConfig {
+            eating_duration: Duration::from_millis(1),
+            meals: Some(2),
+            ..chandy_misra_table::SimulationConfig::new(
+                names.iter().map(|name| name.to_string()).collect(),
+            )
+        };
+        let table = chandy_misra_table::ring_of_n(&config).unwrap();
+        let table = chandy_misra_table::run_table(table, Duration::from_secs(60)).unwrap();
+        let seated: Vec<Philosopher> = table.into_iter().map(Philosopher::from).collect();
+        for (i, p) in seated.iter().enumerate() {
+            assert_eq!(p.name, names[i]);
+            assert_eq!((p.left, p.right), (i, (i + 1) % names.len()));
+        }
+        let table = Arc::new(Table::with_n_seats(names.len()));
+        match run_with_timeout(seated, &table, Duration::from_secs(10), true) {
+            Ok(report) => assert_eq!(report.meals, names.len()),
+            Err(timeout) => panic!("timed out after {} meals", timeout.report_so_far.meals),
+        }
+    }
+
+    #[test]
+    fn timeout_reports_the_meals_so_far() {
+        let table = Arc::new(Table::with_n_seats(2));
+        // a meal takes a second, only one of the two gets started
+        match run_with_timeout(
+            two_philosophers(),
+            &table,
+            Duration::from_millis(100),
+            false,
+        ) {
+            Ok(report) => panic!("all {} meals before the timeout", report.meals),
+            Err(timeout) => assert_eq!(timeout.report_so_far.meals, 1),
+        }
+        assert!(table.all_forks_available());
+    }
+
+    #[test]
+    fn seven_seats_have_seven_free_forks() {
+        let table = Table::with_n_seats(7);
+        assert_eq!(table.num_forks(), 7);
+        assert_eq!(table.num_philosophers(), 7);
+        assert_eq!(table.try_peek_forks(), Some(vec![None; 7]));
+        assert!((0..7).all(|fork| table.fork_owner(fork).is_none()));
+    }
+
+    #[test]
+    fn taking_a_fork_makes_not_all_available() {
+        let table = Table::default();
+        assert!(table.all_forks_available());
+        assert!(table.take_fork(3, Fork::Right(4)).unwrap());
+        assert!(!table.all_forks_available());
+        table.return_all(3, &[4]).unwrap();
+        assert!(table.all_forks_available());
+    }
+
+    #[test]
+    fn tracker_mix_ups_are_errors() {
+        let table = Table::default();
+        assert!(table.take_fork(1, Fork::Right(2)).unwrap());
+        assert_eq!(
+            table.return_all(3, &[2]),
+            Err(ForkError::NotOwner {
+                fork: 2,
+                seat: 3,
+                owner: Some(1)
+            })
+        );
+        assert_eq!(
+            table.owned_by(2, 3),
+            Err(ForkError::TakenTwice {
+                fork: 2,
+                seat: 3,
+                owner: 1
+            })
+        );
+        table.waiter.lock().unwrap()[2] = None;
+        assert_eq!(
+            table.return_all(1, &[2]),
+            Err(ForkError::Disagreement { fork: 2 })
+        );
+        assert_eq!(
+            ForkError::NotOwner {
+                fork: 2,
+                seat: 3,
+                owner: None
+            }
+            .to_string(),
+            "3 returns fork 2, but None owns it"
+        );
+    }
+
+    #[test]
+    fn twenty_two_phase_philosophers_never_deadlock() {
+        let mut table = Table::with_n_seats(20);
+        table.eating = Duration::ZERO;
+        let table = Arc::new(table);
+        let seated = ring(20).into_iter().map(|p| (p, 500)).collect();
+        assert!(eat_meals(seated, &table, true, u64::MAX).is_some());
+        for (seat, meals) in table.meals.iter().enumerate() {
+            assert_eq!(meals.load(Ordering::Relaxed), 500, "seat {}", seat);
+        }
+        assert!(table.all_forks_available());
+    }
+
+    #[test]
+    fn taking_both_forks_never_leaves_one_held() {
+        let mut table = Table::with_n_seats(20);
+        table.eating = Duration::from_micros(100);
+        let table = Arc::new(table);
+        let seated = ring(20).into_iter().map(|p| (p, 20)).collect();
+        let lent = eat_meals(seated, &table, true, u64::MAX).expect("no progress");
+        assert_eq!(
+            table
+                .meals
+                .iter()
+                .map(|m| m.load(Ordering::Relaxed))
+                .sum::<u64>(),
+            400
+        );
+        assert!(!lent.is_empty());
+        for forks in lent {
+            let taken = forks.iter().filter(|holder| holder.is_some()).count();
+            assert_eq!(eating_at(&forks) * 2, taken, "{:?}", forks);
+        }
+    }
+
+    #[test]
+    fn thousand_meals_are_shared_fairly() {
+        let mut table = Table::default();
+        table.eating = Duration::from_micros(100);
+        let table = Arc::new(table);
+        let seated = ring(5).into_iter().map(|p| (p, u64::MAX)).collect();
+        assert!(eat_meals(seated, &table, false, 1000).is_some());
+        let meals: Vec<_> = table
+            .meals
+            .iter()
+            .map(|m| m.load(Ordering::Relaxed))
+            .collect();
+        assert!(meals.iter().sum::<u64>() >= 1000);
+        assert!(table.fairness_score() > 0.9, "{:?}", meals);
+    }
+
+    #[test]
+    fn tracker_keeps_up_with_concurrent_takes() {
+        let table = Arc::new(Table::with_n_seats(8));
+        let handles: Vec<_> = (0..8)
+            .map(|seat| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for round in 0..500 {
+                        let fork = (seat + round % 2) % 8;
+                        if table.take_fork(seat, Fork::Right(fork)).unwrap() {
+                            assert_eq!(table.fork_owner(fork), Some(seat));
+                            table.return_all(seat, &[fork]).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        // the waiter and the tracker agree on every fork whenever we look
+        for _ in 0..100 {
+            let forks = table.waiter.lock().unwrap();
+            for (fork, holder) in forks.iter().enumerate() {
+                assert_eq!(*holder, table.fork_owner(fork), "fork {}", fork);
+            }
+            drop(forks);
+            thread::yield_now();
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert!(table.all_forks_available());
+        assert!(table.fork_owners.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_neighbours_block_each_other() {
+        let mut table = Table::default();
+        table.eating = Duration::from_micros(100);
+        let table = Arc::new(table);
+        let seated = ring(5).into_iter().map(|p| (p, 100)).collect();
+        assert!(eat_meals(seated, &table, false, u64::MAX).is_some());
+        let matrix = table.contention_matrix();
+        let n = matrix.len();
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, &count) in row.iter().enumerate() {
+                // only neighbours share a fork
+                let adjacent = (i + 1) % n == j || (j + 1) % n == i;
+                assert!(
+                    adjacent || count == 0,
+                    "{} blocked {} {} times",
+                    i,
+                    j,
+                    count
+                );
+            }
+        }
+        assert!(matrix.iter().flatten().any(|&count| count > 0));
+    }
+
+    #[test]
+    fn forks_of_the_big_eater_are_the_hottest() {
+        let mut table = Table::default();
+        table.eating = Duration::from_micros(200);
+        let table = Arc::new(table);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut seated = ring(5).into_iter();
+        let mut big_eater = seated.next().unwrap();
+        let others: Vec<_> = seated
+            .map(|mut p| {
+                let (table, shutdown) = (table.clone(), shutdown.clone());
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        assert!(p.eat(&table, &shutdown).unwrap());
+                        // thinks ten times as long as it eats
+                        thread::sleep(table.eating * 10);
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..10 * 20 {
+            assert!(big_eater.eat(&table, &shutdown).unwrap());
+            // and the big eater right away
+            thread::yield_now();
+        }
+        for h in others {
+            h.join().unwrap();
+        }
+        // seat 0 sits between forks 0 and 1
+        let histogram = table.fork_histogram();
+        let mut hottest = [histogram[0].0, histogram[1].0];
+        hottest.sort_unstable();
+        assert_eq!(hottest, [0, 1], "{:?}", histogram);
     }
 }
@@ -0,0 +1,36 @@
+//! The philosopher of `arbitrator.rs`, who asks the waiter for its forks, and how a philosopher
+//! of another solution takes over its seat.
+
+use super::chandy_misra_table;
+
+pub struct Philosopher {
+    pub name: String,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Philosopher {
+    pub fn new(name: &str, left: usize, right: usize) -> Philosopher {
+        Philosopher {
+            name: name.to_string(),
+            left,
+            right,
+        }
+    }
+}
+
+/// A Chandy/Misra philosopher asking the waiter from now on, seated at the fork of its id with
+/// the other fork it needs on the right, as around a round table. Only the name comes along,
+/// the forks go back to the pool and the waiter starts out with the meals.
+impl<const N: usize> From<chandy_misra_table::Philosopher<N>> for Philosopher {
+    fn from(p: chandy_misra_table::Philosopher<N>) -> Self {
+        let left = p.id.index();
+        let right = p
+            .needs
+            .iter()
+            .copied()
+            .find(|&fork| fork != left)
+            .unwrap_or(left);
+        Philosopher::new(&p.name, left, right)
+    }
+}
@@ -0,0 +1,222 @@
+//! Chandy/Misra with philosophers that differ in how long they eat and think.
+//!
+//! Baruch Spinoza eats for 100ms and thinks for 10ms, Michel Foucault does the opposite, the
+//! others spend 50ms on each. Equal durations for everyone can hide how a solution treats
+//! philosophers that hold on to their forks for longer. At the end the meal count of each
+//! philosopher is compared to what it could have eaten if it never had to wait for a fork.
+//!
+//! `cargo run --release --example asymmetric -- 10` shortens the default run of 30 seconds.
+//! `bimodal` lets half of the philosophers eat for 10ms and the other half for 100ms instead,
+//! `zipf` gives the philosopher of rank k an eating time proportional to 1/k. Neighbours that are
+//! both hungry take turns, so with `bimodal` the fast half eats about as often as the slow half.
+
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{RingTopologyBuilder, SimulationConfig};
+
+/// how long one philosopher eats and thinks every time
+#[derive(Debug, Clone, Copy)]
+struct PhilosopherWorkload {
+    eating: Duration,
+    thinking: Duration,
+}
+
+/// Thinking time of every philosopher of a generated workload
+const THINKING: Duration = Duration::from_millis(50);
+
+/// Eating times for a whole table, one philosopher after the other
+enum WorkloadGenerator {
+    Bimodal {
+        fast_proportion: f64,
+        fast: Duration,
+        slow: Duration,
+        next: usize,
+    },
+    Zipf {
+        n: usize,
+        exponent: f64,
+        next: usize,
+    },
+}
+
+impl WorkloadGenerator {
+    /// `fast_proportion` of the philosophers eat for `fast_eating_ms`, the rest for
+    /// `slow_eating_ms`, spread evenly around the table
+    fn bimodal(fast_proportion: f64, fast_eating_ms: u64, slow_eating_ms: u64) -> Self {
+        WorkloadGenerator::Bimodal {
+            fast_proportion,
+            fast: Duration::from_millis(fast_eating_ms),
+            slow: Duration::from_millis(slow_eating_ms),
+            next: 0,
+        }
+    }
+
+    /// `n` philosophers, the one of rank k eats 1/k^`exponent` as long as the first
+    fn zipf(n: usize, exponent: f64) -> Self {
+        WorkloadGenerator::Zipf {
+            n,
+            exponent,
+            next: 0,
+        }
+    }
+}
+
+impl Iterator for WorkloadGenerator {
+    type Item = PhilosopherWorkload;
+
+    fn next(&mut self) -> Option<PhilosopherWorkload> {
+        let eating = match self {
+            WorkloadGenerator::Bimodal {
+                fast_proportion,
+                fast,
+                slow,
+                next,
+            } => {
+                // fast whenever the share of fast philosophers so far falls behind
+                let i = *next as f64;
+                let is_fast =
+                    ((i + 1.0) * *fast_proportion).floor() > (i * *fast_proportion).floor();
+                *next += 1;
+                if is_fast {
+                    *fast
+                } else {
+                    *slow
+                }
+            }
+            WorkloadGenerator::Zipf { n, exponent, next } => {
+                if *next == *n {
+                    return None;
+                }
+                *next += 1;
+                Duration::from_millis(100).div_f64((*next as f64).powf(*exponent))
+            }
+        };
+        Some(PhilosopherWorkload {
+            eating,
+            thinking: THINKING,
+        })
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let seconds = args
+        .iter()
+        .find(|a| a.parse::<u64>().is_ok())
+        .map_or(30, |s| s.parse().unwrap());
+    let ms = Duration::from_millis;
+    let mut setup = [
+        ("Baruch Spinoza", ms(100), ms(10)),
+        ("Gilles Deleuze", ms(50), ms(50)),
+        ("Karl Marx", ms(50), ms(50)),
+        ("Friedrich Nietzsche", ms(50), ms(50)),
+        ("Michel Foucault", ms(10), ms(100)),
+    ];
+    let n = setup.len();
+    let workload = if args.iter().any(|a| a == "bimodal") {
+        Some(WorkloadGenerator::bimodal(0.5, 10, 100))
+    } else if args.iter().any(|a| a == "zipf") {
+        Some(WorkloadGenerator::zipf(n, 1.0))
+    } else {
+        None
+    };
+    if let Some(workload) = workload {
+        for ((_, eating, thinking), w) in setup.iter_mut().zip(workload) {
+            *eating = w.eating;
+            *thinking = w.thinking;
+        }
+    }
+
+    let names = setup.iter().map(|(name, _, _)| name.to_string()).collect();
+    let mut philosophers = RingTopologyBuilder::new(n)
+        .build(&SimulationConfig::new(names))
+        .expect("a table of five");
+    for (p, (_, eating, thinking)) in philosophers.iter_mut().zip(&setup) {
+        p.eating_duration = *eating;
+        p.thinking_duration = *thinking;
+    }
+
+    let run_time = Duration::from_secs(seconds);
+    let philosophers =
+        chandy_misra_table::run_table(philosophers, run_time).expect("philosophers left early");
+
+    println!(
+        "{:<20} | {:>6} | {:>8} | {:>5} | {:>9}",
+        "Philosopher", "eating", "thinking", "meals", "predicted"
+    );
+    for p in philosophers {
+        // meals if forks were always there when needed
+        let predicted =
+            run_time.as_millis() / (p.eating_duration + p.thinking_duration).as_millis();
+        println!(
+            "{:<20} | {:>4}ms | {:>6}ms | {:>5} | {:>9}",
+            p.name,
+            p.eating_duration.as_millis(),
+            p.thinking_duration.as_millis(),
+            p.meals,
+            predicted
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// meals of the philosophers eating and thinking for the milliseconds of `setup`, around a
+    /// ring in that order
+    fn meals(setup: &[(u64, u64)], run_time: Duration) -> Vec<u64> {
+        let names = (0..setup.len())
+            .map(|i| format!("philosopher {}", i))
+            .collect();
+        let mut philosophers = RingTopologyBuilder::new(setup.len())
+            .build(&SimulationConfig::new(names))
+            .unwrap();
+        for (p, &(eating, thinking)) in philosophers.iter_mut().zip(setup) {
+            p.eating_duration = Duration::from_millis(eating);
+            p.thinking_duration = Duration::from_millis(thinking);
+        }
+        chandy_misra_table::run_table(philosophers, run_time)
+            .unwrap()
+            .iter()
+            .map(|p| p.meals)
+            .collect()
+    }
+
+    /// A hungry neighbour gets a fork after every meal, so the fast eater only gets ahead
+    /// between neighbours that leave their forks alone most of the time
+    #[test]
+    fn fast_eater_eats_three_times_as_often() {
+        let setup = [(100, 10), (10, 100), (10, 10), (10, 100), (50, 50)];
+        let meals = meals(&setup, Duration::from_secs(2));
+        assert!(meals[2] >= 3 * meals[0], "{:?}", meals);
+    }
+
+    #[test]
+    fn bimodal_alternates_fast_and_slow() {
+        let eating: Vec<_> = WorkloadGenerator::bimodal(0.5, 10, 100)
+            .take(4)
+            .map(|w| w.eating.as_millis())
+            .collect();
+        assert_eq!(eating, [100, 10, 100, 10]);
+    }
+
+    /// Every fast philosopher sits between two slow ones that are hungry again after 50ms and
+    /// get a fork after each meal, so the fast half cannot get three times ahead
+    #[test]
+    fn bimodal_fast_half_keeps_pace_with_the_slow_half() {
+        let setup: Vec<_> = WorkloadGenerator::bimodal(0.5, 10, 100)
+            .take(6)
+            .map(|w| (w.eating.as_millis() as u64, w.thinking.as_millis() as u64))
+            .collect();
+        let meals = meals(&setup, Duration::from_secs(3));
+        let (fast, slow): (Vec<_>, Vec<_>) = setup.iter().zip(&meals).partition(|(w, _)| w.0 == 10);
+        let fast: u64 = fast.iter().map(|(_, &m)| m).sum();
+        let slow: u64 = slow.iter().map(|(_, &m)| m).sum();
+        assert!(fast * 10 >= slow * 9, "{:?}", meals);
+        assert!(fast < 3 * slow, "{:?}", meals);
+    }
+}
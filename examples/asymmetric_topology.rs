@@ -0,0 +1,146 @@
+//! Chandy/Misra on a star: one philosopher in the middle shares a fork with each of the others.
+//!
+//! The spokes need a single fork to eat, the hub needs all N - 1 of them at once. With forks
+//! handed out on a first come basis the hub would starve: there is nearly always some spoke that
+//! holds or grabs back its fork, so the hub never gets all of them together. Chandy/Misra does
+//! not let that happen. A hungry hub keeps every fork it receives, because a received fork is
+//! clean, and a spoke has to give up its fork after eating because eating made it dirty. So once
+//! the hub is hungry every spoke eats at most once more, then the hub has all its forks.
+//!
+//! The hub has the highest id, so every fork starts at a spoke. All spokes eat before the hub
+//! does, after that the hub gets a turn between two meals of each spoke.
+
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{ForkGraph, Philosopher, SimulationConfig, SimulationEvent};
+
+const EATING: Duration = Duration::from_millis(20);
+const THINKING: Duration = Duration::from_millis(20);
+/// forks of the hub
+const SPOKES: usize = 5;
+
+/// ids in the order of their first meal
+fn first_meals<const N: usize>(philosophers: &[Philosopher<N>]) -> Vec<usize> {
+    let mut order = vec![];
+    for (_, _, _, event) in philosophers[0].events.read_since(&mut 0) {
+        if let SimulationEvent::PhilosopherStartedEating { id, .. } = event {
+            if !order.contains(&id.index()) {
+                order.push(id.index());
+            }
+        }
+    }
+    order
+}
+
+fn main() {
+    let names = [
+        "Baruch Spinoza",
+        "Gilles Deleuze",
+        "Karl Marx",
+        "Friedrich Nietzsche",
+        "Michel Foucault",
+        "Hannah Arendt",
+    ];
+    let graph = ForkGraph::star(names.len()).expect("a table of six");
+    let hub = names.len() - 1;
+    for (p, _) in names.iter().enumerate() {
+        let expected = if p == hub { names.len() - 1 } else { 1 };
+        assert_eq!(graph.adjacency(p).len(), expected, "not a star");
+    }
+    // spokes only ever contend through the hub
+    for a in 0..hub {
+        for b in a + 1..hub {
+            assert_eq!(
+                graph.shortest_path(a, b),
+                Some(vec![a, hub, b]),
+                "not a star"
+            );
+        }
+    }
+    println!("{} sits in the middle of {} spokes", names[hub], hub);
+
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        thinking_duration: THINKING,
+        ..SimulationConfig::new(names.iter().map(|n| n.to_string()).collect())
+    };
+    // hungry right away, while every spoke still holds its fork
+    let philosophers: Vec<Philosopher<SPOKES>> = graph.build(&config).expect("room for the forks");
+    let philosophers = chandy_misra_table::run_table(philosophers, Duration::from_secs(10))
+        .expect("philosophers left early");
+
+    let order = first_meals(&philosophers);
+    for &i in &order {
+        let p = &philosophers[i];
+        let role = if i == hub { "hub" } else { "spoke" };
+        println!("{} {} ({}) ate {} times", p.id, p.name, role, p.meals);
+    }
+    if order.len() < names.len() {
+        println!("somebody starved");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chandy_misra_table::ConfigError;
+
+    #[test]
+    fn shortest_path_between_spokes_goes_through_the_hub() {
+        let graph = ForkGraph::star(4).unwrap();
+        assert_eq!(graph.shortest_path(0, 2), Some(vec![0, 3, 2]));
+        assert_eq!(graph.shortest_path(3, 1), Some(vec![3, 1]));
+        assert_eq!(graph.shortest_path(1, 1), Some(vec![1]));
+    }
+
+    #[test]
+    fn shortest_path_takes_the_short_way_round() {
+        let graph = ForkGraph::ring(6).unwrap();
+        assert_eq!(graph.shortest_path(0, 2), Some(vec![0, 1, 2]));
+        assert_eq!(graph.shortest_path(0, 4), Some(vec![0, 5, 4]));
+        assert_eq!(graph.shortest_path(1, 4).map(|p| p.len()), Some(4));
+    }
+
+    #[test]
+    fn a_star_needs_a_hub_and_a_spoke() {
+        for philosophers in 0..2 {
+            assert!(matches!(
+                ForkGraph::star(philosophers),
+                Err(ConfigError::TooFewPhilosophers(n)) if n == philosophers
+            ));
+        }
+        assert_eq!(ForkGraph::star(2).unwrap().edges, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn no_path_between_separate_tables() {
+        let graph = ForkGraph::from_edges(4, vec![(0, 1), (2, 3)]).unwrap();
+        assert_eq!(graph.shortest_path(0, 3), None);
+        assert_eq!(graph.shortest_path(2, 3), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn hub_eats_after_every_spoke() {
+        let config = SimulationConfig {
+            eating_duration: Duration::from_millis(2),
+            thinking_duration: Duration::from_millis(2),
+            meals: Some(3),
+            ..SimulationConfig::new(
+                (0..SPOKES + 1)
+                    .map(|i| format!("philosopher {}", i))
+                    .collect(),
+            )
+        };
+        let philosophers: Vec<Philosopher<SPOKES>> =
+            ForkGraph::star(SPOKES + 1).unwrap().build(&config).unwrap();
+        let philosophers =
+            chandy_misra_table::run_table(philosophers, Duration::from_secs(60)).unwrap();
+        let order = first_meals(&philosophers);
+        assert_eq!(order.len(), SPOKES + 1, "somebody starved: {:?}", order);
+        assert_eq!(order.last(), Some(&SPOKES), "{:?}", order);
+        assert!(philosophers.iter().all(|p| p.meals == 3));
+    }
+}
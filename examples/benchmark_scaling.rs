@@ -0,0 +1,179 @@
+//! Meals per second for each algorithm as the table grows from 2 to 100 philosophers.
+//!
+//! Every algorithm runs for `WORKLOAD.run` at each table size, all philosophers eating and
+//! thinking in a loop the whole time. Resource hierarchy, n - 1 and Chandy/Misra only ever make
+//! neighbours wait for each other, so their throughput grows with the table. Two-phase locking
+//! hands out both forks of a philosopher behind one lock. The arbitrator hands them out one at a
+//! time behind that lock and counts the free forks of the whole table for every fork it is asked
+//! for. Either lock is only held for a moment compared to a meal of 10ms, at those meals all of
+//! them scale. With meals so short that the waiter is busy most of the time the arbitrator
+//! falls behind as the table grows, see the tests.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+pub mod table_harness;
+
+use chandy_misra_table::{ring_of_n, run_table, SimulationConfig};
+use table_harness::{Arbitrator, NMinusOne, ResourceHierarchy, TwoPhaseLocking};
+
+const SIZES: [usize; 6] = [2, 5, 10, 20, 50, 100];
+
+/// how long every philosopher eats and thinks, and how long the table runs
+#[derive(Debug, Clone, Copy)]
+struct Workload {
+    eating: Duration,
+    thinking: Duration,
+    run: Duration,
+}
+
+const WORKLOAD: Workload = Workload {
+    eating: Duration::from_millis(10),
+    thinking: Duration::from_millis(10),
+    run: Duration::from_secs(5),
+};
+
+/// meals per second at a table of that many philosophers
+type Algorithm = fn(usize, Workload) -> f64;
+
+/// Lets `n` philosophers call `eat` and think in turns until `workload.run` is over. `eat` has
+/// to sleep for `workload.eating` once it has the forks of philosopher `i`.
+fn meals_per_second<F>(n: usize, workload: Workload, eat: F) -> f64
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let started = Instant::now();
+    let meals: u64 = table_harness::simulate(n, workload.run, workload.thinking, eat)
+        .meals
+        .iter()
+        .sum();
+    meals as f64 / started.elapsed().as_secs_f64()
+}
+
+fn resource_hierarchy(n: usize, workload: Workload) -> f64 {
+    let table = ResourceHierarchy::new(n);
+    meals_per_second(n, workload, move |i| {
+        table.eat(i, || thread::sleep(workload.eating))
+    })
+}
+
+/// the waiter hands out both forks together or none of them
+fn two_phase_locking(n: usize, workload: Workload) -> f64 {
+    let table = TwoPhaseLocking::new(n);
+    meals_per_second(n, workload, move |i| {
+        table.eat(i, || thread::sleep(workload.eating))
+    })
+}
+
+/// the waiter of `arbitrator.rs`, one fork at a time and the last free one to a right hand
+fn arbitrator(n: usize, workload: Workload) -> f64 {
+    let table = Arbitrator::new(n);
+    meals_per_second(n, workload, move |i| {
+        table.eat(i, || thread::sleep(workload.eating))
+    })
+}
+
+fn n_minus_one(n: usize, workload: Workload) -> f64 {
+    let table = NMinusOne::new(n);
+    meals_per_second(n, workload, move |i| {
+        table.eat(i, || thread::sleep(workload.eating))
+    })
+}
+
+/// Chandy/Misra as `chandy_misra.rs` runs it, every philosopher in its own thread
+fn chandy_misra(n: usize, workload: Workload) -> f64 {
+    let config = SimulationConfig {
+        eating_duration: workload.eating,
+        thinking_duration: workload.thinking,
+        ..SimulationConfig::new((0..n).map(|i| format!("Philosopher {}", i)).collect())
+    };
+    let started = Instant::now();
+    let meals: u64 = ring_of_n(&config)
+        .and_then(|philosophers| run_table(philosophers, workload.run))
+        .expect("philosophers left early")
+        .iter()
+        .map(|p| p.meals)
+        .sum();
+    meals as f64 / started.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let algorithms: Vec<(&str, Algorithm)> = vec![
+        ("resource hierarchy", resource_hierarchy),
+        ("two-phase locking", two_phase_locking),
+        ("arbitrator", arbitrator),
+        ("n - 1", n_minus_one),
+        ("Chandy/Misra", chandy_misra),
+    ];
+
+    print!("{:<20}", "meals/s");
+    for n in SIZES {
+        print!(" | {:>7}", format!("N={}", n));
+    }
+    println!(" | {:>7}", "scaling");
+    for (name, run) in algorithms {
+        print!("{:<20}", name);
+        let mut throughput = vec![];
+        for n in SIZES {
+            let meals = run(n, WORKLOAD);
+            print!(" | {:>7.1}", meals);
+            throughput.push(meals);
+        }
+        // meals per philosopher at the largest table for each one at the smallest, 1 is linear
+        let per_seat = |i: usize| throughput[i] / SIZES[i] as f64;
+        println!(" | {:>7.2}", per_seat(SIZES.len() - 1) / per_seat(0));
+        // nobody eats more often than eating and thinking in turns allows
+        let max_per_seat = 1.0 / (WORKLOAD.eating + WORKLOAD.thinking).as_secs_f64();
+        for (n, meals) in SIZES.iter().zip(&throughput) {
+            assert!(
+                *meals <= *n as f64 * max_per_seat,
+                "{} with {} philosophers ate more than possible",
+                name,
+                n
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// meals per second and philosopher
+    fn per_seat(algorithm: Algorithm, n: usize, workload: Workload) -> f64 {
+        algorithm(n, workload) / n as f64
+    }
+
+    const SHORT_RUN: Workload = Workload {
+        run: Duration::from_millis(500),
+        ..WORKLOAD
+    };
+
+    #[test]
+    fn n_minus_one_grows_linearly_with_the_table() {
+        let small = per_seat(n_minus_one, 5, SHORT_RUN);
+        let large = per_seat(n_minus_one, 50, SHORT_RUN);
+        assert!(large >= 0.7 * small, "{} at 5, {} at 50", small, large);
+    }
+
+    #[test]
+    fn chandy_misra_grows_linearly_with_the_table() {
+        let small = per_seat(chandy_misra, 5, SHORT_RUN);
+        let large = per_seat(chandy_misra, 50, SHORT_RUN);
+        assert!(large >= 0.5 * small, "{} at 5, {} at 50", small, large);
+    }
+
+    #[test]
+    fn arbitrator_falls_behind_with_short_meals() {
+        let short_meals = Workload {
+            eating: Duration::from_micros(100),
+            thinking: Duration::from_micros(100),
+            ..SHORT_RUN
+        };
+        let small = per_seat(arbitrator, 10, short_meals);
+        let large = per_seat(arbitrator, 100, short_meals);
+        assert!(large < 0.5 * small, "{} at 10, {} at 100", small, large);
+    }
+}
@@ -5,267 +5,2284 @@
 //! 3. When a philosopher with a fork receives a request message, they keep the fork if it is clean, but give it up when it is dirty. If the philosopher sends the fork over, they clean the fork before doing so.
 //! 4. After a philosopher is done eating, all their forks become dirty. If another philosopher had previously requested one of the forks, the philosopher that has just finished eating cleans the fork and sends it.
 
-use std::cell::Cell;
-use std::collections::{BTreeMap, HashMap, VecDeque};
-use std::sync::mpsc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, PartialEq)]
-enum ForkState {
-    Dirty,
-    Clean,
+pub mod chandy_misra_table;
+mod philosopher_name_generator;
+mod philosopher_protocol;
+use chandy_misra_table::*;
+use philosopher_name_generator::NameGenerator;
+
+/// What the monitor prints once a second: the table as the observer sees it and the report
+/// built from the events so far
+fn print_table(
+    observer: &SimulationObserver,
+    events: &EventLog,
+    report: &SimulationReport,
+    meals_eaten: &AtomicU64,
+    forks: usize,
+    philosophers: usize,
+    started: Instant,
+) {
+    assert_eq!(
+        observer.fork_count(),
+        forks,
+        "a fork got lost or duplicated"
+    );
+    println!(
+        "readiness {:.2}, {} meals eaten, {} requests deferred for clean forks",
+        observer.system_readiness(),
+        meals_eaten.load(Ordering::Relaxed),
+        report.deferred_clean
+    );
+    let holders: Vec<_> = report
+        .holders
+        .iter()
+        .map(|(fork, p)| format!("fork {} at {}", fork, p))
+        .collect();
+    print!("{}", report);
+    println!(
+        "{} queued for forks in flight, {}",
+        report.deferred_not_held,
+        holders.join(", ")
+    );
+    print!(
+        "{}",
+        report.to_table_string(philosophers, started.elapsed())
+    );
+    let stats = observer.statistics();
+    for (i, s) in stats.iter().enumerate() {
+        // a glimpse of a running philosopher, but never more than its own forks
+        assert!(s.held_forks <= 2, "philosopher {} holds {:?}", i, s);
+    }
+    let (hungriest, worst) = stats
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, s)| s.max_starvation_ns)
+        .unwrap();
+    let total_wait_ns: u64 = stats.iter().map(|s| s.total_wait_ns).sum();
+    let stats_meals: u64 = stats.iter().map(|s| s.meals_eaten).sum();
+    println!(
+        "longest wait {:?} by {} ({:?}, {} requests to answer), mean wait {:?}",
+        Duration::from_nanos(worst.max_starvation_ns),
+        hungriest,
+        worst.current_state,
+        worst.pending_requests,
+        Duration::from_nanos(total_wait_ns / stats_meals.max(1))
+    );
+    if let Some((at, event)) = events.last() {
+        println!("last event at {}: {}", at, event);
+    }
+    let snapshot = observer.inspect_snapshot();
+    println!(
+        "table after {:?}:",
+        snapshot.timestamp.duration_since(started)
+    );
+    for p in &snapshot.philosophers {
+        let requests: Vec<_> = p
+            .pending_requests
+            .iter()
+            .map(|r| format!("fork {} for {}", r.fork_id, r.requester))
+            .collect();
+        println!(
+            "    {} {} {:?} after {} meals, holding forks {:?}, owing [{}], {} messages queued",
+            p.id,
+            p.name,
+            p.state,
+            p.meals_eaten,
+            p.held_forks,
+            requests.join(", "),
+            p.queued_messages
+        );
+    }
 }
 
-struct Fork {
-    id: usize,
-    state: ForkState,
+fn main() -> Result<(), DiningPhilosophersError> {
+    // `dot` prints the table as set up, otherwise an optional speed up of the simulation,
+    // `batch` requests all missing forks at once, `deferred` waits 100ms before asking and
+    // `probabilistic` asks on every other iteration on average, `avoid` holds back requests closing a cycle of
+    // waiting philosophers, `crash` lets philosopher 2 panic, `migrate` moves philosopher 0 to
+    // another thread, `priority` lets philosopher 0 take clean forks,
+    // `bounded` limits every channel to a single message, `eastern` and `random` pick the names,
+    // `philosophers=N` seats N numbered philosophers unless a name list is picked as well,
+    // `stats` adds up what the philosophers told their neighbours about their meals
+    let flags = [
+        "batch",
+        "deferred",
+        "probabilistic",
+        "avoid",
+        "throttle",
+        "events",
+        "crash",
+        "migrate",
+        "priority",
+        "bounded",
+        "eastern",
+        "random",
+        "stats",
+    ];
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let count = args
+        .iter()
+        .find_map(|a| a.strip_prefix("philosophers="))
+        .map(|n| n.parse::<usize>().expect("number of philosophers"));
+    let spawn_order = match args.iter().find_map(|a| a.strip_prefix("spawn=")) {
+        None | Some("sequential") => PhilosopherOrdering::Sequential,
+        Some("reversed") => PhilosopherOrdering::Reversed,
+        Some("priority") => PhilosopherOrdering::ByPriority,
+        Some(seed) => PhilosopherOrdering::Random(seed.parse().expect("spawn order or seed")),
+    };
+    let run_for = args
+        .iter()
+        .find_map(|a| a.strip_prefix("seconds="))
+        .map(|s| Duration::from_secs_f64(s.parse().expect("seconds to run for")));
+    let meals = args
+        .iter()
+        .find_map(|a| a.strip_prefix("meals="))
+        .map(|m| m.parse().expect("meals per philosopher"));
+    let ticks_per_second = args
+        .iter()
+        .find_map(|a| a.strip_prefix("ticks="))
+        .map(|t| t.parse().expect("ticks per second"));
+    let names = if args.iter().any(|a| a == "eastern") {
+        NameGenerator::eastern()
+    } else if args.iter().any(|a| a == "random") {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        NameGenerator::random(seed)
+    } else if count.is_some() {
+        NameGenerator::numbered()
+    } else {
+        NameGenerator::western()
+    };
+    let count = count.unwrap_or(5);
+    let channel_kind = if args.iter().any(|a| a == "bounded") {
+        ChannelKind::Sync(1)
+    } else {
+        ChannelKind::Unbounded
+    };
+    let batch_requests = args.iter().any(|a| a == "batch");
+    let avoid_wait_cycles = args.iter().any(|a| a == "avoid");
+    let request_strategy = if args.iter().any(|a| a == "deferred") {
+        ForkRequestStrategy::Deferred(Duration::from_millis(100))
+    } else if args.iter().any(|a| a == "probabilistic") {
+        ForkRequestStrategy::Probabilistic(0.5)
+    } else {
+        ForkRequestStrategy::Immediate
+    };
+    let crash = args.iter().any(|a| a == "crash");
+    let migrate = args.iter().any(|a| a == "migrate");
+    let priority = args.iter().any(|a| a == "priority");
+    let throttle = args.iter().any(|a| a == "throttle");
+    let print_events = args.iter().any(|a| a == "events");
+    let events_file = args
+        .iter()
+        .find_map(|a| a.strip_prefix("events="))
+        .map(String::from);
+    let stats = args.iter().any(|a| a == "stats");
+    let arg = args.into_iter().find(|a| {
+        !flags.contains(&a.as_str())
+            && !a.starts_with("philosophers=")
+            && !a.starts_with("seconds=")
+            && !a.starts_with("meals=")
+            && !a.starts_with("spawn=")
+            && !a.starts_with("ticks=")
+            && !a.starts_with("events=")
+    });
+    let clock = match arg.as_deref() {
+        None | Some("dot") => SimulationClock::default(),
+        Some("0") => SimulationClock::fast_forward(),
+        Some(m) => SimulationClock::new(m.parse().expect("clock multiplier")),
+    };
+    let config = SimulationConfig {
+        names: names.take(count).collect(),
+        forks: count,
+        eating_duration: Duration::from_secs(1),
+        thinking_duration: Duration::ZERO,
+        clock,
+        batch_requests,
+        request_strategy,
+        avoid_wait_cycles,
+        channel_kind,
+        // far longer than a meal, only a stuck philosopher runs into it
+        fork_lease: Some(Duration::from_secs(10)),
+        request_rate_limit: throttle.then_some(Duration::from_millis(10)),
+        // plenty for the little a philosopher does, keeps large tables cheap
+        thread_stack_size: Some(128 * 1024),
+        run_for,
+        meals,
+        event_log: Arc::default(),
+        print_states: true,
+        spawn_order,
+        ticks_per_second,
+    };
+    let config = match events_file {
+        Some(path) => config.with_event_sink(
+            FileEventSink::create(path)
+                .map_err(|e| DiningPhilosophersError::ConfigError(e.to_string()))?,
+        ),
+        None if print_events => config.with_event_sink(StdoutSink),
+        None => config,
+    };
+    if let Err(errors) = config.validate() {
+        let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(DiningPhilosophersError::ConfigError(errors.join("; ")));
+    }
+
+    let meals_eaten = Arc::new(AtomicU64::new(0));
+    // forks somebody is eating with right now
+    let in_use = Arc::new(Mutex::new(HashSet::new()));
+    let mut phils: Vec<Philosopher> = ring_of_n(&config)?
+        .into_iter()
+        .map(|p| {
+            let taken = in_use.clone();
+            let returned = in_use.clone();
+            let meals_eaten = meals_eaten.clone();
+            p.with_before_eat(move |id, forks| {
+                let mut in_use = taken.lock()?;
+                for fork in forks {
+                    if !in_use.insert(*fork) {
+                        return Err(DiningPhilosophersError::ForkInUse { id, fork_id: *fork });
+                    }
+                }
+                Ok(())
+            })
+            .with_after_eat(move |_, forks| {
+                let mut in_use = returned.lock()?;
+                for fork in forks {
+                    in_use.remove(fork);
+                }
+                meals_eaten.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })
+        })
+        .collect();
+    let events = phils[0].events.clone();
+    let accounting = phils[0].accounting.clone();
+    if arg.as_deref() == Some("dot") {
+        print!("{}", to_dot(&phils));
+        return Ok(());
+    }
+    println!(
+        "fork messages take {} bytes",
+        ForkMessage::Request(phils[0].id, 0, 0, VectorClock::default()).size_bytes()
+    );
+    let philosophers_at_table = phils.len() as u32;
+    let observer = SimulationObserver::new(&phils, accounting.clone());
+    // a waiting philosopher lets each philosopher of a chain of at most n - 1 eat first
+    let starvation_limit =
+        (clock.scaled(config.eating_duration) * philosophers_at_table).max(Duration::from_secs(10));
+    let progress = ProgressMonitor::new(
+        SimulationObserver::new(&phils, accounting),
+        starvation_limit,
+    );
+    let overlaps = Arc::new(Mutex::new(OverlapDetector::new(&phils)));
+    let monitor_overlaps = overlaps.clone();
+    let log = events.clone();
+    let forks = config.forks;
+    let philosophers = config.names.len();
+    let started = Instant::now();
+    thread::spawn(move || {
+        // follows the log every tick so that it never falls behind, prints once a second
+        let mut report = SimulationReport::default();
+        let mut report_cursor = 0;
+        let mut causality = CausalityChecker::default();
+        for tick in 1.. {
+            thread::sleep(Duration::from_millis(100));
+            for (_, _, _, event) in events.read_since(&mut report_cursor) {
+                report.apply(&event);
+            }
+            for overlap in monitor_overlaps.lock().unwrap().check(&events) {
+                println!("{}", overlap);
+            }
+            let violations = causality.check(&events);
+            assert!(
+                violations.is_empty(),
+                "forks delivered before they were sent: {:?}",
+                violations
+            );
+            if tick % 10 == 0 {
+                print_table(
+                    &observer,
+                    &events,
+                    &report,
+                    &meals_eaten,
+                    forks,
+                    philosophers,
+                    started,
+                );
+            }
+        }
+    });
+
+    let (mut supervisor, successors) =
+        PhilosopherSupervisor::new(config.thread_stack_size, config.run_for);
+    supervisor.watch_progress(progress);
+    if let (true, Some(p)) = (crash, phils.get_mut(2)) {
+        p.crash_after_meals = Some(3);
+    }
+    if migrate {
+        phils[0].migrate_after_meals = Some(3);
+    }
+    if priority {
+        phils[0].priority = 1;
+    }
+    let mut aggregator = StatsAggregator::new();
+    if stats {
+        for p in &mut phils {
+            p.setup_stats(aggregator.sender());
+        }
+    }
+    config.spawn_order.arrange(&mut phils);
+    for mut p in phils {
+        p.setup_supervisor(successors.clone());
+        supervisor.spawn(p)?;
+    }
+    supervisor.run()?;
+    // only reached when the philosophers leave the table, see `run_for`
+    assert_no_adjacent_simultaneous_eating(&log, &mut overlaps.lock().unwrap());
+    if stats {
+        aggregator.collect();
+        println!(
+            "neighbours reported {} meals after {:?} of waiting",
+            aggregator.total_meals(),
+            aggregator.total_wait()
+        );
+    }
+    // the last events of the run, at most `EVENT_LOG_CAPACITY` of them
+    print!(
+        "{}",
+        replay(&log.snapshot(), &mut NullSink).to_table_string(philosophers, started.elapsed())
+    );
+    Ok(())
 }
 
-impl std::fmt::Debug for Fork {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let state = match self.state {
-            ForkState::Dirty => "dirty",
-            ForkState::Clean => "clean",
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::philosopher_protocol::PhilosopherProtocol;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum SpyCall {
+        Run,
+        Request,
+        Eat,
+        HandleRequests,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct SpyRecord {
+        call: SpyCall,
+        at: Instant,
+    }
+
+    /// Wraps a philosopher and writes down every call of `run`, `eat` and `handle_requests`
+    struct PhilosopherSpy<P: PhilosopherSteps> {
+        inner: P,
+        calls: Vec<SpyRecord>,
+    }
+
+    impl<P: PhilosopherSteps> PhilosopherSpy<P> {
+        fn new(inner: P) -> Self {
+            Self {
+                inner,
+                calls: vec![],
+            }
+        }
+
+        fn call_log(&self) -> &[SpyRecord] {
+            &self.calls
+        }
+
+        fn into_inner(self) -> P {
+            self.inner
+        }
+
+        fn record(&mut self, call: SpyCall) {
+            self.calls.push(SpyRecord {
+                call,
+                at: Instant::now(),
+            });
+        }
+
+        /// Whether the calls came in the order of `run`: `run` first, then for every meal `request`,
+        /// `handle_requests` as often as it takes and `eat`. The last meal may stop short of `eat`
+        /// when it was time to leave. Does not know about meal limits, full philosophers only
+        /// handle requests.
+        fn called_in_run_order(&self) -> bool {
+            let (first, steps) = match self.calls.split_first() {
+                Some(split) => split,
+                None => return true,
+            };
+            let mut previous = first.call;
+            let in_order = steps.iter().all(|record| {
+                let allowed = match record.call {
+                    SpyCall::Run => false,
+                    SpyCall::Request => matches!(previous, SpyCall::Run | SpyCall::Eat),
+                    SpyCall::HandleRequests | SpyCall::Eat => {
+                        matches!(previous, SpyCall::Request | SpyCall::HandleRequests)
+                    }
+                };
+                previous = record.call;
+                allowed
+            });
+            let in_time = self.calls.windows(2).all(|w| w[0].at <= w[1].at);
+            first.call == SpyCall::Run && in_order && in_time
+        }
+    }
+
+    impl<P: PhilosopherSteps> PhilosopherSteps for PhilosopherSpy<P> {
+        fn philosopher(&mut self) -> &mut Philosopher {
+            self.inner.philosopher()
+        }
+
+        fn request(&mut self) -> Result<(), DiningPhilosophersError> {
+            self.record(SpyCall::Request);
+            self.inner.request()
+        }
+
+        fn eat(&mut self) -> Result<(), DiningPhilosophersError> {
+            self.record(SpyCall::Eat);
+            self.inner.eat()
+        }
+
+        fn handle_requests(&mut self) -> Result<(), DiningPhilosophersError> {
+            self.record(SpyCall::HandleRequests);
+            self.inner.handle_requests()
+        }
+
+        fn run(&mut self) -> Result<(), DiningPhilosophersError> {
+            self.record(SpyCall::Run);
+            // the steps of the philosopher's own `run`, taken through the spy
+            run_steps(self)
+        }
+    }
+
+    /// counts the allocations of every test thread on its own
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            // gone while the thread shuts down
+            let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// allocations `f` made on this thread
+    fn allocations_during(f: impl FnOnce()) -> u64 {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    fn config(n: usize) -> SimulationConfig {
+        SimulationConfig {
+            eating_duration: Duration::from_millis(1),
+            clock: SimulationClock::fast_forward(),
+            ..SimulationConfig::new(NameGenerator::numbered().take(n).collect())
+        }
+    }
+
+    /// every philosopher in its own thread for `duration`, back once they all left
+    fn run_table(phils: Vec<Philosopher>, duration: Duration) -> Vec<Philosopher> {
+        chandy_misra_table::run_table(phils, duration).unwrap()
+    }
+
+    #[test]
+    fn validate_reports_every_error() {
+        let mut config = config(3);
+        config.eating_duration = Duration::from_micros(10);
+        config.meals = Some(0);
+        config.channel_kind = ChannelKind::Sync(0);
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(matches!(errors[0], ConfigError::EatingTooShort(_)));
+        assert!(matches!(errors[1], ConfigError::NoMeals));
+        assert!(matches!(errors[2], ConfigError::NoChannelCapacity));
+    }
+
+    #[test]
+    fn table_leaves_once_everyone_is_full() {
+        let mut config = config(5);
+        config.meals = Some(3);
+        let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+        for p in &phils {
+            assert_eq!(p.meals, 3);
+        }
+    }
+
+    /// stands in for `SimulationConfig`, noting every philosopher it creates and which of
+    /// them got to run
+    #[derive(Debug, Default)]
+    struct MockPhilosopherFactory {
+        created: Mutex<Vec<PhilosopherId>>,
+        ran: Arc<Mutex<HashSet<PhilosopherId>>>,
+    }
+
+    impl PhilosopherFactory for MockPhilosopherFactory {
+        fn create(
+            &self,
+            id: PhilosopherId,
+            name: &str,
+            receiver: DepthTrackedReceiver,
+        ) -> Philosopher {
+            self.created.lock().unwrap().push(id);
+            let ran = self.ran.clone();
+            let mut p = Philosopher::new(id, name, receiver).with_before_eat(move |id, _| {
+                ran.lock()?.insert(id);
+                Ok(())
+            });
+            p.eating_duration = Duration::from_millis(1);
+            p.meal_limit = Some(1);
+            p
+        }
+    }
+
+    #[test]
+    fn table_runs_every_philosopher_the_factory_creates() {
+        let config = config(5);
+        let factory = MockPhilosopherFactory::default();
+        let phils = ForkGraph::ring(5)
+            .unwrap()
+            .build_with(&config, &factory)
+            .unwrap();
+        let created = factory.created.lock().unwrap().clone();
+        assert_eq!(created, phils.iter().map(|p| p.id).collect::<Vec<_>>());
+        let phils = run_table(phils, Duration::from_secs(60));
+        let ran = factory.ran.lock().unwrap();
+        assert_eq!(ran.len(), 5);
+        for p in &phils {
+            assert!(ran.contains(&p.id));
+            assert_eq!(p.meals, 1);
+        }
+    }
+
+    #[test]
+    fn run_reports_every_meal() {
+        let names = ["Baruch Spinoza", "Gilles Deleuze", "Karl Marx"];
+        let report = run_chandy_misra(&names, 10, Duration::from_millis(1)).unwrap();
+        assert_eq!(report.meals, 30);
+        assert_eq!(report.waits.len(), 30);
+    }
+
+    #[test]
+    fn checkpointed_table_carries_on_where_it_stopped() {
+        let mut config = config(5);
+        config.meals = Some(10);
+        let mut phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+        let checkpoint = SimulationCheckpoint::of(&mut phils).unwrap();
+        let text = checkpoint.to_string();
+        assert_eq!(SimulationCheckpoint::parse(&text).unwrap(), checkpoint);
+        config.meals = Some(20);
+        let restored = SimulationCheckpoint::parse(&text)
+            .unwrap()
+            .restore(&config)
+            .unwrap();
+        for p in run_table(restored, Duration::from_secs(60)) {
+            assert_eq!(p.meals, 20);
+            assert_eq!(p.statistics_snapshot().meals_eaten, 20);
+        }
+    }
+
+    #[test]
+    fn unreadable_checkpoint_is_refused() {
+        let text = "table 2 philosophers\nfork 0 between 0 and 1\n\
+                    philosopher 0 meals ten clock 0 vector - name Aristotle\n";
+        assert!(matches!(
+            SimulationCheckpoint::parse(text),
+            Err(ConfigError::InvalidCheckpoint(_))
+        ));
+    }
+
+    #[test]
+    fn neighbours_report_every_meal() {
+        let mut config = config(5);
+        config.meals = Some(3);
+        let mut phils = ring_of_n(&config).unwrap();
+        let mut aggregator = StatsAggregator::new();
+        for p in &mut phils {
+            p.setup_stats(aggregator.sender());
+        }
+        let log = phils[0].events.clone();
+        run_table(phils, Duration::from_secs(60));
+        aggregator.collect();
+        let meals = log
+            .snapshot()
+            .iter()
+            .filter(|e| matches!(e, SimulationEvent::PhilosopherFinishedEating { .. }))
+            .count();
+        assert_eq!(meals, 15);
+        assert_eq!(aggregator.total_meals(), meals as u64);
+    }
+
+    /// rounds of every philosopher taking a step, one after the other, until `id` first ate
+    fn rounds_to_first_meal(batch_requests: bool, id: usize) -> usize {
+        let mut config = config(5);
+        config.batch_requests = batch_requests;
+        let mut phils = ring_of_n(&config).unwrap();
+        for round in 1.. {
+            for p in phils.iter_mut() {
+                p.begin_iteration().unwrap();
+                if p.can_proceed() {
+                    p.eat().unwrap();
+                } else {
+                    p.ask_for_forks().unwrap();
+                }
+            }
+            for p in phils.iter_mut() {
+                while p.message_queue_depth() > 0 {
+                    p.handle_requests().unwrap();
+                }
+            }
+            if phils[id].meals > 0 {
+                return round;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn batching_asks_for_all_forks_at_once() {
+        // philosopher 4 starts without forks
+        let mut phils = ring_of_n(&config(5)).unwrap();
+        phils[4].request_all_forks().unwrap();
+        let requested = phils[4]
+            .forks
+            .iter()
+            .filter(|(_, s)| s.requested_at().is_some())
+            .count();
+        assert_eq!(requested, 2);
+        let (batched, one_by_one) = (
+            rounds_to_first_meal(true, 4),
+            rounds_to_first_meal(false, 4),
+        );
+        println!(
+            "first meal after {} rounds batched, {} one by one",
+            batched, one_by_one
+        );
+        assert!(batched < one_by_one);
+    }
+
+    #[test]
+    fn lease_returns_the_fork_unasked() {
+        let mut config = config(3);
+        config.fork_lease = Some(Duration::from_millis(100));
+        let mut phils = ring_of_n(&config).unwrap();
+        let leased_at = Instant::now();
+        phils[0].begin_iteration().unwrap();
+        assert!(phils[0].forks[1].is_some());
+        // nobody asks, the wait ends when the lease does
+        phils[0].handle_requests().unwrap();
+        phils[0].begin_iteration().unwrap();
+        assert!(leased_at.elapsed() >= Duration::from_millis(100));
+        assert!(phils[0].forks[1].is_in_flight());
+        phils[1].handle_requests().unwrap();
+        assert!(phils[1].forks[1].is_some());
+    }
+
+    #[test]
+    fn delivered_fork_goes_back_to_its_sender() {
+        let mut config = config(3);
+        config.fork_lease = Some(Duration::from_millis(50));
+        let mut phils = ring_of_n(&config).unwrap();
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        phils[1].handle_requests().unwrap();
+        assert_eq!(phils[1].forks[1].return_to(), Some(phils[0].id));
+        // hungry with both forks, only the timer sends the fork back before the meal
+        let leased_at = Instant::now();
+        while !phils[1].forks[1].is_in_flight() {
+            assert!(leased_at.elapsed() < Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(leased_at.elapsed() >= Duration::from_millis(50));
+        // the acknowledgement of fork 1, then fork 1 itself
+        while !phils[0].forks[1].is_some() {
+            phils[0].handle_requests().unwrap();
+        }
+        assert_eq!(phils[0].forks[1].return_to(), Some(phils[1].id));
+        let held: usize = phils
+            .iter()
+            .map(|p| p.held_forks.load(Ordering::Relaxed))
+            .sum();
+        assert_eq!(held, 3);
+    }
+
+    #[test]
+    fn handing_a_leased_fork_on_cancels_its_timer() {
+        let mut config = config(3);
+        config.fork_lease = Some(Duration::from_millis(50));
+        let mut phils = ring_of_n(&config).unwrap();
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        phils[1].handle_requests().unwrap();
+        let to = phils[0].id;
+        phils[1]
+            .send_fork(1, to, DeliveryReason::Requested)
+            .unwrap();
+        while !phils[0].forks[1].is_some() {
+            phils[0].handle_requests().unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+        // the fork did not come a second time
+        assert!(phils[0].receiver.try_recv().is_err());
+        assert!(phils[1].forks[1].is_in_flight());
+    }
+
+    #[test]
+    fn short_leases_lose_no_fork() {
+        let mut config = config(5);
+        config.fork_lease = Some(Duration::from_millis(1));
+        config.meals = Some(30);
+        let phils = ring_of_n(&config).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let phils = run_table(phils, Duration::from_secs(60));
+        for p in &phils {
+            assert_eq!(p.meals, 30);
+        }
+        assert_eq!(observer.fork_count(), 5);
+    }
+
+    #[test]
+    fn fully_held_table_is_ready() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        assert_eq!(observer.system_readiness(), 1.0);
+    }
+
+    #[test]
+    fn only_requested_deliveries_must_follow_the_request() {
+        // fork 1 starts at philosopher 0 and lies between 0 and 1
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let early = |phils: &mut Vec<Philosopher>, reason| {
+            phils[1].forks[1].requested(10);
+            let fork = phils[0].forks[1].take().unwrap();
+            phils[1].handle_message(ForkMessage::Delivery(
+                fork,
+                reason,
+                5,
+                VectorClock::default(),
+            ))
         };
-        f.write_fmt(format_args!("Fork {} {}", self.id, state))
+        assert!(matches!(
+            early(&mut phils, DeliveryReason::Requested),
+            Err(DiningPhilosophersError::DeliveredBeforeRequested { fork_id: 1, .. })
+        ));
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        early(&mut phils, DeliveryReason::Departure).unwrap();
+        assert!(phils[1].forks[1].is_some());
     }
-}
 
-impl Fork {
-    fn new_dirty(id: usize) -> Self {
-        Self {
-            id,
-            state: ForkState::Dirty,
+    #[test]
+    fn leaving_hands_forks_to_a_hungry_neighbour() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // asks philosopher 0 for fork 1, which 0 never reads
+        phils[1].ask_for_forks().unwrap();
+        assert!(phils[1].forks[1].requested_at().is_some());
+        phils[0].leave_table();
+        while phils[1].receiver.depth() > 0 {
+            phils[1].handle_requests().unwrap();
         }
+        assert!(phils[1].forks[1].is_some());
+        assert!(phils[1].can_proceed());
     }
-    fn is_dirty(&self) -> bool {
-        self.state == ForkState::Dirty
+
+    #[test]
+    fn forks_stop_being_in_flight_once_received() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        phils[1].ask_for_forks().unwrap();
+        // fork 1 is dirty, so philosopher 0 gives it up right away
+        phils[0].handle_requests().unwrap();
+        assert!(phils[0].forks[1].is_in_flight());
+        assert!(consistency_check(&phils).is_ok());
+        phils[1].handle_requests().unwrap();
+        assert!(phils[0].forks[1].is_in_flight());
+        phils[0].handle_requests().unwrap();
+        assert!(!phils[0].forks[1].is_in_flight());
+        assert!(consistency_check(&phils).is_ok());
     }
-    fn clean(&mut self) {
-        self.state = ForkState::Clean;
+
+    #[test]
+    fn state_line_names_the_philosopher_and_its_forks() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        for p in &phils {
+            p.print_state().unwrap();
+        }
+        let plain = phils[1].state_line(false).unwrap();
+        assert!(plain.starts_with(&format!("{} {} is hungry.", phils[1].id, phils[1].name)));
+        assert!(plain.contains("Fork 2"), "{}", plain);
+        assert!(!plain.contains('\x1b'));
+        let colored = phils[1].state_line(true).unwrap();
+        assert!(colored.starts_with("\x1b[33m"), "{:?}", colored);
+        let thinking = phils[2].state_line(true).unwrap();
+        assert!(thinking.starts_with("\x1b[90m"), "{:?}", thinking);
     }
-    fn dirty(&mut self) {
-        self.state = ForkState::Dirty;
+
+    #[test]
+    fn broken_table_fails_the_consistency_check() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        assert!(consistency_check(&phils).is_ok());
+        let (p0, p1) = (phils[0].id, phils[1].id);
+        // a second fork 1, and philosopher 0 still asking for its own
+        phils[1]
+            .forks
+            .insert(1, ForkStorage::new(Some(Fork::new_dirty(1))));
+        phils[0].forks[1].requested(5);
+        let violations = consistency_check(&phils).unwrap_err();
+        assert!(violations.iter().any(|v| matches!(
+            v,
+            InvariantViolation::ForkNotInOnePlace { fork_id: 1, holders } if *holders == [p0, p1]
+        )));
+        assert!(violations.iter().any(
+            |v| matches!(v, InvariantViolation::RequestedWhileHeld { id, fork_id: 1 } if *id == p0)
+        ));
     }
-}
 
-struct ForkStorage {
-    requested: Cell<bool>,
-    fork: Option<Fork>,
-}
+    #[test]
+    fn fork_in_flight_at_rest_is_inconsistent() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // taken out as if sent, with no message carrying it
+        phils[0].forks[1].take().unwrap();
+        let violations = consistency_check(&phils).unwrap_err();
+        assert!(violations
+            .iter()
+            .any(|v| matches!(v, InvariantViolation::StuckInFlight { fork_id: 1 })));
+    }
 
-impl std::fmt::Debug for ForkStorage {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let requested = if self.requested.get() {
-            "requested"
-        } else {
-            "not-req"
+    #[test]
+    fn forced_fork_is_taken_unasked() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let to = phils[1].id;
+        phils[0].force_give_fork(1, to).unwrap();
+        phils[1].handle_requests().unwrap();
+        phils[0].handle_requests().unwrap();
+        assert!(phils[1].forks[1].is_some());
+        assert!(!phils[0].forks[1].is_in_flight());
+        assert!(consistency_check(&phils).is_ok());
+    }
+
+    #[test]
+    fn higher_priority_takes_clean_forks() {
+        let clean_fork_at_0 = |priority| {
+            let mut phils = ring_of_n(&config(3)).unwrap();
+            let mut fork = phils[0].forks[1].take().unwrap();
+            fork.clean();
+            phils[0].forks.insert(1, ForkStorage::new(Some(fork)));
+            phils[1].priority = priority;
+            phils[1].ask_for_forks().unwrap();
+            phils[0].handle_requests().unwrap();
+            phils
         };
-        match &self.fork {
-            Some(fork) => {
-                let state = match fork.state {
-                    ForkState::Dirty => "dirty",
-                    ForkState::Clean => "clean",
-                };
-                f.write_fmt(format_args!("Fork {} {} {}", fork.id, state, requested))
+        // a clean fork stays with its holder
+        assert!(clean_fork_at_0(0)[0].forks[1].is_some());
+        let mut phils = clean_fork_at_0(1);
+        assert!(phils[0].forks[1].is_in_flight());
+        phils[1].handle_requests().unwrap();
+        assert!(phils[1].can_proceed());
+    }
+
+    #[test]
+    fn only_the_sharing_neighbour_is_queued() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let (p1, p2) = (phils[1].id, phils[2].id);
+        // fork 1 lies between 0 and 1, fork 0 between 0 and 2
+        phils[0].queue_request(p2, 1, 0).unwrap();
+        phils[0].queue_request(p1, 1, 0).unwrap();
+        phils[0].queue_request(p2, 0, 0).unwrap();
+        let ignored = phils[0]
+            .events
+            .snapshot()
+            .iter()
+            .filter(|e| matches!(e, SimulationEvent::ForkRequestIgnored { fork_id: 1, .. }))
+            .count();
+        assert_eq!(ignored, 1);
+        let drained = phils[0].drain_request_queue_for_fork(1);
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].requester, p1);
+        assert_eq!(phils[0].pending_request_count(), 1);
+    }
+
+    #[test]
+    fn table_of_single_message_channels_makes_progress() {
+        let mut config = config(5);
+        config.channel_kind = ChannelKind::Sync(1);
+        let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_millis(200));
+        for p in &phils {
+            assert!(p.meals > 0, "{} never ate", p.id);
+        }
+    }
+
+    #[test]
+    fn contended_table_defers_requests_for_clean_forks() {
+        let phils = run_table(ring_of_n(&config(3)).unwrap(), Duration::from_millis(200));
+        let deferred = phils[0]
+            .events
+            .snapshot()
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    SimulationEvent::ForkRequestQueued {
+                        reason: QueueReason::ForkClean,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert!(deferred > 0);
+    }
+
+    #[test]
+    fn event_log_keeps_the_latest_events() {
+        let log = EventLog::default();
+        let meal = || SimulationEvent::PhilosopherFinishedEating {
+            id: PhilosopherId::new(0, 1).unwrap(),
+        };
+        let mut cursor = 0;
+        for _ in 0..EVENT_LOG_CAPACITY + 10 {
+            log.record(0, VectorClock::default(), meal()).unwrap();
+        }
+        assert_eq!(log.read_since(&mut cursor).len(), EVENT_LOG_CAPACITY);
+        log.record(0, VectorClock::default(), meal()).unwrap();
+        assert_eq!(log.read_since(&mut cursor).len(), 1);
+        assert!(log.read_since(&mut cursor).is_empty());
+        assert_eq!(log.snapshot().len(), EVENT_LOG_CAPACITY);
+    }
+
+    #[test]
+    fn overlapping_meals_of_neighbours_are_found() {
+        let phils = ring_of_n(&config(3)).unwrap();
+        let (p0, p1) = (phils[0].id, phils[1].id);
+        let mut detector = OverlapDetector::new(&phils);
+        let log = EventLog::default();
+        let record = |event| log.record(0, VectorClock::default(), event);
+        record(SimulationEvent::PhilosopherStartedEating {
+            id: p0,
+            waited: Duration::ZERO,
+        })
+        .unwrap();
+        assert!(detector.check(&log).is_empty());
+        record(SimulationEvent::PhilosopherStartedEating {
+            id: p1,
+            waited: Duration::ZERO,
+        })
+        .unwrap();
+        thread::sleep(Duration::from_millis(5));
+        record(SimulationEvent::PhilosopherFinishedEating { id: p0 }).unwrap();
+        record(SimulationEvent::PhilosopherFinishedEating { id: p1 }).unwrap();
+        let overlaps = detector.check(&log);
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].philosophers, (p0, p1));
+        assert_eq!(overlaps[0].fork_id, 1);
+        assert!(overlaps[0].duration >= Duration::from_millis(5));
+        // nothing new since
+        assert!(detector.check(&log).is_empty());
+    }
+
+    #[test]
+    fn deliveries_follow_their_transfers() {
+        let phils = run_table(ring_of_n(&config(5)).unwrap(), Duration::from_millis(200));
+        let mut causality = CausalityChecker::default();
+        assert!(causality.check(&phils[0].events).is_empty());
+        // a delivery stamped before its transfer
+        let (p0, p1) = (phils[0].id, phils[1].id);
+        let log = EventLog::default();
+        let mut sent = VectorClock::default();
+        sent.tick(p0);
+        sent.tick(p0);
+        let mut delivered = VectorClock::default();
+        delivered.tick(p0);
+        delivered.tick(p1);
+        log.record(
+            7,
+            sent,
+            SimulationEvent::ForkTransferred {
+                from: p0,
+                to: p1,
+                fork_id: 1,
+            },
+        )
+        .unwrap();
+        log.record(
+            8,
+            delivered,
+            SimulationEvent::ForkDelivered {
+                from: p0,
+                to: p1,
+                fork_id: 1,
+                sent_at: 7,
+            },
+        )
+        .unwrap();
+        assert_eq!(CausalityChecker::default().check(&log), vec![(1, 7)]);
+    }
+
+    #[test]
+    fn replayed_log_gives_the_live_report() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        let events = phils[0].events.clone();
+        // the monitor follows the log while the table runs
+        let follower = {
+            let events = events.clone();
+            thread::spawn(move || {
+                let (mut live, mut cursor) = (SimulationReport::default(), 0);
+                let started = Instant::now();
+                while started.elapsed() < Duration::from_millis(300) {
+                    for (_, _, _, event) in events.read_since(&mut cursor) {
+                        live.apply(&event);
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                (live, cursor)
+            })
+        };
+        run_table(phils, Duration::from_millis(200));
+        let (mut live, mut cursor) = follower.join().unwrap();
+        for (_, _, _, event) in events.read_since(&mut cursor) {
+            live.apply(&event);
+        }
+        assert!(live.meals > 0);
+        assert_eq!(replay(&events.snapshot(), &mut NullSink), live);
+    }
+
+    #[test]
+    fn replayed_events_come_out_in_order() {
+        let log = EventLog::default();
+        for i in 0..3 {
+            let id = PhilosopherId::new(i, 3).unwrap();
+            log.record(
+                i as LamportTime,
+                VectorClock::default(),
+                SimulationEvent::PhilosopherStartedEating {
+                    id,
+                    waited: Duration::ZERO,
+                },
+            )
+            .unwrap();
+            log.record(
+                i as LamportTime,
+                VectorClock::default(),
+                SimulationEvent::PhilosopherFinishedEating { id },
+            )
+            .unwrap();
+        }
+        let recorded = log.snapshot();
+        let sink = VecSink::default();
+        let report = replay(&recorded, &mut sink.clone());
+        assert_eq!(report.meals, 3);
+        let replayed = sink.events().unwrap();
+        assert_eq!(replayed.len(), 6);
+        assert_eq!(
+            replayed.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            recorded.iter().map(|e| e.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn tail_of_the_waits_stays_close_to_the_median() {
+        let config = SimulationConfig {
+            eating_duration: Duration::from_millis(5),
+            thinking_duration: Duration::ZERO,
+            meals: Some(20),
+            ..SimulationConfig::new(NameGenerator::numbered().take(5).collect())
+        };
+        let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+        let report = replay(&phils[0].events.snapshot(), &mut NullSink);
+        assert_eq!(report.meals, 100);
+        assert_eq!(report.waits.len(), 100);
+        assert!(
+            report.wait_time_p99() < 10 * report.wait_time_p50(),
+            "p50 {:?}, p99 {:?}",
+            report.wait_time_p50(),
+            report.wait_time_p99()
+        );
+        assert!(report.wait_time_p99() <= report.wait_time_max());
+    }
+
+    #[test]
+    fn report_draws_the_waits_as_bars() {
+        let mut report = SimulationReport::default();
+        let id = PhilosopherId::new(0, 1).unwrap();
+        for ms in [1, 1, 1, 2, 10] {
+            report.apply(&SimulationEvent::PhilosopherStartedEating {
+                id,
+                waited: Duration::from_millis(ms),
+            });
+        }
+        let histogram = report.wait_histogram(10);
+        assert_eq!(histogram.len(), 10);
+        assert_eq!(histogram[1], (Duration::from_millis(1), 3));
+        assert_eq!(histogram[2], (Duration::from_millis(2), 1));
+        assert_eq!(histogram[9], (Duration::from_millis(9), 1));
+        let text = report.to_string();
+        assert!(text.contains("p50 1ms"), "{}", text);
+        assert!(text.contains("max 10ms"), "{}", text);
+        let bars: Vec<_> = text.lines().skip(2).collect();
+        assert_eq!(bars.len(), 10);
+        assert!(bars[1].contains(&"#".repeat(40)), "{}", text);
+        assert!(!bars[0].contains('#'), "{}", text);
+    }
+
+    #[test]
+    fn failures_come_back_as_errors() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let to = phils[1].id;
+        // fork 1 starts at philosopher 0, so 1 has nothing to give
+        assert!(matches!(
+            phils[1].send_fork(1, to, DeliveryReason::Requested),
+            Err(DiningPhilosophersError::ForkNotHeld { fork_id: 1, .. })
+        ));
+        let state = phils[0].state.clone();
+        let _ = thread::spawn(move || {
+            let _held = state.lock().unwrap();
+            panic!("poisons the state of philosopher 0");
+        })
+        .join();
+        assert!(matches!(
+            phils[0].ask_for_forks(),
+            Err(DiningPhilosophersError::LockPoisoned)
+        ));
+    }
+
+    #[test]
+    fn spied_philosopher_eats_and_handles_requests_in_turns() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let spied = thread::spawn({
+            let mut spy = PhilosopherSpy::new(phils.remove(0));
+            move || {
+                spy.run_for(Duration::from_millis(200)).unwrap();
+                spy
+            }
+        });
+        run_table(phils, Duration::from_millis(200));
+        let spy = spied.join().unwrap();
+        let log = spy.call_log();
+        assert_eq!(log[0].call, SpyCall::Run);
+        assert!(log.iter().any(|r| r.call == SpyCall::Eat));
+        assert!(log.iter().any(|r| r.call == SpyCall::HandleRequests));
+        assert!(spy.called_in_run_order(), "{:?}", log);
+        assert!(spy.into_inner().meals > 0);
+    }
+
+    #[test]
+    fn dropped_philosophers_put_their_forks_back() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        let pool = phils[0].fork_pool.clone();
+        assert_eq!(pool.len(), 0, "every fork is at the table");
+        assert!(pool.take(2).unwrap().is_none());
+        drop(phils);
+        assert_eq!(pool.len(), 5);
+        let fork = pool.take(2).unwrap().unwrap();
+        assert_eq!(fork.id(), 2);
+        assert!(fork.is_dirty());
+    }
+
+    #[test]
+    fn recycling_forks_allocates_nothing() {
+        let pool = ForkPool::new(20);
+        let allocations = allocations_during(|| {
+            for _ in 0..10_000 {
+                for id in 0..20 {
+                    let fork = pool.take(id).unwrap().unwrap();
+                    pool.put_back(fork).unwrap();
+                }
             }
-            None => f.write_fmt(format_args!("Fork None {}", requested)),
+        });
+        assert_eq!(allocations, 0);
+        // made once, up front
+        assert!(allocations_during(|| drop(ForkPool::new(20))) > 0);
+    }
+
+    #[test]
+    fn setting_up_forks_allocates_nothing() {
+        let in_a_map = allocations_during(|| {
+            let mut forks = BTreeMap::new();
+            forks.insert(0, ForkStorage::new(None));
+            forks.insert(1, ForkStorage::new(Some(Fork::new_dirty(1))));
+        });
+        let in_place = allocations_during(|| {
+            let mut forks = ForkMap::<2>::default();
+            forks.insert(0, ForkStorage::new(None));
+            forks.insert(1, ForkStorage::new(Some(Fork::new_dirty(1))));
+        });
+        assert!(in_a_map > 0);
+        assert_eq!(in_place, 0);
+    }
+
+    #[test]
+    fn philosopher_between_three_forks_eats_with_all_of_them() {
+        let id = PhilosopherId::new(0, 1).unwrap();
+        let (_, receiver) = fork_channel(ChannelKind::Unbounded);
+        let mut p = Philosopher::<3>::new(id, "Hannah Arendt", receiver);
+        p.needs = [7, 2, 5].iter().copied().collect();
+        for fid in [7, 2] {
+            p.setup_fork(fid, Some(Fork::new_dirty(fid))).unwrap();
         }
+        p.setup_fork(5, None).unwrap();
+        assert!(!p.can_proceed());
+        p.setup_fork(5, Some(Fork::new_dirty(5))).unwrap();
+        assert!(p.can_proceed());
+        assert_eq!(p.forks.keys().copied().collect::<Vec<_>>(), [2, 5, 7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "one more than 2")]
+    fn third_fork_does_not_fit_at_a_round_table() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        phils[0].setup_fork(9, None).unwrap();
     }
-}
 
-impl ForkStorage {
-    fn new(fork: Option<Fork>) -> Self {
-        Self {
-            requested: Cell::new(false),
-            fork,
+    #[test]
+    fn each_neighbour_shares_one_fork() {
+        let phils = ring_of_n(&config(3)).unwrap();
+        let (p0, p1, p2) = (phils[0].id, phils[1].id, phils[2].id);
+        let mut neighbours: Vec<_> = phils[0].neighbors().collect();
+        neighbours.sort();
+        assert_eq!(neighbours, [p1, p2]);
+        // fork `i` lies between philosopher `i` and the one before
+        assert_eq!(phils[0].fork_for_neighbor(p2), Some(0));
+        assert_eq!(phils[0].fork_for_neighbor(p1), Some(1));
+        assert_eq!(phils[1].fork_for_neighbor(p0), Some(1));
+        assert_eq!(phils[2].fork_for_neighbor(p0), Some(0));
+        assert_eq!(phils[0].fork_for_neighbor(p0), None);
+    }
+
+    #[test]
+    fn registry_sees_the_state_of_every_philosopher() {
+        let phils = ring_of_n(&config(4)).unwrap();
+        let registry = phils[0].registry.clone();
+        assert_eq!(registry.eating_count(), 0);
+        phils[1].set_state(PhilosopherState::Eating).unwrap();
+        phils[3].set_state(PhilosopherState::Eating).unwrap();
+        phils[2].set_state(PhilosopherState::Hungry).unwrap();
+        assert_eq!(registry.eating_count(), 2);
+        assert_eq!(registry.hungry_count(), 1);
+        phils[1].set_state(PhilosopherState::Thinking).unwrap();
+        assert_eq!(registry.eating_count(), 1);
+        for _ in 0..3 {
+            registry.report_full();
         }
+        assert!(!registry.all_full());
+        registry.report_full();
+        assert!(registry.all_full());
     }
 
-    fn is_some(&self) -> bool {
-        self.fork.is_some()
+    #[test]
+    fn held_forks_age_every_iteration() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // philosopher 0 starts with forks 0 and 1, philosopher 2 with none
+        for _ in 0..3 {
+            phils[0].begin_iteration().unwrap();
+            phils[2].begin_iteration().unwrap();
+        }
+        assert_eq!(phils[0].forks[1].age_ticks(), 3);
+        assert_eq!(phils[2].forks[0].age_ticks(), 0);
+        // sent away, the next holder starts over
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        assert_eq!(phils[0].forks[1].age_ticks(), 0);
+        phils[1].handle_requests().unwrap();
+        phils[1].begin_iteration().unwrap();
+        assert_eq!(phils[1].forks[1].age_ticks(), 1);
     }
 
-    fn is_dirty(&self) -> bool {
-        self.fork.as_ref().map_or(false, |f| f.is_dirty())
+    #[test]
+    fn mean_wait_forgets_old_meals() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let p = &mut phils[0];
+        assert_eq!(p.recent_mean_wait(), Duration::ZERO);
+        p.record_wait(Duration::from_millis(1000));
+        p.record_wait(Duration::from_millis(2000));
+        assert_eq!(p.recent_mean_wait(), Duration::from_millis(1500));
+        for _ in 0..HISTORY_LEN {
+            p.record_wait(Duration::from_millis(10));
+        }
+        assert_eq!(p.eating_history.len(), HISTORY_LEN);
+        assert_eq!(p.recent_mean_wait(), Duration::from_millis(10));
     }
 
-    fn take(&mut self) -> Option<Fork> {
-        self.fork.take()
+    #[test]
+    fn clock_scales_simulated_time() {
+        let second = Duration::from_secs(1);
+        assert_eq!(SimulationClock::new(0.5).scaled(second), second / 2);
+        assert_eq!(SimulationClock::default().scaled(second), second);
+        let slept = |clock: SimulationClock| {
+            let started = Instant::now();
+            clock.sleep(Duration::from_millis(200));
+            started.elapsed()
+        };
+        let tenth = slept(SimulationClock::new(0.1));
+        assert!(tenth >= Duration::from_millis(20) && tenth < Duration::from_millis(200));
+        assert!(slept(SimulationClock::fast_forward()) < Duration::from_millis(20));
     }
 
-    fn dirty(&mut self) {
-        self.fork.as_mut().unwrap().dirty();
+    #[test]
+    fn dot_shows_every_philosopher_and_held_fork() {
+        let phils = ring_of_n(&config(3)).unwrap();
+        phils[1].set_state(PhilosopherState::Hungry).unwrap();
+        let dot = to_dot(&phils);
+        assert!(dot.starts_with("graph table {\n") && dot.ends_with("}\n"));
+        assert!(dot.contains("p0 [label=\"0 Philosopher 0\\n2 forks, 0 requests\""));
+        assert!(dot.contains(
+            "p1 [label=\"1 Philosopher 1\\n1 forks, 0 requests\" style=filled fillcolor=yellow]"
+        ));
+        assert!(dot.contains("p0 -- p2 [label=\"fork 0 dirty, held by 0\" style=dashed]"));
+        assert!(dot.contains("p1 -- p2 [label=\"fork 2 dirty, held by 1\" style=dashed]"));
+        assert_eq!(dot.matches(" -- ").count(), 3);
     }
 
-    fn needs_requesting(&self) -> bool {
-        self.fork.is_none() && !self.requested.get()
+    #[test]
+    fn request_for_a_clean_fork_is_served_once_it_is_dirty() {
+        // philosopher 0 holds forks 0 and 1, fork 1 clean as if just received
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let mut fork = phils[0].forks[1].take().unwrap();
+        fork.clean();
+        phils[0].forks.insert(1, ForkStorage::new(Some(fork)));
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        assert!(phils[0].forks[1].is_some());
+        assert!(!phils[0].unblock_if_possible().unwrap());
+        // dropping the request here left philosopher 1 waiting for ever
+        assert_eq!(phils[0].pending_request_count(), 1);
+        phils[0].eat().unwrap();
+        assert!(phils[0].forks[1].is_in_flight());
+        assert_eq!(phils[0].pending_request_count(), 0);
+        phils[1].handle_requests().unwrap();
+        assert!(phils[1].forks[1].is_some());
     }
 
-    fn requested(&self) {
-        self.requested.set(true);
+    #[test]
+    fn messages_have_plausible_sizes() {
+        let id = PhilosopherId::new(0, 2).unwrap();
+        let request = ForkMessage::Request(id, 0, 0, VectorClock::default());
+        let delivery = ForkMessage::Delivery(
+            Fork::new_dirty(0),
+            DeliveryReason::Requested,
+            0,
+            VectorClock::default(),
+        );
+        for msg in [&request, &delivery] {
+            assert!(msg.size_bytes() > 0 && msg.size_bytes() < 1024, "{:?}", msg);
+        }
+        assert_eq!(request.size_bytes(), delivery.size_bytes());
     }
-}
 
-enum ForkMessage {
-    Request(usize, usize),
-    Delivery(Fork),
-}
+    #[test]
+    fn ids_stay_within_the_table() {
+        assert_eq!(PhilosopherId::new(4, 5).unwrap().index(), 4);
+        match PhilosopherId::new(5, 5) {
+            Err(e @ id::InvalidId::TooLarge { max: 5 }) => {
+                assert_eq!(e.to_string(), "philosopher ids must be below 5")
+            }
+            other => panic!("{:?} accepted", other),
+        }
+        assert!(PhilosopherId::new(0, 0).is_err());
+    }
 
-#[derive(Debug)]
-struct Philosopher {
-    id: usize,
-    name: String,
-    receiver: mpsc::Receiver<ForkMessage>,
-    neighbours: HashMap<usize, mpsc::Sender<ForkMessage>>,
-    forks: BTreeMap<usize, ForkStorage>,
-    request_queue: VecDeque<(usize, usize)>,
-}
+    #[test]
+    fn storage_reached_by_the_protocol_is_valid() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        phils[1].handle_requests().unwrap();
+        for p in &phils {
+            for storage in p.forks.values() {
+                storage.debug_assert_valid();
+            }
+        }
+    }
 
-impl Philosopher {
-    fn new(id: usize, name: &str, receiver: mpsc::Receiver<ForkMessage>) -> Self {
-        Self {
-            id,
-            name: name.to_string(),
-            receiver,
-            neighbours: HashMap::new(),
-            forks: BTreeMap::new(),
-            request_queue: VecDeque::new(),
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is held but still marked as requested")]
+    fn held_fork_marked_as_requested_is_caught() {
+        let storage = ForkStorage::new(Some(Fork::new_dirty(1)));
+        storage.requested(3);
+        storage.debug_assert_valid();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "fork never held but ages")]
+    fn aging_fork_never_held_is_caught() {
+        let mut storage = ForkStorage::new(None);
+        storage.age_ticks = 1;
+        storage.debug_assert_valid();
+    }
+
+    #[test]
+    fn requests_from_across_the_table_are_ignored() {
+        let mut phils = ring_of_n(&config(5)).unwrap();
+        let (p1, p2, p4) = (phils[1].id, phils[2].id, phils[4].id);
+        assert!(phils[0].is_neighbor(p1) && phils[0].is_neighbor(p4));
+        assert!(!phils[0].is_neighbor(p2));
+        assert!(!phils[0].is_neighbor(phils[0].id));
+        phils[0]
+            .handle_message(ForkMessage::Request(p2, 1, 0, VectorClock::default()))
+            .unwrap();
+        assert_eq!(phils[0].pending_request_count(), 0);
+        assert!(phils[0].forks[1].is_some());
+        assert!(phils[0].events.snapshot().iter().any(|e| matches!(
+            e,
+            SimulationEvent::ForkRequestIgnored { fork_id: 1, from, .. } if *from == p2
+        )));
+    }
+
+    #[test]
+    fn report_table_lines_up_under_its_header() {
+        let id = PhilosopherId::new(1, 5).unwrap();
+        let mut report = SimulationReport::default();
+        for _ in 0..12 {
+            report.apply(&SimulationEvent::PhilosopherFinishedEating { id });
+        }
+        for ms in [3, 40] {
+            report.apply(&SimulationEvent::PhilosopherStartedEating {
+                id,
+                waited: Duration::from_millis(ms),
+            });
+        }
+        let table = report.to_table_string(5, Duration::from_secs(2));
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Algorithm"));
+        assert!(lines[1].chars().all(|c| c == '-'));
+        let row: Vec<_> = lines[2].split('|').map(str::trim).collect();
+        assert_eq!(row, ["Chandy/Misra", "5", "12", "6.0/s", "40ms", "40ms"]);
+        for line in &lines {
+            assert_eq!(line.len(), lines[0].len(), "{:?}", line);
         }
     }
 
-    fn setup_sender(&mut self, phil_id: usize, sender: mpsc::Sender<ForkMessage>) {
-        self.neighbours.insert(phil_id, sender);
+    #[test]
+    fn queue_depth_counts_messages_not_handled_yet() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        assert_eq!(phils[0].message_queue_depth(), 0);
+        phils[1].ask_for_forks().unwrap();
+        assert_eq!(phils[0].message_queue_depth(), 1);
+        phils[0].handle_requests().unwrap();
+        assert_eq!(phils[0].message_queue_depth(), 0);
+        // a message the channel had no room for is not counted
+        let id = phils[0].id;
+        let (sender, receiver) = fork_channel(ChannelKind::Sync(1));
+        let request = || ForkMessage::Request(id, 0, 0, VectorClock::default());
+        sender.send(request()).unwrap();
+        assert!(sender.send(request()).is_err());
+        assert_eq!(receiver.depth(), 1);
+        receiver.try_recv().unwrap();
+        assert_eq!(receiver.depth(), 0);
+    }
+
+    #[test]
+    fn fork_map_is_indexed_by_fork_id() {
+        let mut forks = ForkMap::<2>::default();
+        forks.insert(4, ForkStorage::new(None));
+        forks.insert(3, ForkStorage::new(Some(Fork::new_dirty(3))));
+        assert!(forks[3].is_dirty());
+        assert!(!forks[4].is_some());
+        forks[4] = ForkStorage::new(Some(Fork::new_dirty(4)));
+        assert!(forks[4].is_some());
+        assert_eq!(forks.len(), 2);
     }
 
-    /// take fork into left hand first, subsequent fork goes to right
-    fn setup_fork(&mut self, neighbour: usize, fork: Option<Fork>) {
-        assert!(self.forks.len() <= 2, "Someone gave me a third fork");
-        self.forks.insert(neighbour, ForkStorage::new(fork));
+    #[test]
+    #[should_panic(expected = "fork 0 does not lie next to this philosopher")]
+    fn indexing_a_fork_across_the_table_panics() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        // philosopher 2 sits between forks 2 and 3
+        phils[2].forks[0].is_some();
     }
 
-    fn eat(&mut self) {
-        // eat when both forks are available
-        if self.forks.iter().all(|(_, f)| f.is_some()) {
-            println!("{} {} is eating. {:?}", self.id, self.name, self.forks);
-            thread::sleep(Duration::from_secs(1));
-            // make forks dirty
-            for (_pid, fork) in self.forks.iter_mut() {
-                fork.dirty();
+    #[test]
+    fn table_stepped_in_turns_eats_soon() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let mut ate_after = [None; 3];
+        for round in 1..=10 {
+            for p in phils.iter_mut() {
+                if p.can_proceed() {
+                    step(p, PhilosopherSteps::eat).unwrap();
+                } else {
+                    step(p, PhilosopherSteps::request).unwrap();
+                }
             }
-            println!("{} {} is done eating. {:?}", self.id, self.name, self.forks);
-            // give forks to others who requested them
-            while let Some((who, fork_id)) = self.request_queue.pop_front() {
-                if self.forks[&fork_id].is_dirty() {
-                    let mut fork = self.forks.get_mut(&fork_id).unwrap().take().unwrap();
-                    fork.clean();
-                    let _ = self.neighbours[&who].send(ForkMessage::Delivery(fork));
+            for (i, p) in phils.iter_mut().enumerate() {
+                while p.message_queue_depth() > 0 {
+                    step(p, PhilosopherSteps::handle_requests).unwrap();
+                }
+                if p.meals > 0 {
+                    ate_after[i].get_or_insert(round);
                 }
             }
-        } else {
-            // cannot eat, request the lower-numbered missing resource at neighbours
-            self.forks
-                .iter() // BTreeMap ordered by key
-                .filter(|(_, f)| f.needs_requesting())
-                .take(1)
-                .for_each(|(fid, fork)| {
-                    let pid = if *fid == self.id {
-                        self.neighbour_left()
-                    } else {
-                        self.neighbour_right()
-                    };
-                    let _ = self.neighbours[&pid].send(ForkMessage::Request(self.id, *fid));
-                    fork.requested();
-                });
-        }
-    }
-
-    fn neighbour_left(&self) -> usize {
-        if self.id == 0 {
-            *self.neighbours.keys().max().unwrap()
-        } else {
-            self.id - 1
-        }
-    }
-
-    fn neighbour_right(&self) -> usize {
-        *self
-            .neighbours
-            .keys()
-            .find(|x| **x != self.neighbour_left())
+        }
+        // one after the other, a round each
+        assert!(
+            ate_after.iter().all(|r| r.is_some_and(|r| r <= 3)),
+            "{:?}",
+            ate_after
+        );
+        assert!(phils.iter().all(|p| p.iterations > 0));
+    }
+
+    #[test]
+    fn statistics_taken_while_running_stay_plausible() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let table = thread::spawn(move || run_table(phils, Duration::from_millis(200)));
+        let mut meals = vec![0; 5];
+        while !table.is_finished() {
+            for (i, stats) in observer.statistics().iter().enumerate() {
+                assert!(stats.held_forks <= 2, "{:?}", stats);
+                assert!(stats.meals_eaten >= meals[i], "meals went back");
+                meals[i] = stats.meals_eaten;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let phils = table.join().unwrap();
+        for p in &phils {
+            let stats = p.statistics_snapshot();
+            assert_eq!(stats.meals_eaten, p.meals);
+            assert!(stats.max_starvation_ns <= stats.total_wait_ns);
+            assert_eq!(stats.held_forks, p.held_fork_count());
+        }
+    }
+
+    #[test]
+    fn philosopher_starts_over_from_its_initial_state() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let initial = phils[0].clone_initial_state();
+        let dirty = Some(ForkState::Dirty);
+        assert_eq!(
+            initial.forks_initial,
+            [(0, dirty), (1, dirty)].iter().copied().collect()
+        );
+        // fork 1 goes to philosopher 1, the state moves on
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        assert_ne!(phils[0].clone_initial_state(), initial);
+        let (_, receiver) = fork_channel(ChannelKind::Unbounded);
+        let copy: Philosopher = Philosopher::from_initial_state(initial.clone(), receiver).unwrap();
+        assert_eq!(copy.clone_initial_state(), initial);
+        assert!(copy.can_proceed());
+        assert_eq!(copy.meals, 0);
+    }
+
+    #[test]
+    fn every_seat_starts_over_from_its_initial_state() {
+        for p in &ring_of_n(&config(5)).unwrap() {
+            let initial = p.clone_initial_state();
+            let (_, receiver) = fork_channel(ChannelKind::Unbounded);
+            let copy: Philosopher =
+                Philosopher::from_initial_state(initial.clone(), receiver).unwrap();
+            assert_eq!(
+                copy.clone_initial_state(),
+                initial,
+                "{} cannot start over",
+                p.name
+            );
+        }
+    }
+
+    #[test]
+    fn requested_dirty_fork_is_sent_without_handling_requests() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // nobody asked for fork 0
+        assert!(!phils[0].send_fork_if_requested(0).unwrap());
+        // the request for fork 1 still waits in the channel of philosopher 0
+        phils[1].ask_for_forks().unwrap();
+        assert_eq!(phils[0].message_queue_depth(), 1);
+        assert!(phils[0].send_fork_if_requested(1).unwrap());
+        assert_eq!(phils[0].message_queue_depth(), 0);
+        assert!(phils[0].forks[1].is_in_flight());
+        assert_eq!(phils[0].pending_request_count(), 0);
+        // taken from the channel already, nothing left for handle_requests
+        phils[0].handle_requests().unwrap();
+        phils[1].handle_requests().unwrap();
+        assert!(phils[1].forks[1].is_some());
+    }
+
+    #[test]
+    fn tick_a_second_lets_one_action_through_every_second() {
+        assert!(matches!(TickScheduler::new(0), Err(ConfigError::NoTicks)));
+        let mut scheduler = TickScheduler::new(1).unwrap();
+        assert_eq!(scheduler.period(), Duration::from_secs(1));
+        let started = Instant::now();
+        let mut actions = 0;
+        loop {
+            // an action taking a while does not push the next tick back
+            thread::sleep(Duration::from_millis(100));
+            scheduler.wait_for_next_tick();
+            if started.elapsed() > Duration::from_millis(2500) {
+                break;
+            }
+            actions += 1;
+        }
+        assert_eq!(actions, 2);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(3) && elapsed < Duration::from_millis(3500));
+    }
+
+    #[test]
+    fn migrated_philosopher_carries_on_eating() {
+        let mut config = config(5);
+        config.meals = Some(6);
+        let mut phils = ring_of_n(&config).unwrap();
+        let mut p0 = phils.remove(0);
+        p0.migrate_after_meals = Some(3);
+        let first = thread::spawn(move || {
+            p0.run().unwrap();
+            assert_eq!(p0.meals, 3);
+            (thread::current().id(), p0.into_parts().unwrap())
+        });
+        let others = thread::spawn(move || run_table(phils, Duration::from_secs(60)));
+        let (first_thread, parts) = first.join().unwrap();
+        let second = thread::spawn(move || {
+            let mut p0 = Philosopher::from_parts(parts).unwrap();
+            p0.migrate_after_meals = None;
+            p0.run().unwrap();
+            (thread::current().id(), p0)
+        });
+        let (second_thread, p0) = second.join().unwrap();
+        assert_ne!(first_thread, second_thread);
+        assert_eq!(p0.meals, 6);
+        for p in others.join().unwrap() {
+            assert_eq!(p.meals, 6, "{} did not finish", p.id);
+        }
+    }
+
+    #[test]
+    fn request_while_held_is_flagged_until_the_fork_goes() {
+        // philosopher 0 holds fork 1 clean, as if just received
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let mut fork = phils[0].forks[1].take().unwrap();
+        fork.clean();
+        phils[0].forks.insert(1, ForkStorage::new(Some(fork)));
+        assert!(!phils[0].forks[1].was_requested_while_held());
+        phils[1].ask_for_forks().unwrap();
+        phils[0].handle_requests().unwrap();
+        assert!(phils[0].forks[1].was_requested_while_held());
+        // fork 0 was not asked for
+        assert!(!phils[0].forks[0].was_requested_while_held());
+        phils[0].eat().unwrap();
+        assert!(!phils[0].forks[1].was_requested_while_held());
+        phils[1].handle_requests().unwrap();
+        assert!(!phils[1].forks[1].was_requested_while_held());
+        // set and cleared on the storage itself
+        let mut storage = ForkStorage::new(Some(Fork::new_dirty(2)));
+        storage.set_request_pending(true).unwrap();
+        assert!(storage.take_request_pending());
+        assert!(!storage.was_requested_while_held());
+        // nobody can ask us for a fork we sent away
+        storage.take();
+        assert!(storage.set_request_pending(true).is_err());
+        assert!(!storage.was_requested_while_held());
+        assert!(storage.set_request_pending(false).is_ok());
+    }
+
+    #[test]
+    fn hungry_philosopher_cannot_move() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        let p0 = phils.remove(0);
+        p0.set_state(PhilosopherState::Hungry).unwrap();
+        match p0.into_parts() {
+            Err(DiningPhilosophersError::MovedWhileBusy { id, state }) => {
+                assert_eq!(id.index(), 0);
+                assert_eq!(state, PhilosopherState::Hungry);
+            }
+            other => panic!("moved while hungry: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn failing_hook_ends_the_run_with_its_error() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // philosopher 0 starts out with both forks and eats right away
+        let mut p0 = phils.remove(0).with_before_eat(|id, forks| {
+            Err(DiningPhilosophersError::ForkInUse {
+                id,
+                fork_id: forks[0],
+            })
+        });
+        match p0.run() {
+            Err(DiningPhilosophersError::ForkInUse { fork_id, .. }) => assert_eq!(fork_id, 0),
+            other => panic!("ate despite the hook: {:?}", other),
+        }
+        // a fork we sent away cannot get dirty
+        let mut storage = ForkStorage::new(Some(Fork::new_dirty(2)));
+        assert!(storage.dirty().is_ok());
+        storage.take();
+        assert!(storage.dirty().is_err());
+    }
+
+    #[test]
+    fn two_philosophers_share_both_forks() {
+        let phils = ring_of_n(&config(2)).unwrap();
+        let (p0, p1) = (phils[0].id, phils[1].id);
+        for fid in [0, 1] {
+            assert_eq!(phils[0].neighbour_sharing(fid).unwrap(), p1);
+            assert_eq!(phils[1].neighbour_sharing(fid).unwrap(), p0);
+        }
+        assert_eq!(phils[0].fork_for_neighbor(p1), Some(0));
+        assert!(matches!(
+            phils[0].neighbour_sharing(2),
+            Err(DiningPhilosophersError::UnsharedFork { fork_id: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn vec_sink_gets_every_event_of_the_table() {
+        let sink = VecSink::default();
+        let mut config = config(3).with_event_sink(sink.clone());
+        config.meals = Some(2);
+        let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+        let events = sink.events().unwrap();
+        let logged: Vec<_> = phils[0]
+            .events
+            .snapshot()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let sunk: Vec<_> = events.iter().map(|e| e.to_string()).collect();
+        assert_eq!(sunk, logged);
+        for p in &phils {
+            let meals = events
+                .iter()
+                .filter(|e| matches!(e, SimulationEvent::PhilosopherFinishedEating { id } if *id == p.id))
+                .count();
+            assert_eq!(meals, 2, "{}", p.name);
+        }
+    }
+
+    #[test]
+    fn dropping_a_philosopher_holding_forks_is_an_event() {
+        let sink = VecSink::default();
+        let mut phils = ring_of_n(&config(3).with_event_sink(sink.clone())).unwrap();
+        let mut p = phils.remove(0);
+        let id = p.id;
+        p.iterations = 1;
+        drop(p);
+        let dropped: Vec<_> = sink
+            .events()
             .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                SimulationEvent::ForkDropped { id: by, fork_id } if by == id => Some(fork_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dropped, vec![0, 1]);
     }
 
-    /// go over messages, store or hand out forks
-    fn handle_requests(&mut self) {
-        if let Ok(msg) = self.receiver.recv() {
-            match msg {
-                ForkMessage::Request(by, fork_id) => {
-                    if self.forks[&fork_id].is_dirty() {
-                        let mut fork = self.forks.get_mut(&fork_id).unwrap().take().unwrap();
-                        fork.clean();
-                        let _ = self.neighbours[&by].send(ForkMessage::Delivery(fork));
-                    } else {
-                        // no fork or is clean queue for later
-                        if !self.request_queue.iter().any(|&x| x == (by, fork_id)) {
-                            self.request_queue.push_back((by, fork_id));
-                        }
-                    }
+    /// `ProgressMonitor` on a table whose philosophers are all hungry since `hungry_for` ago
+    fn hungry_table(hungry_for: Duration) -> (Vec<Philosopher>, ProgressMonitor) {
+        let phils = ring_of_n(&config(3)).unwrap();
+        let since = Instant::now() - hungry_for;
+        for p in &phils {
+            p.snapshot.lock().unwrap().hungry_since = Some(since);
+        }
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let monitor = ProgressMonitor::new(observer, Duration::from_millis(20));
+        (phils, monitor)
+    }
+
+    #[test]
+    fn hungry_table_without_meals_is_a_deadlock() {
+        let (phils, mut monitor) = hungry_table(Duration::from_secs(1));
+        monitor.check().unwrap();
+        thread::sleep(Duration::from_millis(30));
+        match monitor.check() {
+            Err(DiningPhilosophersError::Deadlock {
+                hungry,
+                stalled_for,
+            }) => {
+                assert_eq!(hungry.len(), phils.len());
+                assert!(
+                    stalled_for >= Duration::from_millis(30),
+                    "{:?}",
+                    stalled_for
+                );
+            }
+            other => panic!("expected a deadlock, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn philosopher_hungry_while_others_eat_starves() {
+        let (phils, mut monitor) = hungry_table(Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(30));
+        // the others keep eating
+        phils[1].snapshot.lock().unwrap().hungry_since = None;
+        phils[1]
+            .counters
+            .meals_eaten
+            .fetch_add(1, Ordering::Relaxed);
+        match monitor.check() {
+            Err(DiningPhilosophersError::Starvation {
+                philosopher_id,
+                starved_for,
+            }) => {
+                assert_ne!(philosopher_id, phils[1].id);
+                assert!(starved_for >= Duration::from_secs(1), "{:?}", starved_for);
+            }
+            other => panic!("expected starvation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn supervisor_ends_a_run_that_deadlocks() {
+        let mut phils = ring_of_n(&config(3)).unwrap();
+        // holds both its forks and never runs
+        let absent = phils.remove(0);
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let (mut supervisor, _) = PhilosopherSupervisor::new(None, Some(Duration::from_secs(2)));
+        supervisor.watch_progress(ProgressMonitor::new(observer, Duration::from_millis(200)));
+        for p in phils {
+            supervisor.spawn(p).unwrap();
+        }
+        let started = Instant::now();
+        let result = supervisor.run();
+        assert!(
+            matches!(result, Err(DiningPhilosophersError::Deadlock { .. })),
+            "{:?}",
+            result
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(absent);
+    }
+
+    #[test]
+    fn thousand_philosophers_fit_on_small_stacks() {
+        let mut config = config(1000);
+        config.thread_stack_size = Some(64 * 1024);
+        let phils = ring_of_n(&config).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let (mut supervisor, _) =
+            PhilosopherSupervisor::new(config.thread_stack_size, Some(Duration::from_secs(1)));
+        for p in phils {
+            supervisor.spawn(p).unwrap();
+        }
+        supervisor.run().unwrap();
+        assert!(observer.total_meals() >= 1000);
+        assert_eq!(observer.fork_count(), 1000);
+    }
+
+    #[test]
+    fn fork_count_never_changes_while_the_table_runs() {
+        let mut config = config(5);
+        config.eating_duration = Duration::from_millis(2);
+        config.clock = SimulationClock::default();
+        let phils = ring_of_n(&config).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let table = thread::spawn(move || run_table(phils, Duration::from_millis(300)));
+        let mut counts = vec![];
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(20));
+            counts.push(observer.fork_count());
+        }
+        let phils = table.join().unwrap();
+        assert_eq!(counts, [5; 10]);
+        assert!(observer.total_meals() > 0);
+        assert_eq!(phils.len(), 5);
+    }
+
+    #[test]
+    fn table_eats_on_after_philosopher_2_panics() {
+        let mut phils = ring_of_n(&config(5)).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let (mut supervisor, successors) =
+            PhilosopherSupervisor::new(None, Some(Duration::from_secs(1)));
+        phils[2].crash_after_meals = Some(3);
+        for mut p in phils {
+            p.setup_supervisor(successors.clone());
+            supervisor.spawn(p).unwrap();
+        }
+        supervisor.run().unwrap();
+        let stats = observer.statistics();
+        for (i, s) in stats.iter().enumerate() {
+            // stuck at a few meals each if the neighbours waited for the crashed one
+            assert!(s.meals_eaten >= 20, "philosopher {} ate {:?}", i, s);
+        }
+        assert_eq!(observer.fork_count(), 5);
+    }
+
+    #[test]
+    fn deliveries_follow_their_requests() {
+        let mut config = config(5);
+        config.meals = Some(20);
+        let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+        let events = phils[0].events.read_since(&mut 0);
+        let mut answered = 0;
+        for (i, (_, _, asked, event)) in events.iter().enumerate() {
+            let (requester, holder, fork) = match *event {
+                SimulationEvent::ForkRequested { from, to, fork_id } => (from, to, fork_id),
+                _ => continue,
+            };
+            let transfer = events[i..].iter().find_map(|(_, at, vector, e)| match *e {
+                SimulationEvent::ForkTransferred { from, to, fork_id }
+                    if (from, to, fork_id) == (holder, requester, fork) =>
+                {
+                    Some((*at, vector))
+                }
+                _ => None,
+            });
+            let (sent_at, sent) = match transfer {
+                Some(transfer) => transfer,
+                None => continue,
+            };
+            let delivered = events
+                .iter()
+                .find_map(|(_, _, vector, e)| match *e {
+                    SimulationEvent::ForkDelivered {
+                        to,
+                        fork_id,
+                        sent_at: s,
+                        ..
+                    } if (to, fork_id, s) == (requester, fork, sent_at) => Some(vector),
+                    _ => None,
+                })
+                .unwrap();
+            assert!(asked.happened_before(sent), "{} answered too early", event);
+            assert!(
+                asked.happened_before(delivered),
+                "{} answered too early",
+                event
+            );
+            answered += 1;
+        }
+        assert!(answered > 0);
+    }
+
+    #[test]
+    fn request_closing_a_circle_of_waits_is_held_back() {
+        let mut config = config(4);
+        config.avoid_wait_cycles = true;
+        let mut phils = ring_of_n(&config).unwrap();
+        let ids: Vec<_> = phils.iter().map(|p| p.id).collect();
+        // 1 waits for 2 waits for 3, fork 1 lies between 0 and 1
+        phils[0].wait_for.wait(ids[1], ids[2]).unwrap();
+        phils[0].wait_for.wait(ids[2], ids[3]).unwrap();
+        assert!(!phils[0].would_deadlock_if_requesting(1).unwrap());
+        // and 3 waits for 0, asking 1 would close the circle
+        phils[0].wait_for.wait(ids[3], ids[0]).unwrap();
+        assert!(phils[0].would_deadlock_if_requesting(1).unwrap());
+        let mut fork = phils[0].forks[1].take().unwrap();
+        fork.clean();
+        phils[1].forks.insert(1, ForkStorage::new(Some(fork)));
+        phils[0].ask_for_forks().unwrap();
+        assert!(phils[0].forks[1].requested_at().is_none());
+        assert!(phils[0].requests_held_back);
+        // the circle is broken, the request goes out
+        phils[0].wait_for.done_waiting(ids[3], ids[0]).unwrap();
+        phils[0].ask_for_forks().unwrap();
+        assert!(phils[0].forks[1].requested_at().is_some());
+    }
+
+    #[test]
+    fn after_eat_hook_runs_once_a_meal() {
+        let mut config = config(5);
+        config.meals = Some(10);
+        let counted = Arc::new(AtomicU64::new(0));
+        let phils: Vec<_> = ring_of_n(&config)
+            .unwrap()
+            .into_iter()
+            .map(|p| {
+                let counted = counted.clone();
+                p.with_after_eat(move |_, forks| {
+                    assert_eq!(forks.len(), 2);
+                    counted.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                })
+            })
+            .collect();
+        let phils = run_table(phils, Duration::from_secs(60));
+        let meals: u64 = phils.iter().map(|p| p.meals).sum();
+        assert_eq!(meals, 50);
+        assert_eq!(counted.load(Ordering::Relaxed), meals);
+    }
+
+    #[test]
+    fn every_error_tells_what_went_wrong() {
+        let (p1, p2) = (
+            PhilosopherId::new(1, 5).unwrap(),
+            PhilosopherId::new(2, 5).unwrap(),
+        );
+        let errors: Vec<(DiningPhilosophersError, &[&str])> = vec![
+            (
+                DiningPhilosophersError::PhilosopherPanicked {
+                    id: p1,
+                    message: "choked".to_string(),
+                },
+                &["philosopher 1", "panicked", "choked"],
+            ),
+            (
+                DiningPhilosophersError::ConfigError("no names".to_string()),
+                &["invalid configuration", "no names"],
+            ),
+            (
+                DiningPhilosophersError::ChannelClosed(p2),
+                &["philosopher 2", "closed"],
+            ),
+            (
+                DiningPhilosophersError::ThreadSpawn(std::io::Error::other("out of threads")),
+                &["spawn", "out of threads"],
+            ),
+            (
+                DiningPhilosophersError::DeliveredBeforeRequested { id: p1, fork_id: 3 },
+                &["philosopher 1", "fork 3", "before asking"],
+            ),
+            (DiningPhilosophersError::LockPoisoned, &["panicked", "lock"]),
+            (
+                DiningPhilosophersError::UnsharedFork { id: p1, fork_id: 3 },
+                &["philosopher 1", "fork 3", "nobody"],
+            ),
+            (
+                DiningPhilosophersError::ForkNotHeld { id: p1, fork_id: 3 },
+                &["philosopher 1", "fork 3", "does not hold"],
+            ),
+            (
+                DiningPhilosophersError::NeighbourEating {
+                    id: p1,
+                    neighbour: p2,
+                },
+                &["philosopher 1", "neighbour 2"],
+            ),
+            (
+                DiningPhilosophersError::ForkInUse { id: p1, fork_id: 3 },
+                &["philosopher 1", "fork 3", "in use"],
+            ),
+            (
+                DiningPhilosophersError::MovedWhileBusy {
+                    id: p1,
+                    state: PhilosopherState::Eating,
+                },
+                &["philosopher 1", "move", "Eating"],
+            ),
+            (
+                DiningPhilosophersError::Deadlock {
+                    hungry: vec![p1, p2],
+                    stalled_for: Duration::from_secs(2),
+                },
+                &["deadlock", "1, 2", "2s"],
+            ),
+            (
+                DiningPhilosophersError::Starvation {
+                    philosopher_id: p2,
+                    starved_for: Duration::from_secs(3),
+                },
+                &["philosopher 2", "starves", "3s"],
+            ),
+            (
+                ConfigError::TooFewPhilosophers(1).into(),
+                &["at least 2", "got 1"],
+            ),
+            (
+                ConfigError::ForkCountMismatch {
+                    philosophers: 5,
+                    forks: 4,
                 }
-                ForkMessage::Delivery(fork) => {
-                    self.forks.insert(fork.id, ForkStorage::new(Some(fork)));
+                .into(),
+                &["5 philosophers", "got 4"],
+            ),
+            (
+                ConfigError::InvalidClockMultiplier(-1.0).into(),
+                &["clock multiplier", "-1"],
+            ),
+            (
+                ConfigError::EatingTooShort(Duration::from_micros(10)).into(),
+                &["1ms", "10µs"],
+            ),
+            (ConfigError::NoMeals.into(), &["1 meal"]),
+            (
+                ConfigError::NoChannelCapacity.into(),
+                &["channels", "1 message"],
+            ),
+            (ConfigError::NoTicks.into(), &["1 tick"]),
+            (
+                ConfigError::InvalidFork {
+                    fork_id: 3,
+                    between: (2, 7),
+                    philosophers: 5,
                 }
+                .into(),
+                &["fork 3", "2 and 7", "5 philosophers"],
+            ),
+            (
+                ConfigError::ForkCountOutOfRange {
+                    philosophers: 5,
+                    forks: 11,
+                }
+                .into(),
+                &["5 philosophers", "between 3 and 10", "got 11"],
+            ),
+            (
+                ConfigError::TooManyForks {
+                    philosopher: 2,
+                    forks: 3,
+                    room: 2,
+                }
+                .into(),
+                &["philosopher 2", "3 forks", "room for 2"],
+            ),
+            (
+                ConfigError::NameCountMismatch {
+                    philosophers: 5,
+                    names: 4,
+                }
+                .into(),
+                &["5 philosophers", "4 names"],
+            ),
+            (
+                ConfigError::InvalidCheckpoint("line 2".to_string()).into(),
+                &["checkpoint", "line 2"],
+            ),
+        ];
+        let mut seen = HashSet::new();
+        for (error, fragments) in &errors {
+            let message = error.to_string();
+            for fragment in *fragments {
+                assert!(
+                    message.contains(fragment),
+                    "{:?} in {:?}",
+                    fragment,
+                    message
+                );
             }
+            assert!(seen.insert(message.clone()), "{:?} twice", message);
         }
     }
 
-    fn run(&mut self) {
-        loop {
-            self.eat();
-            self.handle_requests();
+    #[test]
+    fn asking_right_away_is_never_slower_than_waiting_first() {
+        let until_full = |strategy| {
+            let mut config = config(5);
+            config.clock = SimulationClock::default();
+            config.eating_duration = Duration::from_millis(2);
+            config.meals = Some(5);
+            config.request_strategy = strategy;
+            let started = Instant::now();
+            let phils = run_table(ring_of_n(&config).unwrap(), Duration::from_secs(60));
+            assert!(phils.iter().all(|p| p.meals == 5));
+            started.elapsed()
+        };
+        for _ in 0..3 {
+            let immediate = until_full(ForkRequestStrategy::Immediate);
+            let deferred = until_full(ForkRequestStrategy::Deferred(Duration::from_millis(100)));
+            assert!(immediate <= deferred, "{:?} > {:?}", immediate, deferred);
         }
     }
-}
 
-fn main() {
-    let names = vec![
-        "Baruch Spinoza",
-        "Gilles Deleuze",
-        "Karl Marx",
-        "Friedrich Nietzsche",
-        "Michael Foucault",
-    ];
-    let mut senders = vec![];
-    let mut phils: Vec<Philosopher> = names
-        .iter()
-        .enumerate()
-        .map(|(i, n)| {
-            let (s, r) = mpsc::channel::<ForkMessage>();
-            senders.push(s);
-            Philosopher::new(i, n, r)
-        })
-        .collect();
+    #[test]
+    fn rate_limit_bounds_the_channel_when_requests_get_lost() {
+        // every request is lost as far as philosopher 1 knows, so it asks again and again
+        let depth_after_losing_requests = |limit| {
+            let mut config = config(3);
+            config.request_rate_limit = limit;
+            let mut phils = ring_of_n(&config).unwrap();
+            for _ in 0..100 {
+                phils[1].forks[1].requested.set(None);
+                phils[1].ask_for_forks().unwrap();
+            }
+            phils[0].receiver.depth()
+        };
+        assert_eq!(depth_after_losing_requests(None), 100);
+        let limited = depth_after_losing_requests(Some(Duration::from_secs(60)));
+        assert_eq!(limited, 1);
+    }
 
-    let num_phils = phils.len();
-    let phil_max_idx = num_phils - 1;
-    for (i, sender) in senders.iter().enumerate() {
-        let neighbour_left = if i == 0 { phil_max_idx } else { i - 1 };
-        let neighbour_right = if i == phil_max_idx { 0 } else { i + 1 };
-        phils[neighbour_left].setup_sender(i, sender.clone());
-        phils[neighbour_right].setup_sender(i, sender.clone());
-        let gets_fork = std::cmp::min(neighbour_left, i);
-        phils[i].setup_fork(i, None);
-        phils[i].setup_fork(neighbour_right, None);
-        phils[gets_fork].setup_fork(i, Some(Fork::new_dirty(i)));
-    }
-    let mut handles = vec![];
-    for mut p in phils {
-        handles.push(thread::spawn(move || {
-            p.run();
-        }));
+    #[test]
+    fn run_for_ends_on_time() {
+        let phils = ring_of_n(&config(5)).unwrap();
+        let observer = SimulationObserver::new(&phils, phils[0].accounting.clone());
+        let handles: Vec<_> = phils
+            .into_iter()
+            .map(|mut p| {
+                thread::spawn(move || {
+                    let started = Instant::now();
+                    p.run_for(Duration::from_millis(100)).unwrap();
+                    started.elapsed()
+                })
+            })
+            .collect();
+        for handle in handles {
+            // a neighbour that started first may leave first and take the others with it
+            let ran = handle.join().unwrap();
+            assert!(ran < Duration::from_millis(110), "{:?}", ran);
+        }
+        assert!(observer.total_meals() > 0);
+        assert_eq!(observer.fork_count(), 5);
+    }
+
+    /// Philosopher N-1 starts without forks, they all lie at the lower id of the two sharing
+    /// them, so its head start goes to the next one, N-2, who holds one fork already
+    #[test]
+    fn reversed_spawn_lets_the_last_philosopher_with_a_fork_eat_first() {
+        let trials = 50;
+        let first_eaters: Vec<_> = (0..trials)
+            .map(|_| {
+                let mut config = config(5);
+                config.meals = Some(1);
+                let mut phils = ring_of_n(&config).unwrap();
+                PhilosopherOrdering::Reversed.arrange(&mut phils);
+                let phils = run_table(phils, Duration::from_secs(10));
+                let first = phils[0]
+                    .events
+                    .snapshot()
+                    .into_iter()
+                    .find_map(|e| match e {
+                        SimulationEvent::PhilosopherStartedEating { id, .. } => Some(id.index()),
+                        _ => None,
+                    });
+                first.unwrap()
+            })
+            .collect();
+        let n_minus_2 = first_eaters.iter().filter(|&&id| id == 3).count();
+        assert!(n_minus_2 * 10 >= trials * 7, "{:?}", first_eaters);
+    }
+
+    #[test]
+    fn five_western_names_are_unique() {
+        let names: Vec<_> = NameGenerator::western().take(5).collect();
+        let unique: HashSet<_> = names.iter().collect();
+        assert_eq!(unique.len(), 5, "{:?}", names);
+        assert!(names.iter().all(|name| !name.starts_with("Philosopher ")));
     }
-    for h in handles {
-        h.join().unwrap();
+
+    #[test]
+    fn random_names_shuffle_the_same_for_the_same_seed() {
+        let names: Vec<_> = NameGenerator::random(7).take(10).collect();
+        assert_eq!(names, NameGenerator::random(7).take(10).collect::<Vec<_>>());
+        assert_ne!(names, NameGenerator::random(8).take(10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn hundred_numbered_names_are_unique_and_in_order() {
+        let names: Vec<_> = NameGenerator::numbered().take(100).collect();
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(*name, format!("Philosopher {}", i));
+        }
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 100);
+        let config = SimulationConfig::new(names);
+        let phils = ring_of_n(&config).unwrap();
+        assert_eq!(phils[99].name, "Philosopher 99");
     }
 }
@@ -0,0 +1,68 @@
+//! Chandy/Misra at a table of 100 philosophers, nobody starves there either.
+//!
+//! Every philosopher thinks and eats for `RUN`, then the meal count of each one is printed. The
+//! run fails if any philosopher ate fewer than `MIN_MEALS` times. A waiting philosopher is
+//! overtaken by each neighbour at most once, but it can wait on a chain of up to n - 1
+//! philosophers eating one after the other, see `chandy_misra_no_starvation_proof.rs`. The
+//! longest wait grows with the table, `MIN_MEALS` leaves room for that.
+//!
+//! `cargo run --release --example chandy_misra_large -- 10` shortens the default run of 60
+//! seconds.
+
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{RingTopologyBuilder, SimulationConfig};
+
+const PHILOSOPHERS: usize = 100;
+const RUN: Duration = Duration::from_secs(60);
+const MIN_MEALS: u64 = 5;
+const EATING: Duration = Duration::from_millis(10);
+const THINKING: Duration = Duration::from_millis(10);
+
+fn main() {
+    let run = std::env::args().nth(1).map_or(RUN, |s| {
+        Duration::from_secs(s.parse().expect("seconds to run"))
+    });
+
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        thinking_duration: THINKING,
+        ..SimulationConfig::new(
+            (0..PHILOSOPHERS)
+                .map(|i| format!("Philosopher {}", i))
+                .collect(),
+        )
+    };
+    let philosophers = RingTopologyBuilder::new(PHILOSOPHERS)
+        .build(&config)
+        .expect("a table of 100");
+    let philosophers =
+        chandy_misra_table::run_table(philosophers, run).expect("philosophers left early");
+
+    for row in philosophers.chunks(10) {
+        let meals: Vec<_> = row
+            .iter()
+            .map(|p| format!("{:>3}: {:>5}", p.id, p.meals))
+            .collect();
+        println!("{}", meals.join("  "));
+    }
+    let hungry: Vec<_> = philosophers
+        .iter()
+        .filter(|p| p.meals < MIN_MEALS)
+        .map(|p| p.id)
+        .collect();
+    assert!(
+        hungry.is_empty(),
+        "philosophers {:?} ate fewer than {} times in {:?}",
+        hungry,
+        MIN_MEALS,
+        run
+    );
+    println!(
+        "all {} philosophers ate at least {} times in {:?}",
+        PHILOSOPHERS, MIN_MEALS, run
+    );
+}
@@ -0,0 +1,123 @@
+//! Chandy/Misra never lets a philosopher starve, shown by measuring it.
+//!
+//! Five philosophers eat 100 meals each. Every philosopher records how long it was hungry before
+//! each meal and the longest time between two of its meals, prints a histogram of its waits and
+//! the run fails if any wait exceeds `WAIT_BOUND`.
+//!
+//! Why the wait is bounded, following the fairness argument in K. M. Chandy and J. Misra,
+//! "The Drinking Philosophers Problem", ACM TOPLAS 6(4), 1984: the forks orient every edge
+//! of the table towards the philosopher with precedence, the holder of a dirty fork or the one
+//! waiting for a clean fork. The orientation starts acyclic and stays acyclic, because only a
+//! philosopher that eats changes it and eating turns all its edges away from it at once. A hungry
+//! philosopher keeps clean forks, a neighbour that ate gives up its now dirty fork on request.
+//! So once a philosopher is hungry, every neighbour can eat at most once more before that
+//! philosopher gets to eat, nobody can overtake a waiting philosopher twice. The longest chain of
+//! philosophers it can wait on is the longest path in the acyclic graph, at most n - 1 hops.
+
+use std::time::{Duration, Instant};
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{
+    EventLog, Philosopher, RingTopologyBuilder, SimulationConfig, SimulationEvent,
+};
+
+/// at most `HISTORY_LEN`, a philosopher keeps the waits of that many meals
+const MEALS: u64 = chandy_misra_table::HISTORY_LEN as u64;
+const EATING: Duration = Duration::from_millis(10);
+const THINKING: Duration = Duration::from_millis(5);
+/// Empirical limit for a single wait. A chain of n - 1 philosophers eating one after the other
+/// takes 4 * EATING at this table, the rest is slack for the scheduler.
+const WAIT_BOUND: Duration = Duration::from_millis(500);
+
+/// longest wait of `p` for one of its meals
+fn max_wait(p: &Philosopher) -> Duration {
+    p.eating_history.iter().max().copied().unwrap_or_default()
+}
+
+/// Longest time from the end of one meal to the start of the next of every philosopher, the
+/// first one counts from `started`
+fn max_gaps(events: &EventLog, philosophers: usize, started: Instant) -> Vec<Duration> {
+    let mut last_meal = vec![started; philosophers];
+    let mut max_gap = vec![Duration::ZERO; philosophers];
+    for (at, _, _, event) in events.read_since(&mut 0) {
+        match event {
+            SimulationEvent::PhilosopherStartedEating { id, .. } => {
+                let gap = at - last_meal[id.index()];
+                max_gap[id.index()] = max_gap[id.index()].max(gap);
+            }
+            SimulationEvent::PhilosopherFinishedEating { id } => last_meal[id.index()] = at,
+            _ => {}
+        }
+    }
+    max_gap
+}
+
+/// waits in buckets of `EATING`, the last bucket takes everything longer
+fn histogram(p: &Philosopher, buckets: usize) -> Vec<usize> {
+    let mut histogram = vec![0; buckets];
+    for wait in &p.eating_history {
+        let bucket = (wait.as_micros() / EATING.as_micros()) as usize;
+        histogram[bucket.min(buckets - 1)] += 1;
+    }
+    histogram
+}
+
+fn main() {
+    let names = [
+        "Baruch Spinoza",
+        "Gilles Deleuze",
+        "Karl Marx",
+        "Friedrich Nietzsche",
+        "Michel Foucault",
+    ];
+    let n = names.len();
+
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        thinking_duration: THINKING,
+        // full, but the neighbours still need the forks until they are full too
+        meals: Some(MEALS),
+        ..SimulationConfig::new(names.iter().map(|n| n.to_string()).collect())
+    };
+    let philosophers = RingTopologyBuilder::new(n)
+        .build(&config)
+        .expect("a table of five");
+    let started = Instant::now();
+    let philosophers = chandy_misra_table::run_table(philosophers, Duration::from_secs(600))
+        .expect("philosophers left early");
+    let max_gaps = max_gaps(&philosophers[0].events, n, started);
+
+    let buckets = 8;
+    for (p, max_gap) in philosophers.iter().zip(max_gaps) {
+        println!(
+            "{} {}: {} meals, longest wait {:?}, longest gap between meals {:?}",
+            p.id,
+            p.name,
+            p.meals,
+            max_wait(p),
+            max_gap
+        );
+        for (bucket, count) in histogram(p, buckets).into_iter().enumerate() {
+            let from = EATING.as_millis() * bucket as u128;
+            let label = if bucket == buckets - 1 {
+                format!("{}ms+", from)
+            } else {
+                format!("{}-{}ms", from, from + EATING.as_millis())
+            };
+            println!("    {:>9} | {:<50} {}", label, "#".repeat(count / 2), count);
+        }
+    }
+
+    for p in &philosophers {
+        assert!(
+            max_wait(p) < WAIT_BOUND,
+            "{} waited {:?}, more than {:?}",
+            p.name,
+            max_wait(p),
+            WAIT_BOUND
+        );
+    }
+    println!("no philosopher waited longer than {:?}", WAIT_BOUND);
+}
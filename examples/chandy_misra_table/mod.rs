@@ -0,0 +1,4854 @@
+//! Chandy/Misra as `chandy_misra.rs` runs it, for every example that seats Chandy/Misra
+//! philosophers: the forks and their messages, the philosopher and the steps of its run, the
+//! events it records and the tools that watch a table. A `ForkGraph` seats the philosophers at
+//! any table given as the pairs of philosophers sharing a fork, `ring_of_n` at a round one.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::philosopher_protocol::PhilosopherProtocol;
+
+pub type ForkId = usize;
+/// Lamport timestamp, orders events across philosophers by happened-before
+pub type LamportTime = u64;
+/// Philosophers with a higher priority get clean forks too. This gives up the freedom from
+/// starvation of Chandy/Misra for their neighbours with a lower priority: a clean fork is what
+/// keeps a hungry philosopher from being overtaken, so a neighbour that outranks it can take
+/// the fork away again and again before it gets to eat.
+pub type Priority = u8;
+
+pub mod id {
+    /// A seat at the table. Only `new` creates one, so an id is always within the table.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct PhilosopherId(usize);
+
+    #[derive(Debug)]
+    pub enum InvalidId {
+        /// ids of a table with `max` philosophers go from 0 to `max - 1`
+        TooLarge { max: usize },
+    }
+
+    impl PhilosopherId {
+        pub fn new(id: usize, philosophers: usize) -> Result<Self, InvalidId> {
+            if id >= philosophers {
+                Err(InvalidId::TooLarge { max: philosophers })
+            } else {
+                Ok(Self(id))
+            }
+        }
+
+        pub fn index(self) -> usize {
+            self.0
+        }
+    }
+
+    impl std::fmt::Display for PhilosopherId {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_fmt(format_args!("{}", self.0))
+        }
+    }
+
+    impl std::fmt::Display for InvalidId {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                InvalidId::TooLarge { max } => {
+                    f.write_fmt(format_args!("philosopher ids must be below {}", max))
+                }
+            }
+        }
+    }
+}
+
+pub use id::PhilosopherId;
+
+/// One counter per philosopher, the events it has seen of each. Unlike Lamport times these tell
+/// events that happened one after the other apart from concurrent ones. Philosophers missing at
+/// the end count as 0, so a clock only grows as far as the philosophers it heard of.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorClock(pub Vec<u64>);
+
+impl VectorClock {
+    pub fn get(&self, id: usize) -> u64 {
+        self.0.get(id).copied().unwrap_or(0)
+    }
+
+    pub fn tick(&mut self, own: PhilosopherId) {
+        if self.0.len() <= own.index() {
+            self.0.resize(own.index() + 1, 0);
+        }
+        self.0[own.index()] += 1;
+    }
+
+    /// pairwise maximum with a clock received from someone else
+    pub fn merge(&mut self, other: &VectorClock) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (own, theirs) in self.0.iter_mut().zip(&other.0) {
+            *own = (*own).max(*theirs);
+        }
+    }
+
+    /// whether whoever stamped `other` knew about this event
+    pub fn happened_before(&self, other: &VectorClock) -> bool {
+        let len = self.0.len().max(other.0.len());
+        let before = (0..len).all(|i| self.get(i) <= other.get(i));
+        before && (0..len).any(|i| self.get(i) < other.get(i))
+    }
+}
+
+/// meals kept for the rolling wait time average
+pub const HISTORY_LEN: usize = 100;
+/// wait before sending to a full channel again
+pub const OUTBOX_BACKOFF: Duration = Duration::from_millis(1);
+
+pub mod fork {
+    use super::ForkId;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ForkState {
+        Dirty,
+        Clean,
+    }
+
+    /// Only changes state through its methods, so every transition goes through one place
+    pub struct Fork {
+        id: usize,
+        state: ForkState,
+    }
+
+    impl std::fmt::Debug for Fork {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let state = match self.state {
+                ForkState::Dirty => "dirty",
+                ForkState::Clean => "clean",
+            };
+            f.write_fmt(format_args!("Fork {} {}", self.id, state))
+        }
+    }
+
+    // fork ids are unique, so the id alone identifies and orders forks
+    impl PartialEq for Fork {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Eq for Fork {}
+
+    impl PartialOrd for Fork {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Fork {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    impl std::hash::Hash for Fork {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.id.hash(state);
+        }
+    }
+
+    impl Fork {
+        pub fn new_dirty(id: usize) -> Self {
+            Self {
+                id,
+                state: ForkState::Dirty,
+            }
+        }
+        pub fn id(&self) -> ForkId {
+            self.id
+        }
+        pub fn state(&self) -> ForkState {
+            self.state
+        }
+        pub fn is_dirty(&self) -> bool {
+            self.state == ForkState::Dirty
+        }
+        pub fn clean(&mut self) {
+            self.state = ForkState::Clean;
+        }
+        pub fn dirty(&mut self) {
+            self.state = ForkState::Dirty;
+        }
+    }
+}
+
+pub use fork::{Fork, ForkState};
+
+impl Fork {
+    pub fn transfer_to(self, recipient: PhilosopherId) -> TransferredFork {
+        TransferredFork {
+            fork: self,
+            from: None,
+            to: recipient,
+            reason: DeliveryReason::Requested,
+            log: None,
+            sent_at: 0,
+            vector: VectorClock::default(),
+        }
+    }
+}
+
+/// A fork about to be handed to another philosopher
+pub struct TransferredFork {
+    pub fork: Fork,
+    pub from: Option<PhilosopherId>,
+    pub to: PhilosopherId,
+    pub reason: DeliveryReason,
+    pub log: Option<Arc<EventLog>>,
+    pub sent_at: LamportTime,
+    pub vector: VectorClock,
+}
+
+impl TransferredFork {
+    pub fn sent_by(mut self, from: PhilosopherId) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn because(mut self, reason: DeliveryReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn cleaned(mut self) -> Self {
+        self.fork.clean();
+        self
+    }
+
+    pub fn logged(mut self, log: Arc<EventLog>) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    pub fn stamped(mut self, sent_at: LamportTime, vector: VectorClock) -> Self {
+        self.sent_at = sent_at;
+        self.vector = vector;
+        self
+    }
+
+    pub fn send(self, outbox: &mut Outbox) -> Result<(), DiningPhilosophersError> {
+        let TransferredFork {
+            fork,
+            from,
+            to,
+            reason,
+            log,
+            sent_at,
+            vector,
+        } = self;
+        let fork_id = fork.id();
+        outbox
+            .send(ForkMessage::Delivery(fork, reason, sent_at, vector.clone()))
+            .map_err(|_| DiningPhilosophersError::ChannelClosed(to))?;
+        if let (Some(log), Some(from)) = (log, from) {
+            log.record(
+                sent_at,
+                vector,
+                SimulationEvent::ForkTransferred { from, to, fork_id },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a fork is, as far as one of the two philosophers sharing it knows
+pub enum ForkPresence {
+    Held(Fork),
+    /// held, with a `LeaseTimer` that sends it back once the lease runs out
+    Leased(LeaseTimer),
+    /// sent to the neighbour, until it tells us the fork arrived
+    InFlight {
+        fork_id: ForkId,
+        sent_at: Instant,
+    },
+    NotHeld,
+}
+
+/// Sends a leased fork back from a thread of its own once the lease runs out, so a holder that
+/// is stuck does not keep the fork from the table. The fork stays in `slot` until then, the
+/// holder sending it anywhere first cancels the timer.
+pub struct LeaseTimer {
+    pub fork_id: ForkId,
+    pub slot: Arc<Mutex<Option<Fork>>>,
+    pub cancelled: Arc<AtomicBool>,
+    pub thread: thread::Thread,
+}
+
+impl LeaseTimer {
+    pub fn with_slot<T>(&self, f: impl FnOnce(&mut Option<Fork>) -> T) -> T {
+        f(&mut self.slot.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// the fork unless the timer sent it already, the timer ends either way
+    pub fn cancel(&self) -> Option<Fork> {
+        let fork = self.with_slot(|slot| {
+            self.cancelled.store(true, Ordering::Relaxed);
+            slot.take()
+        });
+        self.thread.unpark();
+        fork
+    }
+}
+
+/// What the `LeaseTimer` needs to send the fork the way `Philosopher::send_fork` does, the
+/// clocks are those of the delivery that started the lease
+pub struct LeaseReturn {
+    pub from: PhilosopherId,
+    pub to: PhilosopherId,
+    pub outbox: Outbox,
+    pub held_forks: Arc<AtomicUsize>,
+    pub accounting: Arc<ForkAccounting>,
+    pub events: Arc<EventLog>,
+    pub sent_at: LamportTime,
+    pub vector: VectorClock,
+}
+
+impl LeaseReturn {
+    /// wait for `timeout` unless cancelled before, then send the fork back
+    pub fn expire(mut self, timeout: Duration, slot: &Mutex<Option<Fork>>, cancelled: &AtomicBool) {
+        let expires = Instant::now() + timeout;
+        while !cancelled.load(Ordering::Relaxed) {
+            match expires.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => thread::park_timeout(left),
+                _ => break,
+            }
+        }
+        let _counting = self
+            .accounting
+            .pause
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let fork = {
+            let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+            // the holder cancels under the lock of the slot too
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            match slot.take() {
+                Some(fork) => fork,
+                None => return,
+            }
+        };
+        self.held_forks.fetch_sub(1, Ordering::Relaxed);
+        self.accounting.in_flight.fetch_add(1, Ordering::Relaxed);
+        let sent = fork
+            .transfer_to(self.to)
+            .sent_by(self.from)
+            .because(DeliveryReason::LeaseExpired)
+            .cleaned()
+            .logged(self.events.clone())
+            .stamped(self.sent_at, self.vector.clone())
+            .send(&mut self.outbox);
+        // nobody to tell if the neighbour is gone, the fork went with it
+        while sent.is_ok() && !self.outbox.pending.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+            if self.outbox.flush().is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// The storage was asked to do something only a philosopher holding the fork can do. Storage
+/// does not know whose it is, see `NotHeld::of` for the error of the philosopher.
+#[derive(Debug)]
+pub struct NotHeld;
+
+impl NotHeld {
+    pub fn of(self, id: PhilosopherId, fork_id: ForkId) -> DiningPhilosophersError {
+        DiningPhilosophersError::ForkNotHeld { id, fork_id }
+    }
+}
+
+pub struct ForkStorage {
+    /// our clock when we asked for the fork
+    pub requested: Cell<Option<LamportTime>>,
+    pub fork: ForkPresence,
+    /// iterations the fork has been held since it was received
+    pub age_ticks: u64,
+    /// when we have to give the fork up even if clean
+    pub lease_expires: Option<Instant>,
+    /// who gets the fork back when the lease runs out, see `with_timeout_sender`
+    pub return_to: Option<PhilosopherId>,
+    /// a neighbour asked for the fork since we got it, see `was_requested_while_held`
+    pub request_pending: bool,
+}
+
+impl std::fmt::Debug for ForkStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let requested = if self.requested.get().is_some() {
+            "requested"
+        } else {
+            "not-req"
+        };
+        match &self.fork {
+            ForkPresence::Held(fork) => {
+                let state = match fork.state() {
+                    ForkState::Dirty => "dirty",
+                    ForkState::Clean => "clean",
+                };
+                f.write_fmt(format_args!(
+                    "Fork {} {} {} age {}",
+                    fork.id(),
+                    state,
+                    requested,
+                    self.age_ticks()
+                ))
+            }
+            ForkPresence::Leased(timer) => f.write_fmt(format_args!(
+                "Fork {} leased{} {}",
+                timer.fork_id,
+                if self.is_some() { "" } else { ", sent back" },
+                requested
+            )),
+            ForkPresence::InFlight { fork_id, sent_at } => f.write_fmt(format_args!(
+                "Fork {} sent {:?} ago {}",
+                fork_id,
+                sent_at.elapsed(),
+                requested
+            )),
+            ForkPresence::NotHeld => f.write_fmt(format_args!("Fork None {}", requested)),
+        }
+    }
+}
+
+impl ForkStorage {
+    pub fn new(fork: Option<Fork>) -> Self {
+        Self {
+            requested: Cell::new(None),
+            fork: fork.map_or(ForkPresence::NotHeld, ForkPresence::Held),
+            age_ticks: 0,
+            lease_expires: None,
+            return_to: None,
+            request_pending: false,
+        }
+    }
+
+    pub fn with_lease(fork: Fork, duration: Duration) -> Self {
+        let mut storage = Self::new(Some(fork));
+        storage.lease(duration);
+        storage
+    }
+
+    /// Leased like `with_lease`, once the lease runs out a `LeaseTimer` sends the fork back
+    /// to `back.to`, usually the neighbour it came from, whatever its holder is doing. Sending
+    /// it anywhere before cancels the timer. Without a thread for the timer the holder returns
+    /// the fork, see `Philosopher::return_expired_forks`.
+    pub fn with_timeout_sender(fork: Fork, back: LeaseReturn, timeout: Duration) -> Self {
+        let return_to = back.to;
+        let fork_id = fork.id();
+        let slot = Arc::new(Mutex::new(Some(fork)));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (timer_slot, timer_cancelled) = (slot.clone(), cancelled.clone());
+        let spawned = thread::Builder::new()
+            .name(format!("lease of fork {}", fork_id))
+            .spawn(move || back.expire(timeout, &timer_slot, &timer_cancelled));
+        let mut storage = Self::new(None);
+        storage.lease(timeout);
+        storage.return_to = Some(return_to);
+        storage.fork = match spawned {
+            Ok(handle) => ForkPresence::Leased(LeaseTimer {
+                fork_id,
+                slot,
+                cancelled,
+                thread: handle.thread().clone(),
+            }),
+            Err(_) => slot
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .map_or(ForkPresence::NotHeld, ForkPresence::Held),
+        };
+        storage
+    }
+
+    pub fn return_to(&self) -> Option<PhilosopherId> {
+        self.return_to
+    }
+
+    /// Hold the fork for at most `duration`, afterwards it goes back to the neighbour sharing
+    /// it, clean or not and asked for or not, see `Philosopher::return_expired_forks`. Keeps a
+    /// philosopher stuck with clean forks from blocking the table. Sending the fork away ends
+    /// the lease.
+    pub fn lease(&mut self, duration: Duration) {
+        self.lease_expires = Some(Instant::now() + duration);
+    }
+
+    pub fn lease_expired(&self) -> bool {
+        self.is_some() && self.lease_expires.is_some_and(|t| t <= Instant::now())
+    }
+
+    /// time until the lease of a held fork runs out
+    pub fn lease_remaining(&self) -> Option<Duration> {
+        self.lease_expires
+            .filter(|_| self.is_some())
+            .map(|t| t.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn age_ticks(&self) -> u64 {
+        self.age_ticks
+    }
+
+    pub fn tick(&mut self) {
+        if self.is_some() {
+            self.age_ticks += 1;
+        }
+    }
+
+    pub fn is_some(&self) -> bool {
+        match &self.fork {
+            ForkPresence::Held(_) => true,
+            ForkPresence::Leased(timer) => timer.with_slot(|slot| slot.is_some()),
+            _ => false,
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.fork_state() == Some(ForkState::Dirty)
+    }
+
+    /// Stop the lease timer, keeping the fork if the timer did not send it yet. The lease
+    /// still runs, from now on the holder returns the fork, see
+    /// `Philosopher::return_expired_forks`.
+    pub fn stop_timer(&mut self) {
+        if let ForkPresence::Leased(timer) = &self.fork {
+            let fork_id = timer.fork_id;
+            self.fork = match timer.cancel() {
+                Some(fork) => ForkPresence::Held(fork),
+                None => {
+                    self.age_ticks = 0;
+                    self.lease_expires = None;
+                    self.return_to = None;
+                    self.request_pending = false;
+                    ForkPresence::InFlight {
+                        fork_id,
+                        sent_at: Instant::now(),
+                    }
+                }
+            };
+        }
+    }
+
+    /// take the fork out to send it, from now on it is in flight
+    pub fn take(&mut self) -> Option<Fork> {
+        self.stop_timer();
+        self.age_ticks = 0;
+        self.lease_expires = None;
+        self.return_to = None;
+        self.request_pending = false;
+        match std::mem::replace(&mut self.fork, ForkPresence::NotHeld) {
+            ForkPresence::Held(fork) => {
+                self.fork = ForkPresence::InFlight {
+                    fork_id: fork.id(),
+                    sent_at: Instant::now(),
+                };
+                Some(fork)
+            }
+            other => {
+                self.fork = other;
+                None
+            }
+        }
+    }
+
+    pub fn dirty(&mut self) -> Result<(), NotHeld> {
+        match &mut self.fork {
+            ForkPresence::Held(fork) => {
+                fork.dirty();
+                Ok(())
+            }
+            ForkPresence::Leased(timer) => timer.with_slot(|slot| match slot {
+                Some(fork) => {
+                    fork.dirty();
+                    Ok(())
+                }
+                None => Err(NotHeld),
+            }),
+            _ => Err(NotHeld),
+        }
+    }
+
+    /// state of the fork if we hold it
+    pub fn fork_state(&self) -> Option<ForkState> {
+        match &self.fork {
+            ForkPresence::Held(fork) => Some(fork.state()),
+            ForkPresence::Leased(timer) => timer.with_slot(|slot| slot.as_ref().map(Fork::state)),
+            _ => None,
+        }
+    }
+
+    /// the neighbour we sent the fork to has it now
+    pub fn arrived(&mut self) {
+        if self.is_in_flight() {
+            self.stop_timer();
+            self.fork = ForkPresence::NotHeld;
+        }
+    }
+
+    /// sent away, by us or by the lease timer
+    pub fn is_in_flight(&self) -> bool {
+        match &self.fork {
+            ForkPresence::InFlight { .. } => true,
+            ForkPresence::Leased(_) => !self.is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn needs_requesting(&self) -> bool {
+        !self.is_some() && self.requested.get().is_none()
+    }
+
+    pub fn requested(&self, at: LamportTime) {
+        self.requested.set(Some(at));
+    }
+
+    pub fn requested_at(&self) -> Option<LamportTime> {
+        self.requested.get()
+    }
+
+    /// only a fork we hold can be asked for while we have it
+    pub fn set_request_pending(&mut self, pending: bool) -> Result<(), NotHeld> {
+        if pending && !self.is_some() {
+            return Err(NotHeld);
+        }
+        self.request_pending = pending;
+        Ok(())
+    }
+
+    /// whether somebody asked for the fork, clearing it, for when we are done with the fork
+    pub fn take_request_pending(&mut self) -> bool {
+        std::mem::take(&mut self.request_pending)
+    }
+
+    /// Somebody asked for the fork while we held it, so it goes to them once dirty. Otherwise we
+    /// keep it and save asking for it the next time we are hungry.
+    pub fn was_requested_while_held(&self) -> bool {
+        // no longer held once the lease timer sent it
+        self.request_pending && self.is_some()
+    }
+
+    /// Panics in debug builds if the storage is in a state no sequence of requests, deliveries
+    /// and meals can lead to.
+    pub fn debug_assert_valid(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        match &self.fork {
+            ForkPresence::Held(fork) => assert!(
+                self.requested.get().is_none(),
+                "fork {} is held but still marked as requested",
+                fork.id()
+            ),
+            ForkPresence::Leased(timer) => assert!(
+                self.lease_expires.is_some(),
+                "fork {} has a lease timer but no lease",
+                timer.fork_id
+            ),
+            ForkPresence::InFlight { fork_id, .. } => {
+                assert!(
+                    self.lease_expires.is_none(),
+                    "fork {} was sent away with its lease still running",
+                    fork_id
+                );
+                assert_eq!(self.age_ticks, 0, "fork {} was sent away but ages", fork_id);
+            }
+            ForkPresence::NotHeld => {
+                assert!(self.lease_expires.is_none(), "lease on a fork never held");
+                assert_eq!(self.age_ticks, 0, "fork never held but ages");
+            }
+        }
+    }
+}
+
+/// The forks of one philosopher by id, in id order. At most `N` of them, kept in place so
+/// setting up a philosopher does not allocate for its forks.
+pub struct ForkMap<const N: usize>(pub [Option<(ForkId, ForkStorage)>; N]);
+
+impl<const N: usize> Default for ForkMap<N> {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| None))
+    }
+}
+
+impl<const N: usize> ForkMap<N> {
+    /// replaces the storage of a fork we have already, panics when there are `N` others
+    pub fn insert(&mut self, id: ForkId, storage: ForkStorage) {
+        if let Some((_, old)) = self.0.iter_mut().flatten().find(|(fid, _)| *fid == id) {
+            *old = storage;
+            return;
+        }
+        let free = self
+            .0
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .unwrap_or_else(|| panic!("Someone gave me fork {}, one more than {}", id, N));
+        *free = Some((id, storage));
+        // free slots last
+        self.0
+            .sort_by_key(|slot| slot.as_ref().map_or(ForkId::MAX, |(fid, _)| *fid));
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(Option::is_none)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &ForkId> {
+        self.iter().map(|(fid, _)| fid)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &ForkStorage> {
+        self.iter().map(|(_, storage)| storage)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut ForkStorage> {
+        self.iter_mut().map(|(_, storage)| storage)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ForkId, &ForkStorage)> {
+        self.0.iter().flatten().map(|(fid, storage)| (fid, storage))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&ForkId, &mut ForkStorage)> {
+        self.0
+            .iter_mut()
+            .flatten()
+            .map(|(fid, storage)| (&*fid, storage))
+    }
+
+    /// the forks in storage right now
+    pub fn held(&self) -> ForkSet {
+        self.iter()
+            .filter(|(_, f)| f.is_some())
+            .map(|(fid, _)| *fid)
+            .collect()
+    }
+}
+
+impl<const N: usize> std::fmt::Debug for ForkMap<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// panics when the philosopher does not share that fork
+impl<const N: usize> std::ops::Index<ForkId> for ForkMap<N> {
+    type Output = ForkStorage;
+
+    fn index(&self, id: ForkId) -> &ForkStorage {
+        self.iter()
+            .find(|(fid, _)| **fid == id)
+            .map(|(_, storage)| storage)
+            .unwrap_or_else(|| panic!("fork {} does not lie next to this philosopher", id))
+    }
+}
+
+impl<const N: usize> std::ops::IndexMut<ForkId> for ForkMap<N> {
+    fn index_mut(&mut self, id: ForkId) -> &mut ForkStorage {
+        self.iter_mut()
+            .find(|(fid, _)| **fid == id)
+            .map(|(_, storage)| storage)
+            .unwrap_or_else(|| panic!("fork {} does not lie next to this philosopher", id))
+    }
+}
+
+/// Every fork of the table, made once when the table is set. Philosophers take their forks from
+/// here and put back the ones they are left holding when dropped, so a fork is never made twice.
+#[derive(Debug, Clone, Default)]
+pub struct ForkPool(pub Arc<Mutex<VecDeque<Fork>>>);
+
+impl ForkPool {
+    /// forks `0..forks`, all dirty
+    pub fn new(forks: usize) -> Self {
+        let mut pool = VecDeque::with_capacity(forks);
+        pool.extend((0..forks).map(Fork::new_dirty));
+        Self(Arc::new(Mutex::new(pool)))
+    }
+
+    /// the fork with that id, `None` while it is at the table
+    pub fn take(&self, id: ForkId) -> Result<Option<Fork>, DiningPhilosophersError> {
+        let mut pool = self.0.lock()?;
+        Ok(pool
+            .iter()
+            .position(|f| f.id() == id)
+            .and_then(|i| pool.swap_remove_back(i)))
+    }
+
+    /// dirty again, as it was made
+    pub fn put_back(&self, mut fork: Fork) -> Result<(), DiningPhilosophersError> {
+        fork.dirty();
+        self.0.lock()?.push_back(fork);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Forks by id, e.g. the ones a philosopher needs to eat
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForkSet(pub BTreeSet<ForkId>);
+
+impl ForkSet {
+    /// the left and right fork of a seat at a round table
+    pub fn pair(left: ForkId, right: ForkId) -> Self {
+        Self(BTreeSet::from([left, right]))
+    }
+
+    pub fn contains(&self, id: ForkId) -> bool {
+        self.0.contains(&id)
+    }
+
+    pub fn is_subset_of(&self, held: &ForkSet) -> bool {
+        self.0.is_subset(&held.0)
+    }
+
+    /// the forks of this set that are not in `held`
+    pub fn missing_from(&self, held: &ForkSet) -> ForkSet {
+        Self(self.0.difference(&held.0).copied().collect())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ForkId> {
+        self.0.iter()
+    }
+}
+
+impl std::iter::FromIterator<ForkId> for ForkSet {
+    fn from_iter<I: IntoIterator<Item = ForkId>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// every message carries the Lamport time and vector clock of the sender when it was sent
+#[derive(Debug)]
+pub enum ForkMessage {
+    Request(PhilosopherId, ForkId, LamportTime, VectorClock),
+    /// sent by philosophers with a priority above 0, holders with a lower priority give up the
+    /// fork even when it is clean
+    RequestWithPriority(PhilosopherId, ForkId, LamportTime, VectorClock, Priority),
+    Delivery(Fork, DeliveryReason, LamportTime, VectorClock),
+    /// the fork sent to us arrived, it is no longer in flight
+    Received(ForkId, LamportTime, VectorClock),
+    /// how a neighbour is doing, passed on to the `StatsAggregator` and not part of the protocol
+    Stats {
+        philosopher_id: PhilosopherId,
+        meals: u64,
+        wait_ns: u64,
+    },
+}
+
+/// why a fork was sent, only a requested one answers a request of the receiver
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryReason {
+    Requested,
+    /// the sender left the table and gave away all its forks
+    Departure,
+    /// the sender held the fork past its lease
+    LeaseExpired,
+    /// handed over by `Philosopher::force_give_fork`
+    #[cfg(test)]
+    Forced,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelKind {
+    /// `mpsc::channel`, sending never has to wait
+    Unbounded,
+    /// `mpsc::sync_channel` holding at most this many messages, at least one
+    Sync(usize),
+}
+
+/// Sending half of a philosopher's channel, whatever its kind
+pub trait DiningChannel<T>: std::fmt::Debug + Send + Sync {
+    /// never blocks, a full channel hands the message back
+    fn send(&self, msg: T) -> Result<(), mpsc::TrySendError<T>>;
+}
+
+impl<T: Send> DiningChannel<T> for mpsc::Sender<T> {
+    fn send(&self, msg: T) -> Result<(), mpsc::TrySendError<T>> {
+        mpsc::Sender::send(self, msg).map_err(|e| mpsc::TrySendError::Disconnected(e.0))
+    }
+}
+
+impl<T: Send> DiningChannel<T> for mpsc::SyncSender<T> {
+    fn send(&self, msg: T) -> Result<(), mpsc::TrySendError<T>> {
+        self.try_send(msg)
+    }
+}
+
+pub type ForkSender = Arc<dyn DiningChannel<ForkMessage>>;
+
+pub fn fork_channel(kind: ChannelKind) -> (ForkSender, DepthTrackedReceiver) {
+    let (sender, receiver): (ForkSender, _) = match kind {
+        ChannelKind::Unbounded => {
+            let (s, r) = mpsc::channel();
+            (Arc::new(s), r)
+        }
+        ChannelKind::Sync(capacity) => {
+            let (s, r) = mpsc::sync_channel(capacity);
+            (Arc::new(s), r)
+        }
+    };
+    let depth = Arc::new(AtomicUsize::new(0));
+    let sender = DepthTrackedSender {
+        inner: sender,
+        depth: depth.clone(),
+    };
+    (
+        Arc::new(sender),
+        DepthTrackedReceiver {
+            inner: receiver,
+            depth,
+        },
+    )
+}
+
+/// Sending half of a `DepthTrackedReceiver`
+#[derive(Debug)]
+pub struct DepthTrackedSender {
+    pub inner: ForkSender,
+    pub depth: Arc<AtomicUsize>,
+}
+
+impl DiningChannel<ForkMessage> for DepthTrackedSender {
+    fn send(&self, msg: ForkMessage) -> Result<(), mpsc::TrySendError<ForkMessage>> {
+        // counted before the message can arrive, the receiver must not count it first
+        self.depth.fetch_add(1, Ordering::SeqCst);
+        self.inner.send(msg).inspect_err(|_| {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+        })
+    }
+}
+
+/// A receiver that knows how many messages are waiting in it, `mpsc` does not tell
+#[derive(Debug)]
+pub struct DepthTrackedReceiver {
+    pub inner: mpsc::Receiver<ForkMessage>,
+    pub depth: Arc<AtomicUsize>,
+}
+
+impl DepthTrackedReceiver {
+    pub fn recv(&self) -> Result<ForkMessage, mpsc::RecvError> {
+        let msg = self.inner.recv()?;
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+        Ok(msg)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ForkMessage, mpsc::RecvTimeoutError> {
+        let msg = self.inner.recv_timeout(timeout)?;
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+        Ok(msg)
+    }
+
+    pub fn try_recv(&self) -> Result<ForkMessage, mpsc::TryRecvError> {
+        let msg = self.inner.try_recv()?;
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+        Ok(msg)
+    }
+
+    /// messages sent and not received yet
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
+}
+
+/// Messages for one neighbour, kept in order until its channel has room for them. Two
+/// philosophers blocked on each other's full channel would never get to read their own.
+#[derive(Debug)]
+pub struct Outbox {
+    pub channel: ForkSender,
+    pub pending: VecDeque<ForkMessage>,
+}
+
+impl Outbox {
+    pub fn new(channel: ForkSender) -> Self {
+        Self {
+            channel,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// queue the message and send as much as fits, fails once the neighbour is gone
+    pub fn send(&mut self, msg: ForkMessage) -> Result<(), mpsc::SendError<ForkMessage>> {
+        self.pending.push_back(msg);
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> Result<(), mpsc::SendError<ForkMessage>> {
+        while let Some(msg) = self.pending.pop_front() {
+            match self.channel.send(msg) {
+                Ok(()) => {}
+                Err(mpsc::TrySendError::Full(msg)) => {
+                    self.pending.push_front(msg);
+                    break;
+                }
+                Err(mpsc::TrySendError::Disconnected(msg)) => return Err(mpsc::SendError(msg)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// A neighbour asking for one of our forks
+#[derive(Debug, Clone)]
+pub struct ForkRequest {
+    pub requester: PhilosopherId,
+    pub fork_id: ForkId,
+    pub requested_at: Instant,
+    pub priority: Priority,
+}
+
+impl ForkMessage {
+    /// bytes a message occupies in the channel, every variant takes the size of the enum
+    pub fn size_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhilosopherState {
+    Thinking,
+    Hungry,
+    Eating,
+}
+
+/// What a philosopher was doing between two of its steps
+#[derive(Debug, Clone)]
+pub struct PhilosopherSnapshot {
+    pub id: PhilosopherId,
+    pub name: String,
+    pub state: PhilosopherState,
+    pub held_forks: Vec<ForkId>,
+    pub pending_requests: Vec<ForkRequest>,
+    pub meals_eaten: u64,
+    pub queued_messages: usize,
+    /// since when the philosopher waits for its next meal, `None` unless hungry
+    pub hungry_since: Option<Instant>,
+}
+
+/// Counters of a philosopher, readable by other threads while it runs
+#[derive(Debug, Default)]
+pub struct PhilosopherCounters {
+    pub meals_eaten: AtomicU64,
+    pub total_wait_ns: AtomicU64,
+    pub max_starvation_ns: AtomicU64,
+    pub pending_requests: AtomicUsize,
+}
+
+/// How a philosopher is doing, without pausing it. Every value is read on its own, they may be
+/// a step apart.
+#[derive(Debug, Clone)]
+pub struct PhilosopherStats {
+    pub meals_eaten: u64,
+    pub total_wait_ns: u64,
+    pub max_starvation_ns: u64,
+    pub pending_requests: usize,
+    pub held_forks: usize,
+    pub current_state: PhilosopherState,
+}
+
+impl PhilosopherStats {
+    pub fn read(
+        counters: &PhilosopherCounters,
+        held_forks: &AtomicUsize,
+        state: &Mutex<PhilosopherState>,
+    ) -> Self {
+        Self {
+            meals_eaten: counters.meals_eaten.load(Ordering::Relaxed),
+            total_wait_ns: counters.total_wait_ns.load(Ordering::Relaxed),
+            max_starvation_ns: counters.max_starvation_ns.load(Ordering::Relaxed),
+            pending_requests: counters.pending_requests.load(Ordering::Relaxed),
+            held_forks: held_forks.load(Ordering::Relaxed),
+            current_state: *state.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+}
+
+/// What a `ForkMessage::Stats` said
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsReport {
+    pub philosopher_id: PhilosopherId,
+    pub meals: u64,
+    pub wait_ns: u64,
+}
+
+/// Collects the `ForkMessage::Stats` the neighbours of every philosopher pass on, through one
+/// channel shared by the whole table. Each report arrives once per neighbour, the latest one of
+/// every philosopher counts.
+#[derive(Debug)]
+pub struct StatsAggregator {
+    pub sender: mpsc::Sender<StatsReport>,
+    pub receiver: mpsc::Receiver<StatsReport>,
+    pub latest: BTreeMap<PhilosopherId, StatsReport>,
+}
+
+impl StatsAggregator {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            latest: BTreeMap::new(),
+        }
+    }
+
+    /// for `Philosopher::setup_stats`
+    pub fn sender(&self) -> mpsc::Sender<StatsReport> {
+        self.sender.clone()
+    }
+
+    /// take in the reports that arrived so far
+    pub fn collect(&mut self) {
+        while let Ok(report) = self.receiver.try_recv() {
+            let latest = self.latest.entry(report.philosopher_id).or_insert(report);
+            if report.meals > latest.meals {
+                *latest = report;
+            }
+        }
+    }
+
+    pub fn total_meals(&self) -> u64 {
+        self.latest.values().map(|r| r.meals).sum()
+    }
+
+    pub fn total_wait(&self) -> Duration {
+        Duration::from_nanos(self.latest.values().map(|r| r.wait_ns).sum())
+    }
+}
+
+impl Default for StatsAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// All philosophers at one moment, see `SimulationObserver::inspect_snapshot`
+#[derive(Debug)]
+pub struct SimulationSnapshot {
+    pub timestamp: Instant,
+    pub philosophers: Vec<PhilosopherSnapshot>,
+}
+
+/// Every philosopher at the table by id, allows questions about the whole table
+/// without asking each philosopher thread.
+#[derive(Debug, Default)]
+pub struct PhilosopherRegistry {
+    pub philosophers: HashMap<PhilosopherId, Arc<Mutex<PhilosopherState>>>,
+    /// philosophers that ate all the meals they were to eat
+    pub full: AtomicUsize,
+}
+
+impl PhilosopherRegistry {
+    pub fn register(&mut self, id: PhilosopherId) {
+        self.philosophers
+            .insert(id, Arc::new(Mutex::new(PhilosopherState::Thinking)));
+    }
+
+    pub fn state_of(&self, id: PhilosopherId) -> Arc<Mutex<PhilosopherState>> {
+        self.philosophers[&id].clone()
+    }
+
+    pub fn count(&self, state: PhilosopherState) -> usize {
+        self.philosophers
+            .values()
+            .filter(|s| *s.lock().unwrap_or_else(PoisonError::into_inner) == state)
+            .count()
+    }
+
+    pub fn eating_count(&self) -> usize {
+        self.count(PhilosopherState::Eating)
+    }
+
+    pub fn hungry_count(&self) -> usize {
+        self.count(PhilosopherState::Hungry)
+    }
+
+    pub fn report_full(&self) {
+        self.full.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// every philosopher ate its meals, nobody needs a fork any more
+    pub fn all_full(&self) -> bool {
+        self.full.load(Ordering::SeqCst) == self.philosophers.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueReason {
+    /// the holder still has to eat with it
+    ForkClean,
+    /// the fork is on its way to the holder
+    ForkNotHeld,
+}
+
+#[derive(Debug, Clone)]
+pub enum SimulationEvent {
+    /// `waited` is how long the philosopher was hungry for its forks
+    PhilosopherStartedEating {
+        id: PhilosopherId,
+        waited: Duration,
+    },
+    PhilosopherFinishedEating {
+        id: PhilosopherId,
+    },
+    /// recorded before the request is sent, so it comes before whatever answers it
+    ForkRequested {
+        from: PhilosopherId,
+        to: PhilosopherId,
+        fork_id: ForkId,
+    },
+    ForkRequestQueued {
+        from: PhilosopherId,
+        to: PhilosopherId,
+        fork_id: ForkId,
+        reason: QueueReason,
+    },
+    ForkTransferred {
+        from: PhilosopherId,
+        to: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// a fork arrived, `sent_at` is the time of its `ForkTransferred`
+    ForkDelivered {
+        from: PhilosopherId,
+        to: PhilosopherId,
+        fork_id: ForkId,
+        sent_at: LamportTime,
+    },
+    ForkRequestServed {
+        requester: PhilosopherId,
+        fork_id: ForkId,
+        waited: Duration,
+    },
+    /// a request for a fork `from` does not share with `to`
+    ForkRequestIgnored {
+        from: PhilosopherId,
+        to: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// the philosopher was dropped holding the fork, which went back to the pool
+    ForkDropped {
+        id: PhilosopherId,
+        fork_id: ForkId,
+    },
+}
+
+impl std::fmt::Display for SimulationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationEvent::PhilosopherStartedEating { id, waited } => f.write_fmt(format_args!(
+                "{} started eating after waiting {:?}",
+                id, waited
+            )),
+            SimulationEvent::PhilosopherFinishedEating { id } => {
+                f.write_fmt(format_args!("{} finished eating", id))
+            }
+            SimulationEvent::ForkRequestQueued {
+                from,
+                to,
+                fork_id,
+                reason,
+            } => f.write_fmt(format_args!(
+                "{} queued request of {} for fork {} ({:?})",
+                to, from, fork_id, reason
+            )),
+            SimulationEvent::ForkRequested { from, to, fork_id } => {
+                f.write_fmt(format_args!("{} asked {} for fork {}", from, to, fork_id))
+            }
+            SimulationEvent::ForkTransferred { from, to, fork_id } => {
+                f.write_fmt(format_args!("{} sent fork {} to {}", from, fork_id, to))
+            }
+            SimulationEvent::ForkDelivered {
+                from, to, fork_id, ..
+            } => f.write_fmt(format_args!("{} got fork {} from {}", to, fork_id, from)),
+            SimulationEvent::ForkRequestServed {
+                requester,
+                fork_id,
+                waited,
+            } => f.write_fmt(format_args!(
+                "request of {} for fork {} served after {:?}",
+                requester, fork_id, waited
+            )),
+            SimulationEvent::ForkRequestIgnored { from, to, fork_id } => f.write_fmt(format_args!(
+                "{} ignored request of {} for fork {}",
+                to, from, fork_id
+            )),
+            SimulationEvent::ForkDropped { id, fork_id } => f.write_fmt(format_args!(
+                "WARNING: {} dropped while holding fork {}",
+                id, fork_id
+            )),
+        }
+    }
+}
+
+/// Gets every event as it is recorded, see `EventLog::with_sink`
+pub trait EventSink: std::fmt::Debug + Send {
+    fn on_event(&mut self, event: SimulationEvent);
+}
+
+/// drops every event
+#[derive(Debug)]
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn on_event(&mut self, _: SimulationEvent) {}
+}
+
+/// prints every event
+#[derive(Debug)]
+pub struct StdoutSink;
+
+impl EventSink for StdoutSink {
+    fn on_event(&mut self, event: SimulationEvent) {
+        println!("{}", event);
+    }
+}
+
+/// keeps every event, for whoever holds on to the other end
+#[derive(Debug, Clone, Default)]
+pub struct VecSink(pub Arc<Mutex<Vec<SimulationEvent>>>);
+
+impl VecSink {
+    /// the events so far, oldest first
+    pub fn events(&self) -> Result<Vec<SimulationEvent>, DiningPhilosophersError> {
+        Ok(self.0.lock()?.clone())
+    }
+}
+
+impl EventSink for VecSink {
+    fn on_event(&mut self, event: SimulationEvent) {
+        if let Ok(mut events) = self.0.lock() {
+            events.push(event);
+        }
+    }
+}
+
+/// writes every event on a line of its own
+#[derive(Debug)]
+pub struct FileEventSink(pub std::io::BufWriter<std::fs::File>);
+
+impl FileEventSink {
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self(std::io::BufWriter::new(std::fs::File::create(path)?)))
+    }
+}
+
+impl EventSink for FileEventSink {
+    fn on_event(&mut self, event: SimulationEvent) {
+        use std::io::Write;
+        // losing events to a full disk beats stopping the table
+        let _ = writeln!(self.0, "{}", event);
+    }
+}
+
+/// An event with the wall clock time it was recorded at and the clocks of the philosopher that
+/// recorded it
+pub type RecordedEvent = (Instant, LamportTime, VectorClock, SimulationEvent);
+
+/// Events an `EventLog` keeps, the oldest make room for new ones
+pub const EVENT_LOG_CAPACITY: usize = 100_000;
+
+/// The most recent events of the table, and how many there were in all
+#[derive(Debug, Default)]
+pub struct EventRing {
+    pub events: VecDeque<RecordedEvent>,
+    pub recorded: u64,
+}
+
+/// What happened at the table, in order, with the Lamport time of the philosopher that recorded
+/// it. Keeps the last `EVENT_LOG_CAPACITY` events, readers follow the log with `read_since`
+/// instead of going through all of it again.
+#[derive(Debug)]
+pub struct EventLog {
+    pub events: Mutex<EventRing>,
+    pub sink: Mutex<Box<dyn EventSink>>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::with_sink(NullSink)
+    }
+}
+
+impl EventLog {
+    /// log that also hands every event to `sink`
+    pub fn with_sink(sink: impl EventSink + 'static) -> Self {
+        Self {
+            events: Mutex::default(),
+            sink: Mutex::new(Box::new(sink)),
+        }
+    }
+
+    pub fn record(
+        &self,
+        at: LamportTime,
+        vector: VectorClock,
+        event: SimulationEvent,
+    ) -> Result<(), DiningPhilosophersError> {
+        let mut ring = self.events.lock()?;
+        if ring.events.len() == EVENT_LOG_CAPACITY {
+            ring.events.pop_front();
+        }
+        ring.events
+            .push_back((Instant::now(), at, vector, event.clone()));
+        ring.recorded += 1;
+        self.sink.lock()?.on_event(event);
+        Ok(())
+    }
+
+    /// Events recorded since `cursor` was last moved here, oldest first. Events that dropped
+    /// out of the log in between are skipped.
+    pub fn read_since(&self, cursor: &mut u64) -> Vec<RecordedEvent> {
+        let ring = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        let kept_from = ring.recorded - ring.events.len() as u64;
+        let skip = cursor.saturating_sub(kept_from) as usize;
+        *cursor = ring.recorded;
+        ring.events.iter().skip(skip).cloned().collect()
+    }
+
+    /// every event still kept
+    pub fn snapshot(&self) -> Vec<SimulationEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .events
+            .iter()
+            .map(|(_, _, _, e)| e.clone())
+            .collect()
+    }
+
+    pub fn last(&self) -> Option<(LamportTime, SimulationEvent)> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .events
+            .back()
+            .map(|(_, at, _, e)| (*at, e.clone()))
+    }
+}
+
+/// Who waits for whom: an edge from every philosopher that asked for a fork to the neighbour
+/// sharing it, until the fork arrives
+#[derive(Debug, Default)]
+pub struct WaitForGraph {
+    pub edges: Mutex<HashMap<PhilosopherId, HashSet<PhilosopherId>>>,
+}
+
+impl WaitForGraph {
+    /// whether `from` waiting for `to` would make `from` wait for itself
+    pub fn would_close_cycle(
+        &self,
+        from: PhilosopherId,
+        to: PhilosopherId,
+    ) -> Result<bool, DiningPhilosophersError> {
+        let edges = self.edges.lock()?;
+        let mut seen = HashSet::new();
+        let mut stack = vec![to];
+        while let Some(p) = stack.pop() {
+            if p == from {
+                return Ok(true);
+            }
+            if seen.insert(p) {
+                stack.extend(edges.get(&p).into_iter().flatten().copied());
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn wait(
+        &self,
+        from: PhilosopherId,
+        to: PhilosopherId,
+    ) -> Result<(), DiningPhilosophersError> {
+        self.edges.lock()?.entry(from).or_default().insert(to);
+        Ok(())
+    }
+
+    pub fn done_waiting(
+        &self,
+        from: PhilosopherId,
+        to: PhilosopherId,
+    ) -> Result<(), DiningPhilosophersError> {
+        if let Some(waits_for) = self.edges.lock()?.get_mut(&from) {
+            waits_for.remove(&to);
+        }
+        Ok(())
+    }
+}
+
+/// Forks on their way between philosophers. Philosophers change fork counts only while holding
+/// `pause` for reading, so whoever holds it for writing sees every fork exactly once.
+#[derive(Debug, Default)]
+pub struct ForkAccounting {
+    pub in_flight: AtomicUsize,
+    pub pause: RwLock<()>,
+}
+
+#[derive(Debug)]
+pub enum DiningPhilosophersError {
+    PhilosopherPanicked {
+        id: PhilosopherId,
+        message: String,
+    },
+    ConfigError(String),
+    ChannelClosed(PhilosopherId),
+    ThreadSpawn(std::io::Error),
+    /// a fork arrived in answer to a request made after it was sent
+    DeliveredBeforeRequested {
+        id: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// a thread panicked while holding a lock of the table
+    LockPoisoned,
+    /// the fork lies next to the philosopher but no neighbour shares it
+    UnsharedFork {
+        id: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// the philosopher was to give away a fork it does not hold
+    ForkNotHeld {
+        id: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// two neighbours eat at the same time
+    NeighbourEating {
+        id: PhilosopherId,
+        neighbour: PhilosopherId,
+    },
+    /// a philosopher got to eat with a fork somebody else is eating with
+    ForkInUse {
+        id: PhilosopherId,
+        fork_id: ForkId,
+    },
+    /// only a thinking philosopher can move to another thread
+    MovedWhileBusy {
+        id: PhilosopherId,
+        state: PhilosopherState,
+    },
+    /// philosophers are hungry but nobody ate for a while, see `ProgressMonitor`
+    Deadlock {
+        hungry: Vec<PhilosopherId>,
+        stalled_for: Duration,
+    },
+    /// a philosopher waits for its meal while others eat, see `ProgressMonitor`
+    Starvation {
+        philosopher_id: PhilosopherId,
+        starved_for: Duration,
+    },
+}
+
+impl From<ConfigError> for DiningPhilosophersError {
+    fn from(e: ConfigError) -> Self {
+        DiningPhilosophersError::ConfigError(e.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for DiningPhilosophersError {
+    fn from(_: PoisonError<T>) -> Self {
+        DiningPhilosophersError::LockPoisoned
+    }
+}
+
+impl std::fmt::Display for DiningPhilosophersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiningPhilosophersError::PhilosopherPanicked { id, message } => {
+                f.write_fmt(format_args!("philosopher {} panicked: {}", id, message))
+            }
+            DiningPhilosophersError::ConfigError(msg) => {
+                f.write_fmt(format_args!("invalid configuration: {}", msg))
+            }
+            DiningPhilosophersError::ChannelClosed(id) => {
+                f.write_fmt(format_args!("channel of philosopher {} is closed", id))
+            }
+            DiningPhilosophersError::ThreadSpawn(e) => {
+                f.write_fmt(format_args!("could not spawn philosopher thread: {}", e))
+            }
+            DiningPhilosophersError::DeliveredBeforeRequested { id, fork_id } => {
+                f.write_fmt(format_args!(
+                    "philosopher {} got fork {} before asking for it",
+                    id, fork_id
+                ))
+            }
+            DiningPhilosophersError::LockPoisoned => {
+                f.write_str("a philosopher panicked while holding a lock")
+            }
+            DiningPhilosophersError::UnsharedFork { id, fork_id } => f.write_fmt(format_args!(
+                "philosopher {} shares fork {} with nobody",
+                id, fork_id
+            )),
+            DiningPhilosophersError::ForkNotHeld { id, fork_id } => f.write_fmt(format_args!(
+                "philosopher {} cannot give away fork {}, it does not hold it",
+                id, fork_id
+            )),
+            DiningPhilosophersError::NeighbourEating { id, neighbour } => {
+                f.write_fmt(format_args!(
+                    "philosopher {} eats while its neighbour {} does",
+                    id, neighbour
+                ))
+            }
+            DiningPhilosophersError::ForkInUse { id, fork_id } => f.write_fmt(format_args!(
+                "philosopher {} eats with fork {} while it is in use",
+                id, fork_id
+            )),
+            DiningPhilosophersError::MovedWhileBusy { id, state } => f.write_fmt(format_args!(
+                "philosopher {} cannot move while {:?}",
+                id, state
+            )),
+            DiningPhilosophersError::Deadlock {
+                hungry,
+                stalled_for,
+            } => {
+                let hungry: Vec<_> = hungry.iter().map(|id| id.to_string()).collect();
+                f.write_fmt(format_args!(
+                    "deadlock: philosophers {} are hungry but nobody ate for {:?}",
+                    hungry.join(", "),
+                    stalled_for
+                ))
+            }
+            DiningPhilosophersError::Starvation {
+                philosopher_id,
+                starved_for,
+            } => f.write_fmt(format_args!(
+                "philosopher {} starves, hungry for {:?} while others eat",
+                philosopher_id, starved_for
+            )),
+        }
+    }
+}
+
+impl std::error::Error for DiningPhilosophersError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DiningPhilosophersError::ThreadSpawn(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    TooFewPhilosophers(usize),
+    ForkCountMismatch {
+        philosophers: usize,
+        forks: usize,
+    },
+    InvalidClockMultiplier(f64),
+    /// shorter than a millisecond but not zero, too short for the clock to tell
+    EatingTooShort(Duration),
+    NoMeals,
+    NoChannelCapacity,
+    NoTicks,
+    /// a fork has to lie between two different philosophers of the table
+    InvalidFork {
+        fork_id: ForkId,
+        between: (usize, usize),
+        philosophers: usize,
+    },
+    /// too few forks to give everyone one, or more than there are pairs of philosophers
+    ForkCountOutOfRange {
+        philosophers: usize,
+        forks: usize,
+    },
+    /// the philosopher has room for fewer forks than lie next to it
+    TooManyForks {
+        philosopher: usize,
+        forks: usize,
+        room: usize,
+    },
+    NameCountMismatch {
+        philosophers: usize,
+        names: usize,
+    },
+    /// a `SimulationCheckpoint` that does not describe a table
+    InvalidCheckpoint(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::TooFewPhilosophers(n) => {
+                f.write_fmt(format_args!("need at least 2 philosophers, got {}", n))
+            }
+            ConfigError::ForkCountMismatch {
+                philosophers,
+                forks,
+            } => f.write_fmt(format_args!(
+                "a ring of {} philosophers needs {} forks, got {}",
+                philosophers, philosophers, forks
+            )),
+            ConfigError::InvalidClockMultiplier(m) => f.write_fmt(format_args!(
+                "clock multiplier must be a finite number of at least 0, got {}",
+                m
+            )),
+            ConfigError::EatingTooShort(d) => f.write_fmt(format_args!(
+                "eating takes at least 1ms or no time at all, got {:?}",
+                d
+            )),
+            ConfigError::NoMeals => f.write_fmt(format_args!("need at least 1 meal")),
+            ConfigError::NoChannelCapacity => {
+                f.write_fmt(format_args!("channels need room for at least 1 message"))
+            }
+            ConfigError::NoTicks => f.write_fmt(format_args!("need at least 1 tick per second")),
+            ConfigError::InvalidFork {
+                fork_id,
+                between: (a, b),
+                philosophers,
+            } => f.write_fmt(format_args!(
+                "fork {} lies between {} and {}, not two of the {} philosophers",
+                fork_id, a, b, philosophers
+            )),
+            ConfigError::ForkCountOutOfRange {
+                philosophers,
+                forks,
+            } => f.write_fmt(format_args!(
+                "{} philosophers need between {} and {} forks, got {}",
+                philosophers,
+                philosophers.div_ceil(2),
+                philosophers * philosophers.saturating_sub(1) / 2,
+                forks
+            )),
+            ConfigError::TooManyForks {
+                philosopher,
+                forks,
+                room,
+            } => f.write_fmt(format_args!(
+                "philosopher {} sits between {} forks but has room for {}",
+                philosopher, forks, room
+            )),
+            ConfigError::NameCountMismatch {
+                philosophers,
+                names,
+            } => f.write_fmt(format_args!(
+                "the table seats {} philosophers, got {} names",
+                philosophers, names
+            )),
+            ConfigError::InvalidCheckpoint(why) => {
+                f.write_fmt(format_args!("invalid checkpoint: {}", why))
+            }
+        }
+    }
+}
+
+/// time as the philosophers see it, turned into wall time by a `SimulationClock`
+pub type SimDuration = Duration;
+
+/// Runs the simulation faster or slower than real time, a multiplier of 0.5 halves every sleep.
+#[derive(Debug, Clone, Copy)]
+pub struct SimulationClock {
+    pub multiplier: f64,
+}
+
+impl SimulationClock {
+    pub fn new(multiplier: f64) -> Self {
+        Self { multiplier }
+    }
+
+    /// never sleep, for maximum throughput
+    pub fn fast_forward() -> Self {
+        Self::new(0.0)
+    }
+
+    /// real time a simulated duration takes
+    pub fn scaled(&self, duration: SimDuration) -> Duration {
+        duration.mul_f64(self.multiplier)
+    }
+
+    pub fn sleep(&self, duration: SimDuration) {
+        if self.multiplier > 0.0 {
+            thread::sleep(self.scaled(duration));
+        }
+    }
+}
+
+/// When a hungry philosopher asks for the forks it is missing
+#[derive(Debug, Clone, Copy)]
+pub enum ForkRequestStrategy {
+    /// right away
+    Immediate,
+    /// only once hungry for this long, a fork might come back without asking in the meantime
+    Deferred(SimDuration),
+    /// on every iteration with this probability
+    Probabilistic(f64),
+}
+
+/// Order the philosopher threads are spawned in, the first ones get a head start
+#[derive(Debug, Clone, Copy)]
+pub enum PhilosopherOrdering {
+    /// by id
+    Sequential,
+    /// highest id first
+    Reversed,
+    /// shuffled, the same seed gives the same order
+    Random(u64),
+    /// highest priority first, by id among the same priority
+    ByPriority,
+}
+
+impl PhilosopherOrdering {
+    pub fn arrange(self, philosophers: &mut [Philosopher]) {
+        match self {
+            PhilosopherOrdering::Sequential => {}
+            PhilosopherOrdering::Reversed => philosophers.reverse(),
+            PhilosopherOrdering::Random(seed) => {
+                let mut rng = Rng(seed.max(1));
+                for i in (1..philosophers.len()).rev() {
+                    let j = (rng.next_f64() * (i + 1) as f64) as usize;
+                    philosophers.swap(i, j);
+                }
+            }
+            PhilosopherOrdering::ByPriority => {
+                philosophers.sort_by_key(|p| std::cmp::Reverse(p.priority))
+            }
+        }
+    }
+}
+
+/// Small xorshift generator, good enough for a coin flip
+#[derive(Debug)]
+pub struct Rng(pub u64);
+
+impl Rng {
+    /// uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// uniform in 0..`n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+}
+
+/// At most one request per `min_interval` to the same neighbour, so a philosopher that keeps
+/// asking cannot flood the channel of its neighbour
+#[derive(Debug)]
+pub struct RateLimiter {
+    pub last_sent: Option<Instant>,
+    pub min_interval: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            last_sent: None,
+            min_interval,
+        }
+    }
+
+    /// whether a request may go out now, counted as sent if so
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .last_sent
+            .is_some_and(|t| now.duration_since(t) < self.min_interval)
+        {
+            return false;
+        }
+        self.last_sent = Some(now);
+        true
+    }
+}
+
+/// At most `ticks_per_second` iterations of a philosopher per second of wall time, so a tick can
+/// stand for a fixed amount of simulated time
+#[derive(Debug, Clone, Copy)]
+pub struct TickScheduler {
+    pub ticks_per_second: u64,
+    pub next_tick: Option<Instant>,
+}
+
+impl TickScheduler {
+    pub fn new(ticks_per_second: u64) -> Result<Self, ConfigError> {
+        if ticks_per_second == 0 {
+            return Err(ConfigError::NoTicks);
+        }
+        Ok(Self {
+            ticks_per_second,
+            next_tick: None,
+        })
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.ticks_per_second as f64)
+    }
+
+    /// Sleeps until a period after the last tick. Waits from the last tick rather than from now,
+    /// so the time an iteration takes does not slow the ticks down.
+    pub fn wait_for_next_tick(&mut self) {
+        let now = Instant::now();
+        let next = self.next_tick.map_or(now + self.period(), |t| t.max(now));
+        thread::sleep(next - now);
+        self.next_tick = Some(next + self.period());
+    }
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+pub struct SimulationConfig {
+    pub names: Vec<String>,
+    pub forks: usize,
+    pub eating_duration: SimDuration,
+    /// after every meal
+    pub thinking_duration: SimDuration,
+    pub clock: SimulationClock,
+    /// request all missing forks at once instead of one per iteration
+    pub batch_requests: bool,
+    pub request_strategy: ForkRequestStrategy,
+    /// hold back requests that would close a cycle of waiting philosophers
+    pub avoid_wait_cycles: bool,
+    pub channel_kind: ChannelKind,
+    /// longest a philosopher may keep a fork another one asked for
+    pub fork_lease: Option<Duration>,
+    /// shortest time between two requests to the same neighbour
+    pub request_rate_limit: Option<Duration>,
+    /// stack of each philosopher thread, the platform default when `None`
+    pub thread_stack_size: Option<usize>,
+    /// wall time until every philosopher leaves the table, for ever when `None`
+    pub run_for: Option<Duration>,
+    /// meals each philosopher eats, the table leaves once all of them are full
+    pub meals: Option<u64>,
+    /// where the philosophers record their events, see `with_event_sink`
+    pub event_log: Arc<EventLog>,
+    /// print every philosopher that starts or stops eating
+    pub print_states: bool,
+    pub spawn_order: PhilosopherOrdering,
+    /// iterations of each philosopher per second, as fast as it can when `None`
+    pub ticks_per_second: Option<u64>,
+}
+
+impl SimulationConfig {
+    /// A ring of `names` eating for a second in real time, with nothing else switched on and
+    /// staying at the table for ever
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            forks: names.len(),
+            names,
+            eating_duration: Duration::from_secs(1),
+            thinking_duration: Duration::ZERO,
+            clock: SimulationClock::default(),
+            batch_requests: false,
+            request_strategy: ForkRequestStrategy::Immediate,
+            avoid_wait_cycles: false,
+            channel_kind: ChannelKind::Unbounded,
+            fork_lease: None,
+            request_rate_limit: None,
+            thread_stack_size: None,
+            run_for: None,
+            meals: None,
+            event_log: Arc::default(),
+            print_states: false,
+            spawn_order: PhilosopherOrdering::Sequential,
+            ticks_per_second: None,
+        }
+    }
+
+    /// every table set from this configuration hands its events to `sink`
+    pub fn with_event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.event_log = Arc::new(EventLog::with_sink(sink));
+        self
+    }
+
+    /// report every violated constraint, not just the first one
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+        let philosophers = self.names.len();
+        if philosophers < 2 {
+            errors.push(ConfigError::TooFewPhilosophers(philosophers));
+        }
+        // one fork between each pair of neighbours
+        if self.forks != philosophers {
+            errors.push(ConfigError::ForkCountMismatch {
+                philosophers,
+                forks: self.forks,
+            });
+        }
+        let multiplier = self.clock.multiplier;
+        if !multiplier.is_finite() || multiplier < 0.0 {
+            errors.push(ConfigError::InvalidClockMultiplier(multiplier));
+        }
+        // zero is fine, for benchmarks
+        if !self.eating_duration.is_zero() && self.eating_duration < Duration::from_millis(1) {
+            errors.push(ConfigError::EatingTooShort(self.eating_duration));
+        }
+        if self.meals == Some(0) {
+            errors.push(ConfigError::NoMeals);
+        }
+        if let ChannelKind::Sync(0) = self.channel_kind {
+            errors.push(ConfigError::NoChannelCapacity);
+        }
+        if self.ticks_per_second == Some(0) {
+            errors.push(ConfigError::NoTicks);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Builds the philosophers for a seat each, so setting up the table does not depend on how they
+/// are configured
+pub trait PhilosopherFactory<const N: usize = 2> {
+    fn create(
+        &self,
+        id: PhilosopherId,
+        name: &str,
+        receiver: DepthTrackedReceiver,
+    ) -> Philosopher<N>;
+}
+
+/// philosophers behaving as configured
+impl<const N: usize> PhilosopherFactory<N> for SimulationConfig {
+    fn create(
+        &self,
+        id: PhilosopherId,
+        name: &str,
+        receiver: DepthTrackedReceiver,
+    ) -> Philosopher<N> {
+        let mut p =
+            Philosopher::new(id, name, receiver).with_fork_request_strategy(self.request_strategy);
+        p.eating_duration = self.eating_duration;
+        p.thinking_duration = self.thinking_duration;
+        p.clock = self.clock;
+        p.print_states = self.print_states;
+        p.batch_requests = self.batch_requests;
+        p.avoid_wait_cycles = self.avoid_wait_cycles;
+        p.fork_lease = self.fork_lease;
+        p.request_rate_limit = self.request_rate_limit;
+        // no ticks at all are reported by `validate`
+        p.tick_scheduler = self
+            .ticks_per_second
+            .and_then(|ticks| TickScheduler::new(ticks).ok());
+        p.meal_limit = self.meals;
+        p
+    }
+}
+
+/// called with the philosopher and the forks it eats with, an error ends the run of the philosopher
+pub type EatHook =
+    Arc<dyn Fn(PhilosopherId, &[ForkId]) -> Result<(), DiningPhilosophersError> + Send + Sync>;
+
+/// Run around every meal, see `Philosopher::with_before_eat` and `Philosopher::with_after_eat`
+#[derive(Clone, Default)]
+pub struct EatHooks {
+    pub before: Option<EatHook>,
+    pub after: Option<EatHook>,
+}
+
+impl std::fmt::Debug for EatHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "EatHooks {{ before: {}, after: {} }}",
+            self.before.is_some(),
+            self.after.is_some()
+        ))
+    }
+}
+
+/// What a philosopher starts out with, everything of it that can be cloned. See
+/// `Philosopher::clone_initial_state`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhilosopherInitialState {
+    pub id: PhilosopherId,
+    pub name: String,
+    /// every fork next to the philosopher, with its state if the philosopher holds it
+    pub forks_initial: BTreeMap<ForkId, Option<ForkState>>,
+}
+
+/// A philosopher taken apart between two of its steps, to carry on in another thread. See
+/// `Philosopher::into_parts`.
+#[derive(Debug)]
+pub struct PhilosopherMigration<const N: usize> {
+    pub state: PhilosopherState,
+    pub forks: Vec<(ForkId, ForkStorage)>,
+    pub receiver: DepthTrackedReceiver,
+    /// everything else, without forks and with a channel nobody sends to
+    pub seat: Philosopher<N>,
+}
+
+/// Sits between `N` forks, two at a round table
+#[derive(Debug)]
+pub struct Philosopher<const N: usize = 2> {
+    pub id: PhilosopherId,
+    pub name: String,
+    pub receiver: DepthTrackedReceiver,
+    pub neighbours: HashMap<PhilosopherId, Outbox>,
+    /// the neighbour on the other side of each fork, two forks can lie between the same two
+    pub fork_neighbours: BTreeMap<ForkId, PhilosopherId>,
+    pub forks: ForkMap<N>,
+    /// we eat once all of these are in storage
+    pub needs: ForkSet,
+    pub request_queue: VecDeque<ForkRequest>,
+    pub registry: Arc<PhilosopherRegistry>,
+    pub state: Arc<Mutex<PhilosopherState>>,
+    pub eating_duration: SimDuration,
+    /// after every meal, answering the neighbours meanwhile
+    pub thinking_duration: SimDuration,
+    /// done thinking once this time has come
+    pub thinking_until: Option<Instant>,
+    pub clock: SimulationClock,
+    /// a line on every meal, see `print_state`
+    pub print_states: bool,
+    pub batch_requests: bool,
+    pub request_strategy: ForkRequestStrategy,
+    pub rng: Rng,
+    pub avoid_wait_cycles: bool,
+    /// a request was held back, do not wait for messages before trying again
+    pub requests_held_back: bool,
+    /// deliveries taken out of the channel while looking for requests, handled first
+    pub early_messages: VecDeque<ForkMessage>,
+    /// a request was taken out of the channel early, it would have woken us otherwise
+    pub requests_taken_early: bool,
+    pub fork_lease: Option<Duration>,
+    pub request_rate_limit: Option<Duration>,
+    /// one per neighbour asked since `request_rate_limit` was set
+    pub request_limiters: HashMap<PhilosopherId, RateLimiter>,
+    /// paces `run`, see `TickScheduler`
+    pub tick_scheduler: Option<TickScheduler>,
+    pub iterations: u64,
+    /// Lamport clock, ticks on every send and jumps ahead of every received message
+    pub lamport: Cell<LamportTime>,
+    /// ticks together with `lamport`, merged with the clock of every received message
+    pub vector_clock: RefCell<VectorClock>,
+    pub meals: u64,
+    pub hungry_since: Option<Instant>,
+    /// time waited for each of the last `HISTORY_LEN` meals
+    pub eating_history: VecDeque<Duration>,
+    /// forks in storage, shared with the `SimulationObserver`
+    pub held_forks: Arc<AtomicUsize>,
+    pub events: Arc<EventLog>,
+    pub accounting: Arc<ForkAccounting>,
+    pub wait_for: Arc<WaitForGraph>,
+    /// where the forks go when we are dropped holding them
+    pub fork_pool: ForkPool,
+    /// the `StatsAggregator`, we report to the neighbours after every meal and pass on theirs
+    pub stats: Option<mpsc::Sender<StatsReport>>,
+    /// takes over a successor when the thread panics
+    pub supervisor: Option<mpsc::Sender<Philosopher<N>>>,
+    /// panic on purpose after this many meals, to see the supervisor at work
+    pub crash_after_meals: Option<u64>,
+    /// move to another thread after this many meals, once thinking
+    pub migrate_after_meals: Option<u64>,
+    /// leave the table once this time has come, see `run_for`
+    pub stop_at: Option<Instant>,
+    /// stop eating after this many meals, and leave once everyone did
+    pub meal_limit: Option<u64>,
+    pub priority: Priority,
+    /// published after every step, shared with the `SimulationObserver`
+    pub snapshot: Arc<Mutex<PhilosopherSnapshot>>,
+    pub hooks: EatHooks,
+    /// kept up to date for `statistics_snapshot`
+    pub counters: Arc<PhilosopherCounters>,
+}
+
+impl<const N: usize> Philosopher<N> {
+    pub fn new(id: PhilosopherId, name: &str, receiver: DepthTrackedReceiver) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            receiver,
+            neighbours: HashMap::new(),
+            fork_neighbours: BTreeMap::new(),
+            forks: ForkMap::default(),
+            needs: ForkSet::default(),
+            request_queue: VecDeque::new(),
+            registry: Arc::default(),
+            state: Arc::new(Mutex::new(PhilosopherState::Thinking)),
+            eating_duration: Duration::from_secs(1),
+            thinking_duration: Duration::ZERO,
+            thinking_until: None,
+            clock: SimulationClock::default(),
+            print_states: false,
+            batch_requests: false,
+            request_strategy: ForkRequestStrategy::Immediate,
+            rng: Rng(id.index() as u64 + 1),
+            avoid_wait_cycles: false,
+            requests_held_back: false,
+            early_messages: VecDeque::new(),
+            requests_taken_early: false,
+            fork_lease: None,
+            request_rate_limit: None,
+            tick_scheduler: None,
+            request_limiters: HashMap::new(),
+            iterations: 0,
+            lamport: Cell::new(0),
+            vector_clock: RefCell::default(),
+            meals: 0,
+            hungry_since: None,
+            eating_history: VecDeque::with_capacity(HISTORY_LEN),
+            held_forks: Arc::new(AtomicUsize::new(0)),
+            events: Arc::default(),
+            accounting: Arc::default(),
+            wait_for: Arc::default(),
+            fork_pool: ForkPool::default(),
+            stats: None,
+            supervisor: None,
+            crash_after_meals: None,
+            migrate_after_meals: None,
+            stop_at: None,
+            meal_limit: None,
+            priority: 0,
+            snapshot: Arc::new(Mutex::new(PhilosopherSnapshot {
+                id,
+                name: name.to_string(),
+                state: PhilosopherState::Thinking,
+                held_forks: vec![],
+                pending_requests: vec![],
+                meals_eaten: 0,
+                queued_messages: 0,
+                hungry_since: None,
+            })),
+            hooks: EatHooks::default(),
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn with_fork_request_strategy(mut self, strategy: ForkRequestStrategy) -> Self {
+        self.request_strategy = strategy;
+        self
+    }
+
+    /// `f` runs right before every meal
+    pub fn with_before_eat(
+        mut self,
+        f: impl Fn(PhilosopherId, &[ForkId]) -> Result<(), DiningPhilosophersError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.hooks.before = Some(Arc::new(f));
+        self
+    }
+
+    /// `f` runs right after every meal, while the forks are still ours
+    pub fn with_after_eat(
+        mut self,
+        f: impl Fn(PhilosopherId, &[ForkId]) -> Result<(), DiningPhilosophersError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.hooks.after = Some(Arc::new(f));
+        self
+    }
+
+    pub fn setup_registry(&mut self, registry: Arc<PhilosopherRegistry>) {
+        self.state = registry.state_of(self.id);
+        self.registry = registry;
+    }
+
+    pub fn setup_event_log(&mut self, events: Arc<EventLog>) {
+        self.events = events;
+    }
+
+    pub fn setup_accounting(&mut self, accounting: Arc<ForkAccounting>) {
+        self.accounting = accounting;
+    }
+
+    pub fn setup_wait_for_graph(&mut self, wait_for: Arc<WaitForGraph>) {
+        self.wait_for = wait_for;
+    }
+
+    pub fn setup_fork_pool(&mut self, fork_pool: ForkPool) {
+        self.fork_pool = fork_pool;
+    }
+
+    pub fn setup_stats(&mut self, stats: mpsc::Sender<StatsReport>) {
+        self.stats = Some(stats);
+    }
+
+    pub fn setup_supervisor(&mut self, supervisor: mpsc::Sender<Philosopher<N>>) {
+        self.supervisor = Some(supervisor);
+    }
+
+    /// To start again from where this philosopher is now, `Philosopher::from_initial_state`
+    /// turns it back into a philosopher. The channel cannot be cloned and is left out.
+    pub fn clone_initial_state(&self) -> PhilosopherInitialState {
+        PhilosopherInitialState {
+            id: self.id,
+            name: self.name.clone(),
+            forks_initial: self
+                .forks
+                .iter()
+                .map(|(fid, storage)| (*fid, storage.fork_state()))
+                .collect(),
+        }
+    }
+
+    pub fn from_initial_state(
+        state: PhilosopherInitialState,
+        receiver: DepthTrackedReceiver,
+    ) -> Result<Self, DiningPhilosophersError> {
+        let mut p = Self::new(state.id, &state.name, receiver);
+        p.needs = state.forks_initial.keys().copied().collect();
+        for (fid, fork_state) in state.forks_initial {
+            let fork = fork_state.map(|fork_state| {
+                let mut fork = Fork::new_dirty(fid);
+                if fork_state == ForkState::Clean {
+                    fork.clean();
+                }
+                fork
+            });
+            p.setup_fork(fid, fork)?;
+        }
+        Ok(p)
+    }
+
+    /// Takes out the forks, with requests and lease, and the channel, so the philosopher can be
+    /// put together again in another thread by `Philosopher::from_parts`. Only while thinking, a
+    /// philosopher in the middle of getting its forks has to keep its channel to itself.
+    pub fn into_parts(mut self) -> Result<PhilosopherMigration<N>, DiningPhilosophersError> {
+        let state = *self.state.lock()?;
+        if state != PhilosopherState::Thinking {
+            return Err(DiningPhilosophersError::MovedWhileBusy { id: self.id, state });
+        }
+        let forks = self
+            .forks
+            .iter_mut()
+            .map(|(fid, storage)| (*fid, std::mem::replace(storage, ForkStorage::new(None))))
+            .collect();
+        let receiver =
+            std::mem::replace(&mut self.receiver, fork_channel(ChannelKind::Unbounded).1);
+        Ok(PhilosopherMigration {
+            state,
+            forks,
+            receiver,
+            seat: self,
+        })
+    }
+
+    pub fn from_parts(parts: PhilosopherMigration<N>) -> Result<Self, DiningPhilosophersError> {
+        let PhilosopherMigration {
+            state,
+            forks,
+            receiver,
+            mut seat,
+        } = parts;
+        seat.receiver = receiver;
+        for (fid, storage) in forks {
+            seat.forks.insert(fid, storage);
+        }
+        seat.set_state(state)?;
+        seat.publish_snapshot()?;
+        Ok(seat)
+    }
+
+    /// time to move to another thread, see `migrate_after_meals`
+    pub fn wants_to_migrate(&self) -> bool {
+        self.migrate_after_meals == Some(self.meals)
+            && self
+                .state
+                .lock()
+                .is_ok_and(|state| *state == PhilosopherState::Thinking)
+    }
+
+    /// A fresh philosopher for our seat. Keeps the channels, the forks we hold and the requests
+    /// we still have to answer, neighbours would wait for them forever otherwise.
+    pub fn successor(&mut self) -> Result<Self, DiningPhilosophersError> {
+        let receiver =
+            std::mem::replace(&mut self.receiver, fork_channel(ChannelKind::Unbounded).1);
+        let mut p = Self::new(self.id, &self.name, receiver);
+        p.neighbours = std::mem::take(&mut self.neighbours);
+        p.fork_neighbours = std::mem::take(&mut self.fork_neighbours);
+        p.request_queue = std::mem::take(&mut self.request_queue);
+        p.early_messages = std::mem::take(&mut self.early_messages);
+        p.requests_taken_early = self.requests_taken_early;
+        p.needs = self.needs.clone();
+        p.setup_registry(self.registry.clone());
+        p.set_state(PhilosopherState::Thinking)?;
+        p.setup_event_log(self.events.clone());
+        p.setup_accounting(self.accounting.clone());
+        p.setup_wait_for_graph(self.wait_for.clone());
+        p.setup_fork_pool(self.fork_pool.clone());
+        p.stats = self.stats.clone();
+        p.supervisor = self.supervisor.clone();
+        p.eating_duration = self.eating_duration;
+        p.thinking_duration = self.thinking_duration;
+        p.clock = self.clock;
+        p.print_states = self.print_states;
+        p.batch_requests = self.batch_requests;
+        p.request_strategy = self.request_strategy;
+        p.avoid_wait_cycles = self.avoid_wait_cycles;
+        p.fork_lease = self.fork_lease;
+        p.request_rate_limit = self.request_rate_limit;
+        p.tick_scheduler = self.tick_scheduler;
+        p.priority = self.priority;
+        p.stop_at = self.stop_at;
+        p.meal_limit = self.meal_limit;
+        p.held_forks = self.held_forks.clone();
+        p.snapshot = self.snapshot.clone();
+        p.hooks = self.hooks.clone();
+        p.counters = self.counters.clone();
+        // the clocks must not go back, later events would seem to happen before earlier ones
+        p.lamport = self.lamport.clone();
+        p.vector_clock = self.vector_clock.clone();
+        for (fid, storage) in self.forks.iter_mut() {
+            p.setup_fork(*fid, storage.take())?;
+        }
+        let pending: Vec<ForkId> = p.request_queue.iter().map(|r| r.fork_id).collect();
+        for fid in pending {
+            if p.forks[fid].is_some() {
+                p.forks[fid]
+                    .set_request_pending(true)
+                    .map_err(|e| e.of(p.id, fid))?;
+            }
+        }
+        Ok(p)
+    }
+
+    pub fn set_state(&self, state: PhilosopherState) -> Result<(), DiningPhilosophersError> {
+        *self.state.lock()? = state;
+        Ok(())
+    }
+
+    /// `neighbour` shares `fork_id` with us and gets its messages through `sender`
+    pub fn setup_neighbour(
+        &mut self,
+        fork_id: ForkId,
+        neighbour: PhilosopherId,
+        sender: ForkSender,
+    ) {
+        self.fork_neighbours.insert(fork_id, neighbour);
+        self.neighbours
+            .entry(neighbour)
+            .or_insert_with(|| Outbox::new(sender));
+    }
+
+    /// one of the `N` forks next to us, panics when someone gives us one more
+    pub fn setup_fork(
+        &mut self,
+        neighbour: usize,
+        fork: Option<Fork>,
+    ) -> Result<(), DiningPhilosophersError> {
+        let mut storage = ForkStorage::new(fork);
+        if let (true, Some(lease)) = (storage.is_some(), self.fork_lease) {
+            storage.lease(lease);
+        }
+        self.forks.insert(neighbour, storage);
+        let held = self.forks.values().filter(|f| f.is_some()).count();
+        self.held_forks.store(held, Ordering::Relaxed);
+        self.publish_snapshot()
+    }
+
+    /// Let the observer see where we are. Called while holding `pause` for reading, so a
+    /// snapshot taken with `pause` held for writing shows every philosopher between two steps.
+    pub fn publish_snapshot(&self) -> Result<(), DiningPhilosophersError> {
+        let mut snapshot = self.snapshot.lock()?;
+        snapshot.state = *self.state.lock()?;
+        snapshot.held_forks = self
+            .forks
+            .iter()
+            .filter(|(_, storage)| storage.is_some())
+            .map(|(fid, _)| *fid)
+            .collect();
+        snapshot.pending_requests = self.request_queue.iter().cloned().collect();
+        snapshot.meals_eaten = self.meals;
+        snapshot.queued_messages = self.message_queue_depth();
+        snapshot.hungry_since = self.hungry_since;
+        self.counters
+            .pending_requests
+            .store(self.request_queue.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn statistics_snapshot(&self) -> PhilosopherStats {
+        PhilosopherStats::read(&self.counters, &self.held_forks, &self.state)
+    }
+
+    /// Tell the neighbours our meals and time waited so far, for the `StatsAggregator`. Goes
+    /// straight into their channels, past the outboxes: a full channel drops the report, the
+    /// next one says the same and more.
+    pub fn report_stats(&self) {
+        for outbox in self.neighbours.values() {
+            let _ = outbox.channel.send(ForkMessage::Stats {
+                philosopher_id: self.id,
+                meals: self.meals,
+                wait_ns: self.counters.total_wait_ns.load(Ordering::Relaxed),
+            });
+        }
+    }
+
+    /// the stats handler, forks are none of its business
+    pub fn pass_on_stats(&self, report: StatsReport) {
+        if let Some(stats) = &self.stats {
+            // the aggregator is gone once main returned
+            let _ = stats.send(report);
+        }
+    }
+
+    /// messages from the neighbours not handled yet
+    pub fn message_queue_depth(&self) -> usize {
+        self.receiver.depth()
+    }
+
+    /// nothing sent to us is left to handle and nothing we sent waits in an outbox
+    pub fn is_at_rest(&self) -> bool {
+        self.message_queue_depth() == 0
+            && self.early_messages.is_empty()
+            && self.neighbours.values().all(|o| o.is_empty())
+    }
+
+    pub fn local_clock(&self) -> LamportTime {
+        self.lamport.get()
+    }
+
+    pub fn vector_time(&self) -> VectorClock {
+        self.vector_clock.borrow().clone()
+    }
+
+    /// tick the clocks for a message about to be sent
+    pub fn next_timestamp(&self) -> LamportTime {
+        self.vector_clock.borrow_mut().tick(self.id);
+        self.lamport.set(self.lamport.get() + 1);
+        self.lamport.get()
+    }
+
+    pub fn observe_timestamp(&self, sent_at: LamportTime, vector: &VectorClock) {
+        let mut own = self.vector_clock.borrow_mut();
+        own.merge(vector);
+        own.tick(self.id);
+        self.lamport.set(self.lamport.get().max(sent_at) + 1);
+    }
+
+    pub fn debug_assert_forks_valid(&self) {
+        for (fid, storage) in self.forks.iter() {
+            storage.debug_assert_valid();
+            if cfg!(debug_assertions) {
+                // once, a lease timer may send the fork any time
+                let held = storage.is_some();
+                assert_eq!(
+                    held && storage.request_pending,
+                    held && self.has_request_for(*fid),
+                    "requests for fork {} and its storage disagree",
+                    fid
+                );
+            }
+        }
+    }
+
+    /// whether a neighbour waits for `fork_id`
+    pub fn has_request_for(&self, fork_id: ForkId) -> bool {
+        self.request_queue.iter().any(|r| r.fork_id == fork_id)
+    }
+
+    pub fn held_fork_count(&self) -> usize {
+        self.held_forks.load(Ordering::Relaxed)
+    }
+
+    pub fn pending_request_count(&self) -> usize {
+        self.request_queue.len()
+    }
+
+    pub fn has_pending_for(&self, requester: PhilosopherId, fork_id: ForkId) -> bool {
+        self.request_queue
+            .iter()
+            .any(|r| r.requester == requester && r.fork_id == fork_id)
+    }
+
+    /// Hand a fork over without being asked and no matter whether it is clean, to set up a
+    /// table in a given state or to inject faults. Not part of the algorithm. The receiver
+    /// must not ask for the fork before it arrives, a delivery cannot happen before the
+    /// request it answers.
+    #[cfg(test)]
+    pub fn force_give_fork(
+        &mut self,
+        fork_id: ForkId,
+        to: PhilosopherId,
+    ) -> Result<(), DiningPhilosophersError> {
+        assert!(
+            self.forks[fork_id].is_some(),
+            "fork {} is not here",
+            fork_id
+        );
+        assert_eq!(
+            self.neighbour_sharing(fork_id).ok(),
+            Some(to),
+            "{} does not share it",
+            to
+        );
+        // whoever asked for it gets it anyway
+        self.drain_request_queue_for_fork(fork_id);
+        self.send_fork(fork_id, to, DeliveryReason::Forced)
+    }
+
+    /// clean the fork and hand it to the neighbour
+    pub fn send_fork(
+        &mut self,
+        fork_id: ForkId,
+        to: PhilosopherId,
+        reason: DeliveryReason,
+    ) -> Result<(), DiningPhilosophersError> {
+        let leased = matches!(self.forks[fork_id].fork, ForkPresence::Leased(_));
+        let fork = match self.forks[fork_id].take() {
+            Some(fork) => fork,
+            // the lease timer was quicker and sent it to the neighbour sharing it already
+            None if leased && self.forks[fork_id].is_in_flight() => {
+                self.request_queue.retain(|r| r.fork_id != fork_id);
+                return Ok(());
+            }
+            None => {
+                return Err(DiningPhilosophersError::ForkNotHeld {
+                    id: self.id,
+                    fork_id,
+                })
+            }
+        };
+        self.held_forks.fetch_sub(1, Ordering::Relaxed);
+        self.accounting.in_flight.fetch_add(1, Ordering::Relaxed);
+        fork.transfer_to(to)
+            .sent_by(self.id)
+            .because(reason)
+            .cleaned()
+            .logged(self.events.clone())
+            .stamped(self.next_timestamp(), self.vector_time())
+            .send(
+                self.neighbours
+                    .get_mut(&to)
+                    .ok_or(DiningPhilosophersError::ChannelClosed(to))?,
+            )
+    }
+
+    pub fn send(
+        &mut self,
+        to: PhilosopherId,
+        msg: ForkMessage,
+    ) -> Result<(), DiningPhilosophersError> {
+        self.neighbours
+            .get_mut(&to)
+            .ok_or(DiningPhilosophersError::ChannelClosed(to))?
+            .send(msg)
+            .map_err(|_| DiningPhilosophersError::ChannelClosed(to))
+    }
+
+    /// send what did not fit into the channels of the neighbours before
+    pub fn flush_outboxes(&mut self) -> Result<(), DiningPhilosophersError> {
+        for (id, outbox) in self.neighbours.iter_mut() {
+            outbox
+                .flush()
+                .map_err(|_| DiningPhilosophersError::ChannelClosed(*id))?;
+        }
+        Ok(())
+    }
+
+    pub fn record_wait(&mut self, wait: Duration) {
+        if self.eating_history.len() == HISTORY_LEN {
+            self.eating_history.pop_front();
+        }
+        self.eating_history.push_back(wait);
+    }
+
+    /// mean wait for a meal over the last `HISTORY_LEN` meals
+    pub fn recent_mean_wait(&self) -> Duration {
+        if self.eating_history.is_empty() {
+            return Duration::ZERO;
+        }
+        self.eating_history.iter().sum::<Duration>() / self.eating_history.len() as u32
+    }
+
+    /// One line with id and name colored by state, same colors as `to_dot`, then the forks and
+    /// who asked for them. Plain text when stdout is not a terminal.
+    pub fn print_state(&self) -> Result<(), DiningPhilosophersError> {
+        println!("{}", self.state_line(std::io::stdout().is_terminal())?);
+        Ok(())
+    }
+
+    /// the line `print_state` prints, `colored` or not
+    pub fn state_line(&self, colored: bool) -> Result<String, DiningPhilosophersError> {
+        let state = *self.state.lock()?;
+        let (color, doing, detail) = match state {
+            PhilosopherState::Eating => (
+                "32",
+                "is eating.",
+                format!(
+                    "({} eating, {} hungry)",
+                    self.registry.eating_count(),
+                    self.registry.hungry_count()
+                ),
+            ),
+            PhilosopherState::Hungry => ("33", "is hungry.", String::new()),
+            PhilosopherState::Thinking => (
+                "90",
+                "is done eating.",
+                format!(
+                    "(recent avg wait {:?}, longest {:?})",
+                    self.recent_mean_wait(),
+                    Duration::from_nanos(self.statistics_snapshot().max_starvation_ns)
+                ),
+            ),
+        };
+        let who = format!("{} {}", self.id, self.name);
+        let who = if colored {
+            format!("\x1b[{}m{}\x1b[0m", color, who)
+        } else {
+            who
+        };
+        let requests: Vec<_> = self
+            .request_queue
+            .iter()
+            .map(|r| format!("{} wants {}", r.requester, r.fork_id))
+            .collect();
+        Ok(format!(
+            "{} {} {:?} [{}] {}",
+            who,
+            doing,
+            self.forks,
+            requests.join(", "),
+            detail
+        ))
+    }
+
+    /// The meal once every fork is here: acquire, use_resources and release of the
+    /// `PhilosopherProtocol`, counted as a single step by the other philosophers
+    pub fn eat(&mut self) -> Result<(), DiningPhilosophersError> {
+        let kept = self.keep_forks();
+        debug_assert!(kept, "{} eats without its forks", self.name);
+        let accounting = self.accounting.clone();
+        let _counting = accounting.pause.read()?;
+        self.debug_assert_forks_valid();
+        self.acquire()?;
+        self.use_resources()?;
+        self.release()?;
+        self.debug_assert_forks_valid();
+        self.publish_snapshot()
+    }
+
+    /// get hungry and ask for the missing forks, see `PhilosopherProtocol::request`
+    pub fn ask_for_forks(&mut self) -> Result<(), DiningPhilosophersError> {
+        let accounting = self.accounting.clone();
+        let _counting = accounting.pause.read()?;
+        PhilosopherProtocol::request(self)?;
+        self.publish_snapshot()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.meal_limit.is_some_and(|limit| self.meals >= limit)
+    }
+
+    /// the `thinking_duration` after the last meal has passed
+    pub fn is_done_thinking(&self) -> bool {
+        self.thinking_until.is_none_or(|t| Instant::now() >= t)
+    }
+
+    /// Whether asking for `fork_id` would let the philosophers wait for each other in a circle.
+    /// Chandy/Misra gets out of such a circle by itself, dirty forks are given up on request,
+    /// but holding back the request keeps the messages from going round in the first place.
+    pub fn would_deadlock_if_requesting(
+        &self,
+        fork_id: ForkId,
+    ) -> Result<bool, DiningPhilosophersError> {
+        let holder = self.neighbour_sharing(fork_id)?;
+        self.wait_for.would_close_cycle(self.id, holder)
+    }
+
+    /// cannot eat, request missing resources at neighbours, one per call unless batching
+    pub fn send_all_pending_requests(&mut self) -> Result<(), DiningPhilosophersError> {
+        if self.batch_requests {
+            self.request_all_forks()
+        } else {
+            self.request_forks(1)
+        }
+    }
+
+    /// ask for every missing fork at once, saves a round of `eat` and `handle_requests` for
+    /// each fork but the first
+    pub fn request_all_forks(&mut self) -> Result<(), DiningPhilosophersError> {
+        self.request_forks(self.forks.len())
+    }
+
+    /// ask for at most `limit` of the missing forks, as the request strategy allows
+    pub fn request_forks(&mut self, limit: usize) -> Result<(), DiningPhilosophersError> {
+        let ask_now = match self.request_strategy {
+            ForkRequestStrategy::Immediate => true,
+            ForkRequestStrategy::Deferred(delay) => self
+                .hungry_since
+                .is_none_or(|t| t.elapsed() >= self.clock.scaled(delay)),
+            ForkRequestStrategy::Probabilistic(p) => self.rng.next_f64() < p,
+        };
+        if !ask_now {
+            self.requests_held_back = true;
+            return Ok(());
+        }
+        let (mut held_back, mut missing) = (vec![], vec![]);
+        for fid in self.needs.missing_from(&self.forks.held()).iter().copied() {
+            if !self.forks[fid].needs_requesting() {
+                continue;
+            }
+            if self.avoid_wait_cycles && self.would_deadlock_if_requesting(fid)? {
+                held_back.push(fid);
+            } else {
+                missing.push(fid);
+            }
+        }
+        // asked again after a back off
+        self.requests_held_back = !held_back.is_empty();
+        for fid in missing.into_iter().take(limit) {
+            let pid = self.neighbour_sharing(fid)?;
+            if let Some(interval) = self.request_rate_limit {
+                let limiter = self
+                    .request_limiters
+                    .entry(pid)
+                    .or_insert_with(|| RateLimiter::new(interval));
+                if !limiter.try_acquire() {
+                    // asked this neighbour too recently, try again later
+                    self.requests_held_back = true;
+                    continue;
+                }
+            }
+            self.wait_for.wait(self.id, pid)?;
+            let sent_at = self.next_timestamp();
+            let vector = self.vector_time();
+            self.events.record(
+                sent_at,
+                vector.clone(),
+                SimulationEvent::ForkRequested {
+                    from: self.id,
+                    to: pid,
+                    fork_id: fid,
+                },
+            )?;
+            let msg = if self.priority > 0 {
+                ForkMessage::RequestWithPriority(self.id, fid, sent_at, vector, self.priority)
+            } else {
+                ForkMessage::Request(self.id, fid, sent_at, vector)
+            };
+            self.send(pid, msg)?;
+            self.forks[fid].requested(sent_at);
+        }
+        Ok(())
+    }
+
+    pub fn is_neighbor(&self, other: PhilosopherId) -> bool {
+        self.neighbours.contains_key(&other)
+    }
+
+    /// philosophers we share a fork with
+    pub fn neighbors(&self) -> impl Iterator<Item = PhilosopherId> + '_ {
+        self.neighbours.keys().copied()
+    }
+
+    /// every fork with the neighbour on the other side of it, by fork id
+    pub fn shared_forks(&self) -> impl Iterator<Item = (ForkId, PhilosopherId)> + '_ {
+        self.fork_neighbours.iter().map(|(fid, n)| (*fid, *n))
+    }
+
+    /// The fork shared with `neighbor`, `None` if not seated next to us. With just the two of
+    /// us at the table we share two forks, this is the one with the lower id.
+    pub fn fork_for_neighbor(&self, neighbor: PhilosopherId) -> Option<ForkId> {
+        self.shared_forks()
+            .find(|(_, n)| *n == neighbor)
+            .map(|(fid, _)| fid)
+    }
+
+    /// the neighbour on the other side of `fork_id`
+    pub fn neighbour_sharing(
+        &self,
+        fork_id: ForkId,
+    ) -> Result<PhilosopherId, DiningPhilosophersError> {
+        self.fork_neighbours
+            .get(&fork_id)
+            .copied()
+            .ok_or(DiningPhilosophersError::UnsharedFork {
+                id: self.id,
+                fork_id,
+            })
+    }
+
+    /// Sends dirty fork `fork_id` right away if a neighbour asked for it, including requests still
+    /// waiting in the channel, instead of leaving them for `handle_requests`. Requests behind a
+    /// message left for later wait for it, a `Received` drops the requests sent before it.
+    pub fn send_fork_if_requested(
+        &mut self,
+        fork_id: ForkId,
+    ) -> Result<bool, DiningPhilosophersError> {
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                ForkMessage::Request(by, fid, sent_at, vector)
+                    if self.early_messages.is_empty() =>
+                {
+                    self.receive_request(by, fid, sent_at, &vector, 0)?
+                }
+                ForkMessage::RequestWithPriority(by, fid, sent_at, vector, priority)
+                    if self.early_messages.is_empty() =>
+                {
+                    self.receive_request(by, fid, sent_at, &vector, priority)?
+                }
+                other => self.early_messages.push_back(other),
+            }
+            self.requests_taken_early = true;
+        }
+        if !self.forks[fork_id].is_dirty() || !self.forks[fork_id].take_request_pending() {
+            return Ok(false);
+        }
+        let request = match self
+            .drain_request_queue_for_fork(fork_id)
+            .into_iter()
+            .next()
+        {
+            Some(request) => request,
+            None => return Ok(false),
+        };
+        self.send_fork(fork_id, request.requester, DeliveryReason::Requested)?;
+        self.events.record(
+            self.local_clock(),
+            self.vector_time(),
+            SimulationEvent::ForkRequestServed {
+                requester: request.requester,
+                fork_id,
+                waited: request.requested_at.elapsed(),
+            },
+        )?;
+        Ok(true)
+    }
+
+    pub fn receive_request(
+        &mut self,
+        by: PhilosopherId,
+        fork_id: ForkId,
+        sent_at: LamportTime,
+        vector: &VectorClock,
+        priority: Priority,
+    ) -> Result<(), DiningPhilosophersError> {
+        self.observe_timestamp(sent_at, vector);
+        self.queue_request(by, fork_id, priority)
+    }
+
+    /// go over messages, store or hand out forks
+    pub fn handle_requests(&mut self) -> Result<(), DiningPhilosophersError> {
+        if let Some(msg) = self.early_messages.pop_front() {
+            return self.handle_message(msg);
+        }
+        if std::mem::take(&mut self.requests_taken_early) {
+            return Ok(());
+        }
+        let wait = if self.is_full() {
+            // look again whether everyone else is full too
+            Some(OUTBOX_BACKOFF)
+        } else if self.neighbours.values().all(|o| o.is_empty()) && !self.requests_held_back {
+            // no longer than until it is time to leave
+            self.stop_at
+                .map(|t| t.saturating_duration_since(Instant::now()))
+        } else {
+            // back off, then try to get rid of the messages waiting in the outboxes or held back
+            // requests again
+            Some(OUTBOX_BACKOFF)
+        };
+        // wake up in time to return a fork whose lease runs out, or to get hungry again
+        let thinking = self
+            .thinking_until
+            .and_then(|t| t.checked_duration_since(Instant::now()));
+        let wake_up = self
+            .forks
+            .iter()
+            .filter_map(|(_, storage)| storage.lease_remaining())
+            .chain(thinking)
+            .min();
+        let wait = match (wait, wake_up) {
+            (Some(wait), Some(wake_up)) => Some(wait.min(wake_up)),
+            (wait, wake_up) => wait.or(wake_up),
+        };
+        let msg = match wait {
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| DiningPhilosophersError::ChannelClosed(self.id))?,
+            Some(wait) => match self.receiver.recv_timeout(wait) {
+                Ok(msg) => msg,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(DiningPhilosophersError::ChannelClosed(self.id))
+                }
+            },
+        };
+        self.handle_message(msg)
+    }
+
+    pub fn handle_message(&mut self, msg: ForkMessage) -> Result<(), DiningPhilosophersError> {
+        let accounting = self.accounting.clone();
+        let _counting = accounting.pause.read()?;
+        self.debug_assert_forks_valid();
+        let request = match msg {
+            ForkMessage::Request(by, fork_id, sent_at, vector) => {
+                self.receive_request(by, fork_id, sent_at, &vector, 0)?;
+                Some((by, fork_id))
+            }
+            ForkMessage::RequestWithPriority(by, fork_id, sent_at, vector, priority) => {
+                self.receive_request(by, fork_id, sent_at, &vector, priority)?;
+                Some((by, fork_id))
+            }
+            ForkMessage::Delivery(fork, reason, sent_at, vector) => {
+                let storage_id = fork.id();
+                // a requested delivery answers our request, so it cannot have happened before it
+                if let (DeliveryReason::Requested, Some(asked)) =
+                    (reason, self.forks[storage_id].requested_at())
+                {
+                    if asked >= sent_at {
+                        return Err(DiningPhilosophersError::DeliveredBeforeRequested {
+                            id: self.id,
+                            fork_id: storage_id,
+                        });
+                    }
+                }
+                self.observe_timestamp(sent_at, &vector);
+                let from = self.neighbour_sharing(storage_id)?;
+                if reason == DeliveryReason::Departure {
+                    // nobody left to give forks to
+                    self.request_queue.retain(|r| r.requester != from);
+                }
+                self.wait_for.done_waiting(self.id, from)?;
+                self.events.record(
+                    self.local_clock(),
+                    self.vector_time(),
+                    SimulationEvent::ForkDelivered {
+                        from,
+                        to: self.id,
+                        fork_id: storage_id,
+                        sent_at,
+                    },
+                )?;
+                self.held_forks.fetch_add(1, Ordering::Relaxed);
+                self.accounting.in_flight.fetch_sub(1, Ordering::Relaxed);
+                // before anything we send back, so the sender learns of it first
+                if reason != DeliveryReason::Departure {
+                    let msg = ForkMessage::Received(
+                        storage_id,
+                        self.next_timestamp(),
+                        self.vector_time(),
+                    );
+                    self.send(from, msg)?;
+                }
+                let mut storage = match self.fork_lease {
+                    // nobody to return the fork to once the neighbour left
+                    Some(lease) if reason != DeliveryReason::Departure => {
+                        ForkStorage::with_timeout_sender(fork, self.lease_return(from)?, lease)
+                    }
+                    _ => ForkStorage::new(Some(fork)),
+                };
+                // asked for while on its way to us
+                storage
+                    .set_request_pending(self.has_request_for(storage_id))
+                    .map_err(|e| e.of(self.id, storage_id))?;
+                self.forks.insert(storage_id, storage);
+                None
+            }
+            ForkMessage::Received(fork_id, sent_at, vector) => {
+                self.observe_timestamp(sent_at, &vector);
+                self.stop_lease_timer(fork_id);
+                self.forks[fork_id].arrived();
+                // asked for while on its way to the neighbour, who has it now and asks again
+                // once it needs the fork back
+                self.request_queue.retain(|r| r.fork_id != fork_id);
+                None
+            }
+            ForkMessage::Stats {
+                philosopher_id,
+                meals,
+                wait_ns,
+            } => {
+                self.pass_on_stats(StatsReport {
+                    philosopher_id,
+                    meals,
+                    wait_ns,
+                });
+                None
+            }
+        };
+        self.unblock_if_possible()?;
+        if let Some((by, fork_id)) = request {
+            if self.has_pending_for(by, fork_id) {
+                let reason = if self.forks[fork_id].is_some() {
+                    QueueReason::ForkClean
+                } else {
+                    QueueReason::ForkNotHeld
+                };
+                self.events.record(
+                    self.local_clock(),
+                    self.vector_time(),
+                    SimulationEvent::ForkRequestQueued {
+                        from: by,
+                        to: self.id,
+                        fork_id,
+                        reason,
+                    },
+                )?;
+            }
+        }
+        self.debug_assert_forks_valid();
+        self.publish_snapshot()?;
+        Ok(())
+    }
+
+    pub fn queue_request(
+        &mut self,
+        requester: PhilosopherId,
+        fork_id: ForkId,
+        priority: Priority,
+    ) -> Result<(), DiningPhilosophersError> {
+        // a misrouted message, we have nothing to give. Only the neighbour on the other side
+        // of a fork gets into the queue, so there is never more than one request for a fork.
+        if !self.is_neighbor(requester)
+            || !self.needs.contains(fork_id)
+            || self.neighbour_sharing(fork_id).ok() != Some(requester)
+        {
+            self.events.record(
+                self.local_clock(),
+                self.vector_time(),
+                SimulationEvent::ForkRequestIgnored {
+                    from: requester,
+                    to: self.id,
+                    fork_id,
+                },
+            )?;
+            return Ok(());
+        }
+        if !self.has_pending_for(requester, fork_id) {
+            self.request_queue.push_back(ForkRequest {
+                requester,
+                fork_id,
+                requested_at: Instant::now(),
+                priority,
+            });
+        }
+        if self.forks[fork_id].is_some() {
+            self.forks[fork_id]
+                .set_request_pending(true)
+                .map_err(|e| e.of(self.id, fork_id))?;
+        }
+        Ok(())
+    }
+
+    /// take every queued request for `fork_id` out of the queue, oldest first
+    pub fn drain_request_queue_for_fork(&mut self, fork_id: ForkId) -> Vec<ForkRequest> {
+        let (drained, kept): (VecDeque<_>, _) = std::mem::take(&mut self.request_queue)
+            .into_iter()
+            .partition(|r| r.fork_id == fork_id);
+        self.request_queue = kept;
+        drained.into()
+    }
+
+    /// Send every requested fork that is dirty. Requests for clean forks, or forks we do not
+    /// have, stay queued until the fork becomes dirty after eating. Returns whether any fork
+    /// was sent.
+    pub fn unblock_if_possible(&mut self) -> Result<bool, DiningPhilosophersError> {
+        let mut sent = false;
+        for _ in 0..self.request_queue.len() {
+            // runners up drained below shorten the queue
+            let request = match self.request_queue.pop_front() {
+                Some(request) => request,
+                None => break,
+            };
+            let storage = &self.forks[request.fork_id];
+            let outranked = request.priority > self.priority && storage.is_some();
+            if storage.is_dirty() || storage.lease_expired() || outranked {
+                self.send_fork(
+                    request.fork_id,
+                    request.requester,
+                    DeliveryReason::Requested,
+                )?;
+                self.events.record(
+                    self.local_clock(),
+                    self.vector_time(),
+                    SimulationEvent::ForkRequestServed {
+                        requester: request.requester,
+                        fork_id: request.fork_id,
+                        waited: request.requested_at.elapsed(),
+                    },
+                )?;
+                sent = true;
+            } else {
+                self.request_queue.push_back(request);
+            }
+        }
+        Ok(sent)
+    }
+
+    /// what `run` does before every step: count the iteration, age the forks and send what
+    /// waits in the outboxes
+    pub fn begin_iteration(&mut self) -> Result<(), DiningPhilosophersError> {
+        if self.crash_after_meals == Some(self.meals) {
+            panic!("{} choked on the meal", self.name);
+        }
+        self.iterations += 1;
+        for fork in self.forks.values_mut() {
+            fork.tick();
+        }
+        self.return_expired_forks()?;
+        self.flush_outboxes()
+    }
+
+    /// what a `LeaseTimer` needs to return a fork that came from `to`
+    pub fn lease_return(
+        &mut self,
+        to: PhilosopherId,
+    ) -> Result<LeaseReturn, DiningPhilosophersError> {
+        let channel = self
+            .neighbours
+            .get(&to)
+            .ok_or(DiningPhilosophersError::ChannelClosed(to))?
+            .channel
+            .clone();
+        Ok(LeaseReturn {
+            from: self.id,
+            to,
+            outbox: Outbox::new(channel),
+            held_forks: self.held_forks.clone(),
+            accounting: self.accounting.clone(),
+            events: self.events.clone(),
+            sent_at: self.next_timestamp(),
+            vector: self.vector_time(),
+        })
+    }
+
+    /// stop the lease timer of the fork, the requests for it are answered if the timer sent it
+    pub fn stop_lease_timer(&mut self, fork_id: ForkId) {
+        let storage = &mut self.forks[fork_id];
+        let sent = matches!(storage.fork, ForkPresence::Leased(_)) && storage.is_in_flight();
+        storage.stop_timer();
+        if sent {
+            self.request_queue.retain(|r| r.fork_id != fork_id);
+        }
+    }
+
+    /// Stop the lease timers of the forks we need, whether all of them are still here. Once
+    /// stopped no timer takes a fork away while we eat.
+    pub fn keep_forks(&mut self) -> bool {
+        let needs: Vec<ForkId> = self.needs.iter().copied().collect();
+        for fid in needs {
+            self.stop_lease_timer(fid);
+        }
+        self.can_proceed()
+    }
+
+    /// hand every fork held past its lease to the neighbour sharing it, whoever asked for it
+    pub fn return_expired_forks(&mut self) -> Result<(), DiningPhilosophersError> {
+        if *self.state.lock()? == PhilosopherState::Hungry && self.can_proceed() {
+            // about to eat, the forks go after the meal
+            return Ok(());
+        }
+        let accounting = self.accounting.clone();
+        let _counting = accounting.pause.read()?;
+        let expired: Vec<(ForkId, Option<PhilosopherId>)> = self
+            .forks
+            .iter()
+            .filter(|(_, storage)| storage.lease_expired())
+            .map(|(fid, storage)| (*fid, storage.return_to()))
+            .collect();
+        for (fid, return_to) in expired {
+            // unless its timer was quicker
+            self.stop_lease_timer(fid);
+            if !self.forks[fid].is_some() {
+                continue;
+            }
+            let to = match return_to {
+                Some(to) => to,
+                None => self.neighbour_sharing(fid)?,
+            };
+            self.request_queue.retain(|r| r.fork_id != fid);
+            self.send_fork(fid, to, DeliveryReason::LeaseExpired)?;
+        }
+        Ok(())
+    }
+
+    /// until `stop_at`, and until everyone ate `meal_limit` meals
+    pub fn stays_at_table(&self) -> bool {
+        let full = self.meal_limit.is_some() && self.registry.all_full();
+        !full && self.stop_at.is_none_or(|t| Instant::now() < t)
+    }
+
+    /// give away the forks and whatever still waits in the outboxes, to neighbours that may
+    /// have left already, marked as a departure since nobody has to have asked for them
+    pub fn leave_table(&mut self) {
+        let accounting = self.accounting.clone();
+        // the forks have to go even if someone panicked while counting them
+        let _counting = accounting
+            .pause
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let held: Vec<ForkId> = self
+            .forks
+            .iter()
+            .filter(|(_, storage)| storage.is_some())
+            .map(|(fid, _)| *fid)
+            .collect();
+        for fid in held {
+            self.request_queue.retain(|r| r.fork_id != fid);
+            if let Ok(to) = self.neighbour_sharing(fid) {
+                let _ = self.send_fork(fid, to, DeliveryReason::Departure);
+            }
+        }
+        let _ = self.flush_outboxes();
+        // the last reports of the neighbours, the rest stays for whoever looks
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                ForkMessage::Stats {
+                    philosopher_id,
+                    meals,
+                    wait_ns,
+                } => self.pass_on_stats(StatsReport {
+                    philosopher_id,
+                    meals,
+                    wait_ns,
+                }),
+                other => self.early_messages.push_back(other),
+            }
+        }
+    }
+}
+
+/// The calls `run` makes, a `PhilosopherSpy` stands in between them and the philosopher
+pub trait PhilosopherSteps<const N: usize = 2> {
+    fn philosopher(&mut self) -> &mut Philosopher<N>;
+
+    /// get hungry and ask for the missing forks
+    fn request(&mut self) -> Result<(), DiningPhilosophersError>;
+
+    /// acquire, use and release the forks once `can_proceed`
+    fn eat(&mut self) -> Result<(), DiningPhilosophersError>;
+
+    fn handle_requests(&mut self) -> Result<(), DiningPhilosophersError>;
+
+    fn run(&mut self) -> Result<(), DiningPhilosophersError>;
+
+    /// Run for `duration` of wall time, then hand every fork to the neighbour sharing it so
+    /// the neighbours can finish their meals.
+    fn run_for(&mut self, duration: Duration) -> Result<(), DiningPhilosophersError> {
+        self.philosopher().stop_at = Some(Instant::now() + duration);
+        self.run()
+    }
+}
+
+/// One call of `run`: begin the iteration, take the step and wait for the next tick
+pub fn step<const N: usize, S: PhilosopherSteps<N>>(
+    steps: &mut S,
+    call: fn(&mut S) -> Result<(), DiningPhilosophersError>,
+) -> Result<(), DiningPhilosophersError> {
+    steps.philosopher().begin_iteration()?;
+    call(steps)?;
+    if let Some(scheduler) = &mut steps.philosopher().tick_scheduler {
+        scheduler.wait_for_next_tick();
+    }
+    Ok(())
+}
+
+/// The meals of the `PhilosopherProtocol`, until it is time to leave the table or to move to
+/// another thread:
+///
+/// ```text
+/// loop { request; while !can_proceed { handle_requests } acquire; use_resources; release; think }
+/// ```
+///
+/// with the three steps after waiting in `eat`, and thinking as `handle_requests` until
+/// `is_done_thinking`. Waiting ends once `keep_forks` stopped the lease timers of the forks, too.
+/// A full philosopher only answers the others.
+pub fn run_meals<const N: usize, S: PhilosopherSteps<N>>(
+    steps: &mut S,
+) -> Result<(), DiningPhilosophersError> {
+    while steps.philosopher().stays_at_table() {
+        if steps.philosopher().is_full() {
+            step(steps, S::handle_requests)?;
+            continue;
+        }
+        step(steps, S::request)?;
+        while !(steps.philosopher().can_proceed() && steps.philosopher().keep_forks()) {
+            if !steps.philosopher().stays_at_table() {
+                return Ok(());
+            }
+            step(steps, S::handle_requests)?;
+        }
+        step(steps, S::eat)?;
+        while !steps.philosopher().is_done_thinking() {
+            if !steps.philosopher().stays_at_table() {
+                return Ok(());
+            }
+            step(steps, S::handle_requests)?;
+        }
+        if steps.philosopher().wants_to_migrate() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// `run_meals`, then leave the table unless moving to another thread
+pub fn run_steps<const N: usize, S: PhilosopherSteps<N>>(
+    steps: &mut S,
+) -> Result<(), DiningPhilosophersError> {
+    match run_meals(steps) {
+        // a neighbour whose time was up, or who was full, left the table before us
+        Err(DiningPhilosophersError::ChannelClosed(_))
+            if steps.philosopher().stop_at.is_some()
+                || steps.philosopher().meal_limit.is_some() => {}
+        result => result?,
+    }
+    if steps.philosopher().wants_to_migrate() {
+        // carries on in the thread the supervisor spawns, without leaving the table
+        return Ok(());
+    }
+    steps.philosopher().leave_table();
+    Ok(())
+}
+
+impl<const N: usize> PhilosopherSteps<N> for Philosopher<N> {
+    fn philosopher(&mut self) -> &mut Philosopher<N> {
+        self
+    }
+
+    fn request(&mut self) -> Result<(), DiningPhilosophersError> {
+        self.ask_for_forks()
+    }
+
+    fn eat(&mut self) -> Result<(), DiningPhilosophersError> {
+        Philosopher::eat(self)
+    }
+
+    /// Hungry philosophers give away dirty forks on request, and without batching they ask for
+    /// one fork at a time, so they ask again after every message.
+    fn handle_requests(&mut self) -> Result<(), DiningPhilosophersError> {
+        Philosopher::handle_requests(self)?;
+        if *self.state.lock()? == PhilosopherState::Hungry && !self.can_proceed() {
+            self.ask_for_forks()?;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), DiningPhilosophersError> {
+        run_steps(self)
+    }
+}
+
+// a philosopher leaving the table takes its forks along to the pool, the neighbours will wait for
+// them forever
+impl<const N: usize> Drop for Philosopher<N> {
+    fn drop(&mut self) {
+        if let Some(supervisor) = self.supervisor.take() {
+            if thread::panicking() {
+                // the supervisor is gone when main returned, nobody left to take over
+                if let Ok(successor) = self.successor() {
+                    let _ = supervisor.send(successor);
+                }
+                return;
+            }
+        }
+        let mut dropped = vec![];
+        for (fid, storage) in self.forks.iter_mut() {
+            if let Some(fork) = storage.take() {
+                dropped.push(*fid);
+                let _ = self.fork_pool.put_back(fork);
+            }
+        }
+        // never sat down, nobody was waiting for the forks yet
+        if self.iterations > 0 {
+            for fork_id in dropped {
+                let event = SimulationEvent::ForkDropped {
+                    id: self.id,
+                    fork_id,
+                };
+                let _ = self
+                    .events
+                    .record(self.local_clock(), self.vector_time(), event);
+            }
+        }
+    }
+}
+
+pub fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|m| m.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+pub fn run_supervised<S: PhilosopherSteps>(
+    steps: &mut S,
+    run_for: Option<Duration>,
+) -> Result<(), DiningPhilosophersError> {
+    match run_for {
+        // a successor keeps the time its predecessor had left
+        Some(duration) if steps.philosopher().stop_at.is_none() => steps.run_for(duration),
+        _ => steps.run(),
+    }
+}
+
+pub type PhilosopherHandle = thread::JoinHandle<Result<(), DiningPhilosophersError>>;
+
+/// Runs the philosopher threads and puts a successor in the seat of every philosopher whose
+/// thread panics, so the neighbours keep eating.
+pub struct PhilosopherSupervisor {
+    pub handles: Vec<(PhilosopherId, PhilosopherHandle)>,
+    pub successors: mpsc::Receiver<Philosopher>,
+    pub stack_size: Option<usize>,
+    /// how long each philosopher stays at the table, for ever when `None`
+    pub run_for: Option<Duration>,
+    /// ends the run with its error, see `watch_progress`
+    pub progress: Option<ProgressMonitor>,
+}
+
+impl PhilosopherSupervisor {
+    /// supervisor and the sender every philosopher needs to hand over a successor
+    pub fn new(
+        stack_size: Option<usize>,
+        run_for: Option<Duration>,
+    ) -> (Self, mpsc::Sender<Philosopher>) {
+        let (sender, successors) = mpsc::channel();
+        let supervisor = Self {
+            handles: vec![],
+            successors,
+            stack_size,
+            run_for,
+            progress: None,
+        };
+        (supervisor, sender)
+    }
+
+    pub fn spawn(&mut self, mut p: Philosopher) -> Result<(), DiningPhilosophersError> {
+        let id = p.id;
+        let mut builder = thread::Builder::new().name(p.name.clone());
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let run_for = self.run_for;
+        let handle = builder
+            .spawn(move || {
+                let result = run_supervised(&mut p, run_for);
+                if let (true, Some(supervisor)) = (p.wants_to_migrate(), p.supervisor.clone()) {
+                    println!("{} {} moves to another thread", p.id, p.name);
+                    p.migrate_after_meals = None;
+                    let _ = supervisor.send(Philosopher::from_parts(p.into_parts()?)?);
+                }
+                result
+            })
+            .map_err(DiningPhilosophersError::ThreadSpawn)?;
+        self.handles.push((id, handle));
+        Ok(())
+    }
+
+    pub fn respawn(&mut self, successor: Philosopher) -> Result<(), DiningPhilosophersError> {
+        let id = successor.id;
+        if let Some(i) = self.handles.iter().position(|(h, _)| *h == id) {
+            let (_, handle) = self.handles.remove(i);
+            if let Err(panic) = handle.join() {
+                println!(
+                    "philosopher {} panicked: {}, respawning",
+                    id,
+                    panic_message(&*panic)
+                );
+            }
+        }
+        self.spawn(successor)
+    }
+
+    /// look for deadlocks and starving philosophers while the table runs
+    pub fn watch_progress(&mut self, monitor: ProgressMonitor) {
+        self.progress = Some(monitor);
+    }
+
+    /// Returns the first error of a philosopher, panics without a successor included, or of
+    /// the `ProgressMonitor`
+    pub fn run(mut self) -> Result<(), DiningPhilosophersError> {
+        while !self.handles.is_empty() {
+            match self.successors.recv_timeout(Duration::from_millis(100)) {
+                Ok(successor) => self.respawn(successor)?,
+                Err(_) => {
+                    if let Some(monitor) = &mut self.progress {
+                        monitor.check()?;
+                    }
+                    // every thread that finished, they all do at once with `run_for`
+                    while let Some(i) = self.handles.iter().position(|(_, h)| h.is_finished()) {
+                        let (id, handle) = self.handles.remove(i);
+                        match handle.join() {
+                            Ok(result) => result?,
+                            Err(panic) => match self.successors.try_recv() {
+                                // finished right after handing over the successor
+                                Ok(successor) => self.spawn(successor)?,
+                                Err(_) => {
+                                    let message = panic_message(&*panic);
+                                    return Err(DiningPhilosophersError::PhilosopherPanicked {
+                                        id,
+                                        message,
+                                    });
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One step of the protocol per call of `eat`: ask for the missing forks while any is missing,
+/// otherwise eat
+impl<const N: usize> PhilosopherProtocol for Philosopher<N> {
+    type Error = DiningPhilosophersError;
+
+    fn request(&mut self) -> Result<(), DiningPhilosophersError> {
+        self.set_state(PhilosopherState::Hungry)?;
+        self.hungry_since.get_or_insert_with(Instant::now);
+        self.send_all_pending_requests()
+    }
+
+    /// all forks we need are in storage
+    fn can_proceed(&self) -> bool {
+        self.needs.is_subset_of(&self.forks.held())
+    }
+
+    fn acquire(&mut self) -> Result<(), DiningPhilosophersError> {
+        self.set_state(PhilosopherState::Eating)?;
+        let wait = self
+            .hungry_since
+            .take()
+            .map_or(Duration::ZERO, |t| t.elapsed());
+        self.events.record(
+            self.local_clock(),
+            self.vector_time(),
+            SimulationEvent::PhilosopherStartedEating {
+                id: self.id,
+                waited: wait,
+            },
+        )?;
+        self.record_wait(wait);
+        let wait_ns = wait.as_nanos() as u64;
+        self.counters
+            .total_wait_ns
+            .fetch_add(wait_ns, Ordering::Relaxed);
+        self.counters
+            .max_starvation_ns
+            .fetch_max(wait_ns, Ordering::Relaxed);
+        // neighbours never eat at the same time, one that ate is thinking before it hands on a fork
+        for neighbour in self.neighbors() {
+            let state = self.registry.philosophers.get(&neighbour);
+            if state.is_some_and(|s| {
+                *s.lock().unwrap_or_else(PoisonError::into_inner) == PhilosopherState::Eating
+            }) {
+                return Err(DiningPhilosophersError::NeighbourEating {
+                    id: self.id,
+                    neighbour,
+                });
+            }
+        }
+        if self.print_states {
+            self.print_state()?;
+            if self.meals == 0 {
+                println!(
+                    "{} {} got to eat after {} iterations.",
+                    self.id, self.name, self.iterations
+                );
+            }
+        }
+        if let Some(before) = &self.hooks.before {
+            let forks: Vec<ForkId> = self.forks.keys().copied().collect();
+            before(self.id, &forks)?;
+        }
+        Ok(())
+    }
+
+    fn use_resources(&mut self) -> Result<(), DiningPhilosophersError> {
+        self.clock.sleep(self.eating_duration);
+        self.events.record(
+            self.local_clock(),
+            self.vector_time(),
+            SimulationEvent::PhilosopherFinishedEating { id: self.id },
+        )?;
+        self.meals += 1;
+        self.counters.meals_eaten.fetch_add(1, Ordering::Relaxed);
+        if self.stats.is_some() {
+            // before we count as full, the neighbours stay until they got it
+            self.report_stats();
+        }
+        if self.meal_limit == Some(self.meals) {
+            self.registry.report_full();
+        }
+        Ok(())
+    }
+
+    /// forks on the table are dirty, and handed to whoever asked for them
+    fn release(&mut self) -> Result<(), DiningPhilosophersError> {
+        let forks: Vec<ForkId> = self.forks.keys().copied().collect();
+        if let Some(after) = &self.hooks.after {
+            after(self.id, &forks)?;
+        }
+        let id = self.id;
+        for (fid, fork) in self.forks.iter_mut() {
+            fork.dirty().map_err(|e| e.of(id, *fid))?;
+        }
+        self.set_state(PhilosopherState::Thinking)?;
+        if !self.thinking_duration.is_zero() {
+            self.thinking_until = Some(Instant::now() + self.clock.scaled(self.thinking_duration));
+        }
+        if self.print_states {
+            self.print_state()?;
+        }
+        // give forks to others who requested them, also those asking while we ate
+        for fid in forks {
+            self.send_fork_if_requested(fid)?;
+            debug_assert!(!self.forks[fid].was_requested_while_held());
+        }
+        self.unblock_if_possible()?;
+        Ok(())
+    }
+}
+
+/// Watches the whole table from outside the philosopher threads.
+pub struct SimulationObserver {
+    pub held_forks: Vec<Arc<AtomicUsize>>,
+    pub snapshots: Vec<Arc<Mutex<PhilosopherSnapshot>>>,
+    pub counters: Vec<Arc<PhilosopherCounters>>,
+    pub states: Vec<Arc<Mutex<PhilosopherState>>>,
+    pub accounting: Arc<ForkAccounting>,
+    /// forks on the table, each shared by two philosophers but counted once
+    pub expected_forks: usize,
+}
+
+impl SimulationObserver {
+    pub fn new(philosophers: &[Philosopher], accounting: Arc<ForkAccounting>) -> Self {
+        Self {
+            held_forks: philosophers.iter().map(|p| p.held_forks.clone()).collect(),
+            snapshots: philosophers.iter().map(|p| p.snapshot.clone()).collect(),
+            counters: philosophers.iter().map(|p| p.counters.clone()).collect(),
+            states: philosophers.iter().map(|p| p.state.clone()).collect(),
+            accounting,
+            expected_forks: philosophers
+                .iter()
+                .flat_map(|p| p.forks.iter().map(|(fid, _)| *fid))
+                .collect::<BTreeSet<_>>()
+                .len(),
+        }
+    }
+
+    /// share of the needed forks in the hands of philosophers, 0 to 1
+    pub fn system_readiness(&self) -> f64 {
+        let held: usize = self
+            .held_forks
+            .iter()
+            .map(|h| h.load(Ordering::Relaxed))
+            .sum();
+        held as f64 / self.expected_forks as f64
+    }
+
+    /// Forks held by philosophers plus forks in the channels, with every philosopher paused
+    /// between two steps. Forks are neither created nor destroyed, so this never changes.
+    pub fn fork_count(&self) -> usize {
+        let _paused = self
+            .accounting
+            .pause
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let held: usize = self
+            .held_forks
+            .iter()
+            .map(|h| h.load(Ordering::Relaxed))
+            .sum();
+        held + self.accounting.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// meals of the whole table so far
+    pub fn total_meals(&self) -> u64 {
+        self.counters
+            .iter()
+            .map(|c| c.meals_eaten.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// `Philosopher::statistics_snapshot` of every philosopher, without pausing anyone
+    pub fn statistics(&self) -> Vec<PhilosopherStats> {
+        self.counters
+            .iter()
+            .zip(&self.held_forks)
+            .zip(&self.states)
+            .map(|((c, h), s)| PhilosopherStats::read(c, h, s))
+            .collect()
+    }
+
+    /// Every philosopher at the same moment, pausing them all while collecting
+    pub fn inspect_snapshot(&self) -> SimulationSnapshot {
+        let _paused = self
+            .accounting
+            .pause
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        SimulationSnapshot {
+            timestamp: Instant::now(),
+            philosophers: self
+                .snapshots
+                .iter()
+                .map(|s| s.lock().unwrap_or_else(PoisonError::into_inner).clone())
+                .collect(),
+        }
+    }
+}
+
+/// Tells a table where nobody eats any more from one where a single philosopher waits too long
+pub struct ProgressMonitor {
+    pub observer: SimulationObserver,
+    /// longest a philosopher may stay hungry
+    pub limit: Duration,
+    /// meals of the whole table at the last check
+    pub meals: u64,
+    pub watching_since: Instant,
+    /// the check that first saw the meals go up last
+    pub last_meal: Option<Instant>,
+}
+
+impl ProgressMonitor {
+    pub fn new(observer: SimulationObserver, limit: Duration) -> Self {
+        let meals = observer.total_meals();
+        Self {
+            observer,
+            limit,
+            meals,
+            watching_since: Instant::now(),
+            last_meal: None,
+        }
+    }
+
+    /// `Deadlock` once hungry philosophers saw nobody eat for `limit`, otherwise `Starvation`
+    /// of the philosopher hungry for longest if that is more than `limit` and others ate
+    /// meanwhile
+    pub fn check(&mut self) -> Result<(), DiningPhilosophersError> {
+        let now = Instant::now();
+        let meals = self.observer.total_meals();
+        if meals != self.meals {
+            self.meals = meals;
+            self.last_meal = Some(now);
+        }
+        let hungry: Vec<(PhilosopherId, Instant)> = self
+            .observer
+            .snapshots
+            .iter()
+            .filter_map(|s| {
+                let s = s.lock().unwrap_or_else(PoisonError::into_inner);
+                s.hungry_since.map(|since| (s.id, since))
+            })
+            .collect();
+        let stalled_for = now - self.last_meal.unwrap_or(self.watching_since);
+        match hungry.iter().min_by_key(|(_, since)| *since) {
+            Some(_) if stalled_for > self.limit => Err(DiningPhilosophersError::Deadlock {
+                hungry: hungry.iter().map(|(id, _)| *id).collect(),
+                stalled_for,
+            }),
+            Some(&(philosopher_id, since))
+                if now - since > self.limit && self.last_meal.is_some_and(|t| t > since) =>
+            {
+                Err(DiningPhilosophersError::Starvation {
+                    philosopher_id,
+                    starved_for: now - since,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Meals whose waits a `SimulationReport` keeps for its percentiles
+pub const WAIT_WINDOW: usize = 10_000;
+
+/// What happened at the table, as far as the events tell
+#[derive(Debug, Default, PartialEq)]
+pub struct SimulationReport {
+    pub meals: usize,
+    pub transfers: usize,
+    pub deferred_clean: usize,
+    pub deferred_not_held: usize,
+    pub served: usize,
+    /// how long philosophers were hungry for their forks before each of the last
+    /// `WAIT_WINDOW` meals, in the order the meals started
+    pub waits: VecDeque<Duration>,
+    /// longest wait before any meal
+    pub max_wait: Duration,
+    /// last philosopher each fork was sent to, forks that never moved are missing
+    pub holders: BTreeMap<ForkId, PhilosopherId>,
+}
+
+/// Builds the report of a run from its recorded events, applying them one by one in order
+/// and passing each on to `sink` as it goes, so a recorded run can be watched again.
+/// Works on a log taken from a running table as well as on one kept after the run.
+pub fn replay(events: &[SimulationEvent], sink: &mut dyn EventSink) -> SimulationReport {
+    let mut report = SimulationReport::default();
+    for event in events {
+        report.apply(event);
+        sink.on_event(event.clone());
+    }
+    report
+}
+
+impl SimulationReport {
+    /// one more event, in the order they were recorded
+    pub fn apply(&mut self, event: &SimulationEvent) {
+        match event {
+            SimulationEvent::PhilosopherStartedEating { waited, .. } => {
+                if self.waits.len() == WAIT_WINDOW {
+                    self.waits.pop_front();
+                }
+                self.waits.push_back(*waited);
+                self.max_wait = self.max_wait.max(*waited);
+            }
+            SimulationEvent::PhilosopherFinishedEating { .. } => self.meals += 1,
+            SimulationEvent::ForkRequestQueued { reason, .. } => match reason {
+                QueueReason::ForkClean => self.deferred_clean += 1,
+                QueueReason::ForkNotHeld => self.deferred_not_held += 1,
+            },
+            SimulationEvent::ForkTransferred { to, fork_id, .. } => {
+                self.transfers += 1;
+                self.holders.insert(*fork_id, *to);
+            }
+            SimulationEvent::ForkDelivered { .. } => {}
+            SimulationEvent::ForkRequestIgnored { .. } => {}
+            SimulationEvent::ForkRequested { .. } => {}
+            SimulationEvent::ForkDropped { .. } => {}
+            SimulationEvent::ForkRequestServed { .. } => self.served += 1,
+        }
+    }
+
+    /// wait that `percent` of the recent meals did not exceed, zero before the first one
+    pub fn wait_time_percentile(&self, percent: usize) -> Duration {
+        if self.waits.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut waits: Vec<_> = self.waits.iter().copied().collect();
+        waits.sort();
+        let rank = (waits.len() * percent).div_ceil(100);
+        waits[rank.max(1) - 1]
+    }
+
+    pub fn wait_time_p50(&self) -> Duration {
+        self.wait_time_percentile(50)
+    }
+
+    pub fn wait_time_p95(&self) -> Duration {
+        self.wait_time_percentile(95)
+    }
+
+    pub fn wait_time_p99(&self) -> Duration {
+        self.wait_time_percentile(99)
+    }
+
+    pub fn wait_time_max(&self) -> Duration {
+        self.max_wait
+    }
+
+    /// the headline numbers as a table for the terminal, `elapsed` is how long the table ran
+    pub fn to_table_string(&self, philosophers: usize, elapsed: Duration) -> String {
+        let header = format!(
+            "{:<12} | {:>12} | {:>8} | {:>10} | {:>10} | {:>10}",
+            "Algorithm", "Philosophers", "Meals", "Throughput", "P99 Wait", "Max Wait"
+        );
+        let throughput = self.meals as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let row = format!(
+            "{:<12} | {:>12} | {:>8} | {:>8.1}/s | {:>8}ms | {:>8}ms",
+            "Chandy/Misra",
+            philosophers,
+            self.meals,
+            throughput,
+            self.wait_time_p99().as_millis(),
+            self.wait_time_max().as_millis()
+        );
+        format!("{}\n{}\n{}\n", header, "-".repeat(header.len()), row)
+    }
+
+    /// the recent waits in `buckets` of equal width up to the longest of them
+    pub fn wait_histogram(&self, buckets: usize) -> Vec<(Duration, usize)> {
+        let longest = self.waits.iter().max().copied().unwrap_or_default();
+        let width = (longest / buckets as u32).max(Duration::from_nanos(1));
+        let mut histogram: Vec<_> = (0..buckets).map(|b| (width * b as u32, 0)).collect();
+        for wait in &self.waits {
+            let bucket = (wait.as_nanos() / width.as_nanos()) as usize;
+            histogram[bucket.min(buckets - 1)].1 += 1;
+        }
+        histogram
+    }
+}
+
+/// Bars the histogram of the recent waits is at most as long as
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// the counts, the wait percentiles and a bar chart of the waits
+impl std::fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{} meals, {} forks sent, {} requests served\nwaits p50 {:?}, p95 {:?}, p99 {:?}, \
+             max {:?}\n",
+            self.meals,
+            self.transfers,
+            self.served,
+            self.wait_time_p50(),
+            self.wait_time_p95(),
+            self.wait_time_p99(),
+            self.wait_time_max()
+        ))?;
+        let histogram = self.wait_histogram(10);
+        let most = histogram.iter().map(|&(_, count)| count).max().unwrap_or(0);
+        for (from, count) in histogram {
+            let bar = (count * HISTOGRAM_WIDTH).div_ceil(most.max(1));
+            f.write_fmt(format_args!(
+                "{:>10.1?} | {:<width$} {}\n",
+                from,
+                "#".repeat(bar),
+                count,
+                width = HISTOGRAM_WIDTH
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// A Chandy/Misra invariant that does not hold, see `consistency_check`
+#[derive(Debug)]
+pub enum InvariantViolation {
+    /// held by both philosophers sharing it, or by neither without being on its way
+    ForkNotInOnePlace {
+        fork_id: ForkId,
+        holders: Vec<PhilosopherId>,
+    },
+    /// the philosopher has all its forks but still waits for one of them
+    RequestedWhileHeld { id: PhilosopherId, fork_id: ForkId },
+    /// neither philosopher sharing the fork holds it or has asked for it
+    NobodyAsking { fork_id: ForkId },
+    /// no message is left to deliver the fork, still its sender thinks it is on its way
+    StuckInFlight { fork_id: ForkId },
+}
+
+impl std::fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantViolation::ForkNotInOnePlace { fork_id, holders } => {
+                f.write_fmt(format_args!(
+                    "fork {} is held by {:?} and not in flight",
+                    fork_id, holders
+                ))
+            }
+            InvariantViolation::RequestedWhileHeld { id, fork_id } => f.write_fmt(format_args!(
+                "{} holds all its forks and still asks for fork {}",
+                id, fork_id
+            )),
+            InvariantViolation::NobodyAsking { fork_id } => f.write_fmt(format_args!(
+                "fork {} is neither held nor asked for",
+                fork_id
+            )),
+            InvariantViolation::StuckInFlight { fork_id } => f.write_fmt(format_args!(
+                "fork {} is in flight with no message left to carry it",
+                fork_id
+            )),
+        }
+    }
+}
+
+/// Checks the invariants of the forks shared between `philosophers`. Only meaningful when none
+/// of them is running, a fork taken out of storage and not sent yet would look lost. Once every
+/// message has been handled the table is at rest, and no fork may be in flight any more.
+pub fn consistency_check(philosophers: &[Philosopher]) -> Result<(), Vec<InvariantViolation>> {
+    let at_rest = philosophers.iter().all(|p| p.is_at_rest());
+    let mut violations = vec![];
+    let mut sharers: BTreeMap<ForkId, Vec<(PhilosopherId, &ForkStorage)>> = BTreeMap::new();
+    for p in philosophers {
+        for (fid, storage) in p.forks.iter() {
+            sharers.entry(*fid).or_default().push((p.id, storage));
+        }
+        if p.needs.is_subset_of(&p.forks.held()) {
+            for (fid, storage) in p.forks.iter() {
+                if storage.requested_at().is_some() {
+                    violations.push(InvariantViolation::RequestedWhileHeld {
+                        id: p.id,
+                        fork_id: *fid,
+                    });
+                }
+            }
+        }
+    }
+    for (fork_id, sharers) in sharers {
+        let holders: Vec<_> = sharers
+            .iter()
+            .filter(|(_, storage)| storage.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        let in_flight = sharers.iter().any(|(_, storage)| storage.is_in_flight());
+        if in_flight && at_rest {
+            violations.push(InvariantViolation::StuckInFlight { fork_id });
+        }
+        if holders.len() > 1 || (holders.is_empty() && (!in_flight || at_rest)) {
+            violations.push(InvariantViolation::ForkNotInOnePlace { fork_id, holders });
+        }
+        if sharers
+            .iter()
+            .all(|(_, storage)| storage.needs_requesting())
+        {
+            violations.push(InvariantViolation::NobodyAsking { fork_id });
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Forks as edges between pairs of philosophers by seat, the index of an edge is the fork id
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForkGraph {
+    pub philosophers: usize,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl ForkGraph {
+    pub fn from_edges(
+        philosophers: usize,
+        edges: Vec<(usize, usize)>,
+    ) -> Result<Self, ConfigError> {
+        if philosophers < 2 {
+            return Err(ConfigError::TooFewPhilosophers(philosophers));
+        }
+        for (fork_id, &(a, b)) in edges.iter().enumerate() {
+            if a == b || a >= philosophers || b >= philosophers {
+                return Err(ConfigError::InvalidFork {
+                    fork_id,
+                    between: (a, b),
+                    philosophers,
+                });
+            }
+        }
+        Ok(Self {
+            philosophers,
+            edges,
+        })
+    }
+
+    /// a round table, fork `i` between philosopher `i` and its left neighbour
+    pub fn ring(philosophers: usize) -> Result<Self, ConfigError> {
+        let n = philosophers;
+        Self::from_edges(n, (0..n).map(|i| ((i + n - 1) % n, i)).collect())
+    }
+
+    /// every philosopher shares a fork with the hub, the one with the highest id
+    pub fn star(philosophers: usize) -> Result<Self, ConfigError> {
+        let hub = philosophers.saturating_sub(1);
+        Self::from_edges(philosophers, (0..hub).map(|spoke| (spoke, hub)).collect())
+    }
+
+    /// distinct random pairs until there are `forks` of them and nobody is left without a fork,
+    /// the same seed gives the same table
+    pub fn random(philosophers: usize, forks: usize, seed: u64) -> Result<Self, ConfigError> {
+        let pairs = philosophers * philosophers.saturating_sub(1) / 2;
+        if philosophers >= 2 && (forks < philosophers.div_ceil(2) || forks > pairs) {
+            return Err(ConfigError::ForkCountOutOfRange {
+                philosophers,
+                forks,
+            });
+        }
+        let mut rng = Rng(seed.max(1));
+        loop {
+            let mut edges = vec![];
+            while edges.len() < forks {
+                let a = rng.below(philosophers);
+                let b = rng.below(philosophers);
+                let edge = (a.min(b), a.max(b));
+                if a != b && !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+            let seated = (0..philosophers).all(|p| edges.iter().any(|&(a, b)| a == p || b == p));
+            if seated {
+                return Self::from_edges(philosophers, edges);
+            }
+        }
+    }
+
+    /// the forks of seat `p`, with the seat of the neighbour sharing each
+    pub fn adjacency(&self, p: usize) -> Vec<(ForkId, usize)> {
+        self.edges
+            .iter()
+            .enumerate()
+            .filter_map(|(fork, &(a, b))| {
+                if p == a {
+                    Some((fork, b))
+                } else if p == b {
+                    Some((fork, a))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Fewest philosophers from `from` to `to` when hopping between fork sharers, both ends
+    /// included. `None` if they are not connected.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        // breadth first, remembering where each philosopher was reached from
+        let mut reached_from = HashMap::new();
+        reached_from.insert(from, from);
+        let mut queue = VecDeque::from([from]);
+        while let Some(p) = queue.pop_front() {
+            if p == to {
+                let mut path = vec![to];
+                while let Some(&last) = path.last().filter(|&&last| last != from) {
+                    path.push(reached_from[&last]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (_, next) in self.adjacency(p) {
+                if let std::collections::hash_map::Entry::Vacant(e) = reached_from.entry(next) {
+                    e.insert(p);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The philosophers of `config` in their seats, wired up and ready to be spawned, with room
+    /// for `N` forks each. Every fork starts out dirty at the lower id of the two sharing it,
+    /// which keeps the precedence graph acyclic.
+    pub fn build<const N: usize>(
+        &self,
+        config: &SimulationConfig,
+    ) -> Result<Vec<Philosopher<N>>, DiningPhilosophersError> {
+        self.build_with(config, config)
+    }
+
+    /// Like `build`, with the philosophers created by `factory` rather than as `config` has
+    /// them. The table itself, its channels and forks, still comes from `config`.
+    pub fn build_with<const N: usize>(
+        &self,
+        config: &SimulationConfig,
+        factory: &dyn PhilosopherFactory<N>,
+    ) -> Result<Vec<Philosopher<N>>, DiningPhilosophersError> {
+        let n = self.philosophers;
+        if config.names.len() != n {
+            return Err(ConfigError::NameCountMismatch {
+                philosophers: n,
+                names: config.names.len(),
+            }
+            .into());
+        }
+        for p in 0..n {
+            let forks = self.adjacency(p).len();
+            if forks > N {
+                return Err(ConfigError::TooManyForks {
+                    philosopher: p,
+                    forks,
+                    room: N,
+                }
+                .into());
+            }
+        }
+        let mut senders = vec![];
+        let mut phils = config
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let id = PhilosopherId::new(i, n)
+                    .map_err(|e| DiningPhilosophersError::ConfigError(e.to_string()))?;
+                let (s, r) = fork_channel(config.channel_kind);
+                senders.push(s);
+                Ok(factory.create(id, name, r))
+            })
+            .collect::<Result<Vec<Philosopher<N>>, DiningPhilosophersError>>()?;
+
+        let mut registry = PhilosopherRegistry::default();
+        for p in &phils {
+            registry.register(p.id);
+        }
+        let registry = Arc::new(registry);
+        let events = config.event_log.clone();
+        let accounting = Arc::new(ForkAccounting::default());
+        let wait_for = Arc::new(WaitForGraph::default());
+        let fork_pool = ForkPool::new(self.edges.len());
+        for p in phils.iter_mut() {
+            p.setup_registry(registry.clone());
+            p.setup_event_log(events.clone());
+            p.setup_accounting(accounting.clone());
+            p.setup_wait_for_graph(wait_for.clone());
+            p.setup_fork_pool(fork_pool.clone());
+        }
+
+        for (fork_id, &(a, b)) in self.edges.iter().enumerate() {
+            let (id_a, id_b) = (phils[a].id, phils[b].id);
+            phils[a].setup_neighbour(fork_id, id_b, senders[b].clone());
+            phils[b].setup_neighbour(fork_id, id_a, senders[a].clone());
+            phils[a.max(b)].setup_fork(fork_id, None)?;
+            phils[a.min(b)].setup_fork(fork_id, fork_pool.take(fork_id)?)?;
+        }
+        for (i, p) in phils.iter_mut().enumerate() {
+            p.needs = self.adjacency(i).into_iter().map(|(fid, _)| fid).collect();
+        }
+        Ok(phils)
+    }
+}
+
+/// Seats philosophers around a round table, fork `i` between philosopher `i` and its left
+/// neighbour. Two philosophers share both forks between them.
+#[derive(Debug, Clone, Copy)]
+pub struct RingTopologyBuilder {
+    pub philosophers: usize,
+}
+
+impl RingTopologyBuilder {
+    pub fn new(philosophers: usize) -> Self {
+        Self { philosophers }
+    }
+
+    pub fn graph(&self) -> Result<ForkGraph, ConfigError> {
+        ForkGraph::ring(self.philosophers)
+    }
+
+    /// the philosophers of `config`, one per seat
+    pub fn build(
+        &self,
+        config: &SimulationConfig,
+    ) -> Result<Vec<Philosopher>, DiningPhilosophersError> {
+        self.graph()?.build(config)
+    }
+}
+
+/// Philosophers around a round table as `config` has them, wired up and ready to be spawned,
+/// see `RingTopologyBuilder`
+pub fn ring_of_n(config: &SimulationConfig) -> Result<Vec<Philosopher>, DiningPhilosophersError> {
+    RingTopologyBuilder::new(config.names.len()).build(config)
+}
+
+/// Every philosopher in its own thread for `duration`, back in the order they were given once
+/// they all left. The first error of a philosopher is returned instead.
+pub fn run_table<const N: usize>(
+    philosophers: Vec<Philosopher<N>>,
+    duration: Duration,
+) -> Result<Vec<Philosopher<N>>, DiningPhilosophersError> {
+    let handles: Vec<_> = philosophers
+        .into_iter()
+        .map(|mut p| {
+            let id = p.id;
+            let handle = thread::spawn(move || p.run_for(duration).map(|()| p));
+            (id, handle)
+        })
+        .collect();
+    handles
+        .into_iter()
+        .map(|(id, handle)| {
+            handle.join().unwrap_or_else(|panic| {
+                Err(DiningPhilosophersError::PhilosopherPanicked {
+                    id,
+                    message: panic_message(&*panic),
+                })
+            })
+        })
+        .collect()
+}
+
+/// Seats `names` around a ring, lets each of them eat `meals` meals of `eating` and reports
+/// the run from its events. Gives up on a table not full within an hour.
+pub fn run_chandy_misra(
+    names: &[&str],
+    meals: u64,
+    eating: Duration,
+) -> Result<SimulationReport, DiningPhilosophersError> {
+    let config = SimulationConfig {
+        eating_duration: eating,
+        meals: Some(meals),
+        ..SimulationConfig::new(names.iter().map(|name| name.to_string()).collect())
+    };
+    run_table(ring_of_n(&config)?, Duration::from_secs(60 * 60))?;
+    Ok(replay(&config.event_log.snapshot(), &mut NullSink))
+}
+
+/// Where a philosopher was when its table was checkpointed, see `SimulationCheckpoint`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhilosopherCheckpoint {
+    pub name: String,
+    pub meals: u64,
+    pub lamport: LamportTime,
+    pub vector: VectorClock,
+    /// the forks it holds
+    pub forks: BTreeMap<ForkId, ForkState>,
+    /// the requests it still has to answer, by seat of the requester
+    pub requests: Vec<(usize, ForkId, Priority)>,
+}
+
+/// A table to carry on with later, after a crash for instance: the seats and forks, and every
+/// philosopher with its forks, meals, clocks and the requests it owes. Written out as text by
+/// `Display` and read back with `parse`, a line per fork, philosopher, held fork and request:
+///
+/// ```text
+/// table 2 philosophers
+/// fork 0 between 0 and 1
+/// fork 1 between 0 and 1
+/// philosopher 0 meals 10 clock 57 vector 30,27 name Aristotle
+/// holds fork 0 dirty
+/// owes fork 1 to 1 priority 0
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationCheckpoint {
+    pub graph: ForkGraph,
+    pub philosophers: Vec<PhilosopherCheckpoint>,
+}
+
+impl SimulationCheckpoint {
+    /// The table of philosophers that stopped, after `run_table` for instance. Forks and
+    /// requests still in a channel or an outbox count as arrived, the messages stay where they
+    /// are.
+    pub fn of<const N: usize>(
+        philosophers: &mut [Philosopher<N>],
+    ) -> Result<Self, DiningPhilosophersError> {
+        let n = philosophers.len();
+        let mut edges = BTreeMap::new();
+        for (i, p) in philosophers.iter().enumerate() {
+            for (fid, neighbour) in &p.fork_neighbours {
+                let j = neighbour.index();
+                edges.entry(*fid).or_insert((i.min(j), i.max(j)));
+            }
+        }
+        if edges.keys().copied().ne(0..edges.len()) {
+            return Err(ConfigError::InvalidCheckpoint("fork ids have gaps".to_string()).into());
+        }
+        let graph = ForkGraph::from_edges(n, edges.into_values().collect())?;
+        for p in philosophers.iter_mut() {
+            while let Ok(msg) = p.receiver.try_recv() {
+                p.early_messages.push_back(msg);
+            }
+        }
+        let mut incoming: Vec<Vec<&ForkMessage>> = vec![vec![]; n];
+        for p in philosophers.iter() {
+            incoming[p.id.index()].extend(&p.early_messages);
+            for (to, outbox) in &p.neighbours {
+                incoming[to.index()].extend(&outbox.pending);
+            }
+        }
+        let mut holders = vec![0; graph.edges.len()];
+        let mut saved = vec![];
+        for (p, messages) in philosophers.iter().zip(incoming) {
+            let mut forks: BTreeMap<ForkId, ForkState> = p
+                .forks
+                .iter()
+                .filter_map(|(fid, storage)| storage.fork_state().map(|state| (*fid, state)))
+                .collect();
+            let mut requests: Vec<(usize, ForkId, Priority)> = p
+                .request_queue
+                .iter()
+                .map(|r| (r.requester.index(), r.fork_id, r.priority))
+                .collect();
+            for msg in messages {
+                match msg {
+                    ForkMessage::Delivery(fork, ..) => {
+                        forks.insert(fork.id(), fork.state());
+                    }
+                    ForkMessage::Request(by, fork_id, ..) => {
+                        requests.push((by.index(), *fork_id, 0))
+                    }
+                    ForkMessage::RequestWithPriority(by, fork_id, _, _, priority) => {
+                        requests.push((by.index(), *fork_id, *priority))
+                    }
+                    ForkMessage::Received(..) | ForkMessage::Stats { .. } => {}
+                }
+            }
+            for fid in forks.keys() {
+                holders[*fid] += 1;
+            }
+            saved.push(PhilosopherCheckpoint {
+                name: p.name.clone(),
+                meals: p.meals,
+                lamport: p.local_clock(),
+                vector: p.vector_time(),
+                forks,
+                requests,
+            });
+        }
+        if let Some((fid, held)) = holders.iter().enumerate().find(|(_, held)| **held != 1) {
+            return Err(ConfigError::InvalidCheckpoint(format!(
+                "fork {} is held {} times",
+                fid, held
+            ))
+            .into());
+        }
+        Ok(Self {
+            graph,
+            philosophers: saved,
+        })
+    }
+
+    /// The philosophers of the checkpoint as `config` has them, under their own names, with the
+    /// forks, meals, clocks and requests they had
+    pub fn restore<const N: usize>(
+        &self,
+        config: &SimulationConfig,
+    ) -> Result<Vec<Philosopher<N>>, DiningPhilosophersError> {
+        let mut phils = self.graph.build::<N>(config)?;
+        // the forks of the pool, handed out again where the checkpoint has them
+        let mut forks = BTreeMap::new();
+        for p in phils.iter_mut() {
+            for (fid, storage) in p.forks.iter_mut() {
+                if let Some(fork) = storage.take() {
+                    forks.insert(*fid, fork);
+                }
+            }
+        }
+        let n = phils.len();
+        for (p, saved) in phils.iter_mut().zip(&self.philosophers) {
+            p.name = saved.name.clone();
+            p.meals = saved.meals;
+            p.counters.meals_eaten.store(saved.meals, Ordering::Relaxed);
+            p.lamport.set(saved.lamport);
+            *p.vector_clock.borrow_mut() = saved.vector.clone();
+            let next_to: Vec<ForkId> = p.forks.keys().copied().collect();
+            for fid in next_to {
+                let fork = match saved.forks.get(&fid) {
+                    Some(state) => {
+                        let mut fork = forks.remove(&fid).ok_or_else(|| {
+                            ConfigError::InvalidCheckpoint(format!("fork {} is held twice", fid))
+                        })?;
+                        if *state == ForkState::Dirty {
+                            fork.dirty();
+                        } else {
+                            fork.clean();
+                        }
+                        Some(fork)
+                    }
+                    None => None,
+                };
+                p.setup_fork(fid, fork)?;
+            }
+            for &(by, fork_id, priority) in &saved.requests {
+                let by = PhilosopherId::new(by, n)
+                    .map_err(|e| DiningPhilosophersError::ConfigError(e.to_string()))?;
+                p.queue_request(by, fork_id, priority)?;
+            }
+            if p.is_full() {
+                p.registry.report_full();
+            }
+        }
+        Ok(phils)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let invalid =
+            |line: &str| ConfigError::InvalidCheckpoint(format!("cannot read {:?}", line));
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let first = lines.next().unwrap_or_default();
+        let philosophers = match first.split_whitespace().collect::<Vec<_>>()[..] {
+            ["table", n, "philosophers"] => n.parse().map_err(|_| invalid(first))?,
+            _ => return Err(invalid(first)),
+        };
+        let mut edges = vec![];
+        let mut saved: Vec<PhilosopherCheckpoint> = vec![];
+        for line in lines {
+            let (words, name) = match line.split_once(" name ") {
+                Some((words, name)) => (words, Some(name)),
+                None => (line, None),
+            };
+            let words: Vec<&str> = words.split_whitespace().collect();
+            let number = |word: &str| word.parse::<u64>().map_err(|_| invalid(line));
+            match (&words[..], name, saved.last_mut()) {
+                (["fork", fid, "between", a, "and", b], None, None) => {
+                    if number(fid)? != edges.len() as u64 {
+                        return Err(invalid(line));
+                    }
+                    edges.push((number(a)? as usize, number(b)? as usize));
+                }
+                (
+                    ["philosopher", i, "meals", meals, "clock", clock, "vector", vector],
+                    Some(name),
+                    _,
+                ) => {
+                    if number(i)? != saved.len() as u64 {
+                        return Err(invalid(line));
+                    }
+                    let vector = match *vector {
+                        "-" => vec![],
+                        _ => vector.split(',').map(number).collect::<Result<_, _>>()?,
+                    };
+                    saved.push(PhilosopherCheckpoint {
+                        name: name.to_string(),
+                        meals: number(meals)?,
+                        lamport: number(clock)?,
+                        vector: VectorClock(vector),
+                        forks: BTreeMap::new(),
+                        requests: vec![],
+                    });
+                }
+                (["holds", "fork", fid, state], None, Some(p)) => {
+                    let state = match *state {
+                        "dirty" => ForkState::Dirty,
+                        "clean" => ForkState::Clean,
+                        _ => return Err(invalid(line)),
+                    };
+                    p.forks.insert(number(fid)? as usize, state);
+                }
+                (["owes", "fork", fid, "to", by, "priority", priority], None, Some(p)) => {
+                    p.requests.push((
+                        number(by)? as usize,
+                        number(fid)? as usize,
+                        priority.parse().map_err(|_| invalid(line))?,
+                    ))
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+        if saved.len() != philosophers {
+            return Err(ConfigError::NameCountMismatch {
+                philosophers,
+                names: saved.len(),
+            });
+        }
+        Ok(Self {
+            graph: ForkGraph::from_edges(philosophers, edges)?,
+            philosophers: saved,
+        })
+    }
+}
+
+impl std::fmt::Display for SimulationCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "table {} philosophers\n",
+            self.graph.philosophers
+        ))?;
+        for (fid, (a, b)) in self.graph.edges.iter().enumerate() {
+            f.write_fmt(format_args!("fork {} between {} and {}\n", fid, a, b))?;
+        }
+        for (i, p) in self.philosophers.iter().enumerate() {
+            let vector: Vec<String> = p.vector.0.iter().map(u64::to_string).collect();
+            let vector = if vector.is_empty() {
+                "-".to_string()
+            } else {
+                vector.join(",")
+            };
+            f.write_fmt(format_args!(
+                "philosopher {} meals {} clock {} vector {} name {}\n",
+                i, p.meals, p.lamport, vector, p.name
+            ))?;
+            for (fid, state) in &p.forks {
+                let state = match state {
+                    ForkState::Dirty => "dirty",
+                    ForkState::Clean => "clean",
+                };
+                f.write_fmt(format_args!("holds fork {} {}\n", fid, state))?;
+            }
+            for (by, fid, priority) in &p.requests {
+                f.write_fmt(format_args!(
+                    "owes fork {} to {} priority {}\n",
+                    fid, by, priority
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds deliveries that do not causally follow the transfer of their fork, by fork and Lamport
+/// time of the transfer. Follows the log as it grows: every transfer waits for its delivery and
+/// the other way round, since the sender records the transfer after sending, and is dropped
+/// once the two met.
+#[derive(Debug, Default)]
+pub struct CausalityChecker {
+    pub cursor: u64,
+    pub transfers: HashMap<(ForkId, LamportTime), VectorClock>,
+    pub deliveries: HashMap<(ForkId, LamportTime), VectorClock>,
+}
+
+impl CausalityChecker {
+    /// violations among the events recorded since the last call
+    pub fn check(&mut self, log: &EventLog) -> Vec<(ForkId, LamportTime)> {
+        let mut violations = vec![];
+        for (_, at, vector, event) in log.read_since(&mut self.cursor) {
+            // the transfers of a fork follow each other, so their Lamport times differ
+            let (key, sent, delivered) = match event {
+                SimulationEvent::ForkTransferred { fork_id, .. } => {
+                    match self.deliveries.remove(&(fork_id, at)) {
+                        Some(delivered) => ((fork_id, at), vector, delivered),
+                        None => {
+                            self.transfers.insert((fork_id, at), vector);
+                            continue;
+                        }
+                    }
+                }
+                SimulationEvent::ForkDelivered {
+                    fork_id, sent_at, ..
+                } => match self.transfers.remove(&(fork_id, sent_at)) {
+                    Some(sent) => ((fork_id, sent_at), sent, vector),
+                    None => {
+                        self.deliveries.insert((fork_id, sent_at), vector);
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            if !sent.happened_before(&delivered) {
+                violations.push(key);
+            }
+        }
+        violations
+    }
+}
+
+/// Two neighbours eating at the same time, found after the fact
+#[derive(Debug)]
+pub struct Overlap {
+    pub philosophers: (PhilosopherId, PhilosopherId),
+    pub names: (String, String),
+    pub fork_id: ForkId,
+    pub duration: Duration,
+}
+
+impl std::fmt::Display for Overlap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (a, b) = self.philosophers;
+        let (a_name, b_name) = &self.names;
+        f.write_fmt(format_args!(
+            "neighbours {} {} and {} {} ate with fork {} at the same time for {:?}",
+            a, a_name, b, b_name, self.fork_id, self.duration
+        ))
+    }
+}
+
+/// Checks a run for neighbours that ate at the same time, which the forks should make
+/// impossible. Works on the wall clock times of the events, so unlike the assertion in `eat` it
+/// also catches a meal that overlaps one that already finished. Follows the log as it grows,
+/// keeping only who eats right now.
+pub struct OverlapDetector {
+    /// the neighbours of every philosopher, with the fork they share
+    pub neighbours: HashMap<PhilosopherId, Vec<(PhilosopherId, ForkId)>>,
+    pub names: HashMap<PhilosopherId, String>,
+    pub cursor: u64,
+    /// philosophers eating right now, since when
+    pub eating: HashMap<PhilosopherId, Instant>,
+    /// neighbours eating together right now with the fork between them, since when
+    pub open: HashMap<(PhilosopherId, PhilosopherId), (ForkId, Instant)>,
+    /// every overlap that ended so far
+    pub found: Vec<Overlap>,
+}
+
+impl OverlapDetector {
+    pub fn new(philosophers: &[Philosopher]) -> Self {
+        let neighbours = philosophers
+            .iter()
+            .map(|p| {
+                let shared = p.shared_forks().map(|(fid, n)| (n, fid)).collect();
+                (p.id, shared)
+            })
+            .collect();
+        let names = philosophers
+            .iter()
+            .map(|p| (p.id, p.name.clone()))
+            .collect();
+        Self {
+            neighbours,
+            names,
+            cursor: 0,
+            eating: HashMap::new(),
+            open: HashMap::new(),
+            found: vec![],
+        }
+    }
+
+    /// overlaps that ended among the events recorded since the last call
+    pub fn check(&mut self, log: &EventLog) -> &[Overlap] {
+        let before = self.found.len();
+        for (at, _, _, event) in log.read_since(&mut self.cursor) {
+            match event {
+                SimulationEvent::PhilosopherStartedEating { id, .. } => {
+                    for &(n, fork_id) in &self.neighbours[&id] {
+                        if self.eating.contains_key(&n) {
+                            self.open.insert((id.min(n), id.max(n)), (fork_id, at));
+                        }
+                    }
+                    self.eating.insert(id, at);
+                }
+                SimulationEvent::PhilosopherFinishedEating { id } => {
+                    self.eating.remove(&id);
+                    for &(n, _) in &self.neighbours[&id] {
+                        let (a, b) = (id.min(n), id.max(n));
+                        if let Some((fork_id, start)) = self.open.remove(&(a, b)) {
+                            self.found.push(Overlap {
+                                philosophers: (a, b),
+                                names: (self.names[&a].clone(), self.names[&b].clone()),
+                                fork_id,
+                                duration: at - start,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        &self.found[before..]
+    }
+}
+
+/// Panics listing every time two neighbours ate at the same time, after reading the rest of `log`
+pub fn assert_no_adjacent_simultaneous_eating(log: &EventLog, detector: &mut OverlapDetector) {
+    detector.check(log);
+    let overlaps: Vec<_> = detector.found.iter().map(|o| o.to_string()).collect();
+    assert!(overlaps.is_empty(), "{}", overlaps.join("; "));
+}
+
+/// Graphviz DOT graph of the table, philosophers colored by state and forks as edges between
+/// the two philosophers sharing them. Render with `dot -Tpng -o table.png`.
+pub fn to_dot(philosophers: &[Philosopher]) -> String {
+    let mut dot = String::from("graph table {\n");
+    for p in philosophers {
+        let color = match *p.state.lock().unwrap_or_else(PoisonError::into_inner) {
+            PhilosopherState::Eating => "green",
+            PhilosopherState::Hungry => "yellow",
+            PhilosopherState::Thinking => "grey",
+        };
+        dot.push_str(&format!(
+            "    p{} [label=\"{} {}\\n{} forks, {} requests\" style=filled fillcolor={}];\n",
+            p.id,
+            p.id,
+            p.name,
+            p.held_fork_count(),
+            p.pending_request_count(),
+            color
+        ));
+    }
+    for p in philosophers {
+        for (fid, storage) in p.forks.iter() {
+            if let Some(fork_state) = storage.fork_state() {
+                let (state, style) = match fork_state {
+                    ForkState::Dirty => ("dirty", "dashed"),
+                    ForkState::Clean => ("clean", "solid"),
+                };
+                let neighbor = match p.neighbour_sharing(*fid) {
+                    Ok(neighbor) => neighbor,
+                    Err(_) => continue,
+                };
+                let (a, b) = (p.id.min(neighbor), p.id.max(neighbor));
+                dot.push_str(&format!(
+                    "    p{} -- p{} [label=\"fork {} {}, held by {}\" style={}];\n",
+                    a, b, fid, state, p.id, style
+                ));
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
@@ -0,0 +1,180 @@
+//! How evenly each algorithm shares the meals, by Jain's fairness index.
+//!
+//! All algorithms run side by side with the same number of philosophers for the same time, the
+//! table lists them from the fairest to the least fair. An index of 1 means everybody ate equally
+//! often, 1/n that a single philosopher ate everything.
+//!
+//! `cargo run --release --example compare_fairness -- 5` shortens the default run of 10 seconds.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+mod fairness;
+pub mod table_harness;
+
+use table_harness::{left_right, Arbitrator, NMinusOne, ResourceHierarchy};
+
+const PHILOSOPHERS: usize = 10;
+const EATING: Duration = Duration::from_millis(10);
+const THINKING: Duration = Duration::from_millis(10);
+
+struct FairnessReport {
+    algorithm: &'static str,
+    meals: Vec<u64>,
+}
+
+impl FairnessReport {
+    fn total_meals(&self) -> u64 {
+        self.meals.iter().sum()
+    }
+
+    /// Jain's fairness index of the meals, see `fairness::jain_index`
+    fn fairness(&self) -> f64 {
+        fairness::jain_index(&self.meals)
+    }
+}
+
+fn simulate<F>(algorithm: &'static str, duration: Duration, eat: F) -> FairnessReport
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    FairnessReport {
+        algorithm,
+        meals: table_harness::simulate(PHILOSOPHERS, duration, THINKING, eat).meals,
+    }
+}
+
+fn resource_hierarchy(duration: Duration) -> FairnessReport {
+    let table = ResourceHierarchy::new(PHILOSOPHERS);
+    simulate("resource hierarchy", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+/// whoever asks the waiter while the forks are free gets them
+fn greedy_arbitrator(duration: Duration) -> FairnessReport {
+    let table = Arbitrator::new(PHILOSOPHERS);
+    simulate("greedy arbitrator", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+/// The waiter serves philosophers in the order they asked. A philosopher whose forks are taken
+/// holds up everybody who asked after it.
+fn fifo_arbitrator(duration: Duration) -> FairnessReport {
+    // forks taken, and the philosophers waiting in line
+    let waiter = Mutex::new((vec![false; PHILOSOPHERS], VecDeque::new()));
+    simulate("FIFO arbitrator", duration, move |i| {
+        let (left, right) = left_right(i, PHILOSOPHERS);
+        waiter.lock().unwrap().1.push_back(i);
+        loop {
+            let mut guard = waiter.lock().unwrap();
+            let (forks, queue) = &mut *guard;
+            if queue.front() == Some(&i) && !forks[left] && !forks[right] {
+                queue.pop_front();
+                forks[left] = true;
+                forks[right] = true;
+                break;
+            }
+            drop(guard);
+            thread::yield_now();
+        }
+        thread::sleep(EATING);
+        let mut guard = waiter.lock().unwrap();
+        guard.0[left] = false;
+        guard.0[right] = false;
+    })
+}
+
+fn n_minus_one(duration: Duration) -> FairnessReport {
+    let table = NMinusOne::new(PHILOSOPHERS);
+    simulate("n - 1", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+fn main() {
+    let seconds = std::env::args()
+        .nth(1)
+        .map(|s| s.parse().expect("duration in seconds"))
+        .unwrap_or(10);
+    let duration = Duration::from_secs(seconds);
+    println!(
+        "running {} philosophers for {} seconds per algorithm",
+        PHILOSOPHERS, seconds
+    );
+
+    let algorithms: Vec<fn(Duration) -> FairnessReport> = vec![
+        resource_hierarchy,
+        greedy_arbitrator,
+        fifo_arbitrator,
+        n_minus_one,
+    ];
+    let handles: Vec<_> = algorithms
+        .into_iter()
+        .map(|run| thread::spawn(move || run(duration)))
+        .collect();
+    let mut reports: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    reports.sort_by(|a, b| b.fairness().total_cmp(&a.fairness()));
+
+    println!(
+        "{:<20} | {:>11} | {:>19} | {:>9} | {:>9}",
+        "Algorithm", "Meals Total", "Jain Fairness Index", "Min Meals", "Max Meals"
+    );
+    for r in &reports {
+        println!(
+            "{:<20} | {:>11} | {:>19.4} | {:>9} | {:>9}",
+            r.algorithm,
+            r.total_meals(),
+            r.fairness(),
+            r.meals.iter().min().unwrap(),
+            r.meals.iter().max().unwrap()
+        );
+    }
+
+    for r in &reports {
+        let fairness = r.fairness();
+        assert!(
+            (1.0 / PHILOSOPHERS as f64..=1.0).contains(&fairness),
+            "{} has a fairness index of {} outside of [1/n, 1]",
+            r.algorithm,
+            fairness
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(meals: Vec<u64>) -> FairnessReport {
+        FairnessReport {
+            algorithm: "test",
+            meals,
+        }
+    }
+
+    #[test]
+    fn equal_meals_are_perfectly_fair() {
+        assert_eq!(report(vec![7; 5]).fairness(), 1.0);
+    }
+
+    #[test]
+    fn one_eater_scores_one_over_n() {
+        let fairness = report(vec![0, 0, 12, 0, 0]).fairness();
+        assert!((fairness - 1.0 / 5.0).abs() < 1e-12, "{}", fairness);
+    }
+
+    #[test]
+    fn nobody_eating_yet_is_fair() {
+        assert_eq!(report(vec![0; 5]).fairness(), 1.0);
+    }
+
+    #[test]
+    fn uneven_meals_stay_between_one_over_n_and_one() {
+        let fairness = report(vec![1, 2, 3, 40, 5]).fairness();
+        assert!((1.0 / 5.0..1.0).contains(&fairness), "{}", fairness);
+    }
+}
@@ -1,7 +1,11 @@
-use std::sync::{Arc, Mutex};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::Duration;
 
+mod philosopher_protocol;
+use philosopher_protocol::PhilosopherProtocol;
+
 struct Philosopher {
     name: String,
     left: usize,
@@ -19,15 +23,59 @@ impl Philosopher {
         }
     }
 
-    fn eat(&self, table: &Table) {
-        let _left = table.forks[self.left].lock().unwrap();
-        let _right = table.forks[self.right].lock().unwrap();
+    fn eat(&self, table: &Table) -> Result<(), Infallible> {
+        let mut meal = Meal {
+            philosopher: self,
+            table,
+            forks: None,
+        };
+        meal.request()?;
+        assert!(meal.can_proceed());
+        meal.acquire()?;
+        meal.use_resources()?;
+        meal.release()
+    }
+}
+
+/// a philosopher at the table for a meal, holding the forks in between `acquire` and `release`
+struct Meal<'a> {
+    philosopher: &'a Philosopher,
+    table: &'a Table,
+    forks: Option<(MutexGuard<'a, ()>, MutexGuard<'a, ()>)>,
+}
+
+impl PhilosopherProtocol for Meal<'_> {
+    type Error = Infallible;
+
+    /// nobody to ask, the forks are simply picked up
+    fn request(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    /// always, waiting for the forks one after the other in order never deadlocks
+    fn can_proceed(&self) -> bool {
+        true
+    }
 
-        println!("{} is eating.", self.name);
+    fn acquire(&mut self) -> Result<(), Infallible> {
+        let left = self.table.forks[self.philosopher.left].lock().unwrap();
+        let right = self.table.forks[self.philosopher.right].lock().unwrap();
+        self.forks = Some((left, right));
+        Ok(())
+    }
+
+    fn use_resources(&mut self) -> Result<(), Infallible> {
+        println!("{} is eating.", self.philosopher.name);
 
         thread::sleep(Duration::from_millis(1000));
 
-        println!("{} is done eating.", self.name);
+        println!("{} is done eating.", self.philosopher.name);
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Infallible> {
+        self.forks = None;
+        Ok(())
     }
 }
 
@@ -60,7 +108,7 @@ fn main() {
             let table = table.clone();
 
             thread::spawn(move || {
-                p.eat(&table);
+                let Ok(()) = p.eat(&table);
             })
         })
         .collect();
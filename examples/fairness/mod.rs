@@ -0,0 +1,14 @@
+//! How evenly the meals went around, shared by the examples that compare or report on it.
+
+/// Jain's fairness index over the meals of every philosopher, from 1/n when a single
+/// philosopher ate everything to 1 when all ate equally often. A table where nobody ate yet is
+/// fair.
+pub fn jain_index(meals: &[u64]) -> f64 {
+    let meals: Vec<f64> = meals.iter().map(|&m| m as f64).collect();
+    let sum: f64 = meals.iter().sum();
+    let sum_of_squares: f64 = meals.iter().map(|x| x * x).sum();
+    if sum_of_squares == 0.0 {
+        return 1.0;
+    }
+    sum * sum / (meals.len() as f64 * sum_of_squares)
+}
@@ -1,8 +1,14 @@
-use std::sync::{Arc, Mutex};
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::Duration;
 
-use semaphore::Semaphore;
+use semaphore::{Semaphore, SemaphoreGuard};
+
+mod philosopher_protocol;
+use philosopher_protocol::PhilosopherProtocol;
 
 struct Philosopher {
     name: String,
@@ -21,55 +27,114 @@ impl Philosopher {
         }
     }
 
-    fn eat(&self, table: &Table) {
-        let _left = table.forks[self.left].lock().unwrap();
+    /// waits for one of the seats `seats` lets in, then eats
+    fn eat(&self, table: &Table, seats: &Semaphore<()>) -> Result<(), Infallible> {
+        let mut meal = Meal {
+            philosopher: self,
+            table,
+            seats,
+            seat: Cell::new(None),
+            forks: None,
+        };
+        meal.request()?;
+        while !meal.can_proceed() {}
+        meal.acquire()?;
+        meal.use_resources()?;
+        meal.release()
+    }
+}
+
+/// a philosopher at the table for a meal, holding a seat from `can_proceed` and the forks from
+/// `acquire` until `release`
+struct Meal<'a> {
+    philosopher: &'a Philosopher,
+    table: &'a Table,
+    seats: &'a Semaphore<()>,
+    seat: Cell<Option<SemaphoreGuard<()>>>,
+    forks: Option<(MutexGuard<'a, ()>, MutexGuard<'a, ()>)>,
+}
+
+impl PhilosopherProtocol for Meal<'_> {
+    type Error = Infallible;
+
+    /// nobody to ask, `can_proceed` tries to get a seat
+    fn request(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    /// Once there is a seat. With at most all but one philosopher reaching for the forks, one
+    /// of them always gets both, so waiting for them never deadlocks.
+    fn can_proceed(&self) -> bool {
+        let seat = self.seat.take().or_else(|| self.seats.try_access().ok());
+        let seated = seat.is_some();
+        self.seat.set(seat);
+        seated
+    }
+
+    fn acquire(&mut self) -> Result<(), Infallible> {
+        let left = self.table.forks[self.philosopher.left].lock().unwrap();
         //thread::sleep(Duration::from_millis(1));
-        let _right = table.forks[self.right].lock().unwrap();
+        let right = self.table.forks[self.philosopher.right].lock().unwrap();
+        self.forks = Some((left, right));
+        Ok(())
+    }
 
-        println!("{} is eating.", self.name);
+    fn use_resources(&mut self) -> Result<(), Infallible> {
+        println!("{} is eating.", self.philosopher.name);
 
-        thread::sleep(Duration::from_millis(1000));
+        thread::sleep(self.table.eating);
 
-        println!("{} is done eating.", self.name);
+        println!("{} is done eating.", self.philosopher.name);
+        Ok(())
+    }
+
+    /// the forks first, then the seat
+    fn release(&mut self) -> Result<(), Infallible> {
+        self.forks = None;
+        self.seat.set(None);
+        Ok(())
     }
 }
 
 struct Table {
     forks: Vec<Mutex<()>>,
+    /// how long a meal takes
+    eating: Duration,
 }
 
-fn main() {
+struct SimulationReport {
+    meals: usize,
+}
+
+/// Seats the philosophers around as many forks, lets at most all but one of them reach for the
+/// forks at the same time and waits until each of them ate `meals` meals of `eating`.
+fn run_n_minus_one(names: &[&str], meals: usize, eating: Duration) -> SimulationReport {
+    let n = names.len();
     let table = Arc::new(Table {
-        forks: vec![
-            Mutex::new(()),
-            Mutex::new(()),
-            Mutex::new(()),
-            Mutex::new(()),
-            Mutex::new(()),
-        ],
+        forks: (0..n).map(|_| Mutex::new(())).collect(),
+        eating,
     });
-
-    let philosophers = vec![
-        Philosopher::new("Baruch Spinoza", 0, 1),
-        Philosopher::new("Gilles Deleuze", 1, 2),
-        Philosopher::new("Karl Marx", 2, 3),
-        Philosopher::new("Friedrich Nietzsche", 3, 4),
-        Philosopher::new("Michel Foucault", 4, 0),
-    ];
+    let philosophers: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Philosopher::new(name, i, (i + 1) % n))
+        .collect();
     let n_minus_one = philosophers.len() - 1;
 
     let sem = Arc::new(Semaphore::new(n_minus_one, ()));
+    let eaten = Arc::new(AtomicUsize::new(0));
 
     let handles: Vec<_> = philosophers
         .into_iter()
         .map(|p| {
             let table = table.clone();
             let sem = sem.clone();
+            let eaten = eaten.clone();
 
-            thread::spawn(move || loop {
-                if sem.try_access().is_ok() {
-                    p.eat(&table);
-                    break;
+            thread::spawn(move || {
+                for _ in 0..meals {
+                    let Ok(()) = p.eat(&table, &sem);
+                    eaten.fetch_add(1, Ordering::Relaxed);
                 }
             })
         })
@@ -78,4 +143,37 @@ fn main() {
     for h in handles {
         h.join().unwrap();
     }
+    SimulationReport {
+        meals: eaten.load(Ordering::Relaxed),
+    }
+}
+
+fn main() {
+    let names = [
+        "Baruch Spinoza",
+        "Gilles Deleuze",
+        "Karl Marx",
+        "Friedrich Nietzsche",
+        "Michel Foucault",
+    ];
+    let report = run_n_minus_one(&names, 1, Duration::from_millis(1000));
+    assert_eq!(report.meals, names.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everyone_eats_every_meal() {
+        let names = ["Baruch Spinoza", "Gilles Deleuze", "Karl Marx"];
+        let report = run_n_minus_one(&names, 10, Duration::from_millis(1));
+        assert_eq!(report.meals, 30);
+    }
+
+    #[test]
+    fn two_philosophers_take_turns() {
+        let report = run_n_minus_one(&["Karl Marx", "Michel Foucault"], 10, Duration::ZERO);
+        assert_eq!(report.meals, 20);
+    }
 }
@@ -0,0 +1,206 @@
+//! n - 1 at a table that grows while the philosophers eat.
+//!
+//! A fork is only allocated when a philosopher first reaches for it. A new seat comes with a new
+//! fork, put between the last and the first one, and lets one more philosopher reach for the
+//! forks at the same time. Seats are added while nobody holds a fork, so nobody ever holds a
+//! fork that moved to a different neighbour.
+
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::Duration;
+
+type Seat = usize;
+type ForkId = usize;
+
+const MEALS: usize = 3;
+const EATING: Duration = Duration::from_millis(100);
+
+struct Philosopher {
+    name: String,
+    seat: Seat,
+}
+
+impl Philosopher {
+    fn new(name: &str, seat: Seat) -> Philosopher {
+        Philosopher {
+            name: name.to_string(),
+            seat,
+        }
+    }
+
+    /// waits until it may reach for the forks, then eats
+    fn eat(&self, table: &Table) {
+        table.reach();
+        // the table cannot grow in the middle of a meal
+        let forks = table.forks.read().unwrap();
+        let n = forks.len();
+        let (left, right) = (self.seat, (self.seat + 1) % n);
+        let _left = forks[left].get_or_init(|| Mutex::new(())).lock().unwrap();
+        let _right = forks[right].get_or_init(|| Mutex::new(())).lock().unwrap();
+
+        println!("{} is eating with forks {} and {}.", self.name, left, right);
+
+        thread::sleep(EATING);
+
+        println!("{} is done eating.", self.name);
+        table.done_reaching();
+    }
+}
+
+struct Table {
+    /// fork `i` lies between seat `i` and the seat before
+    forks: RwLock<Vec<OnceLock<Mutex<()>>>>,
+    /// philosophers reaching for their forks or eating, at most `Reaching::limit`
+    reaching: Mutex<Reaching>,
+    /// notified when someone stops reaching or the limit grows
+    place_freed: Condvar,
+}
+
+struct Reaching {
+    philosophers: usize,
+    /// one less than the forks on the table
+    limit: usize,
+}
+
+impl Table {
+    /// table with `n` forks, none of them allocated yet
+    fn with_n_seats(n: usize) -> Self {
+        assert!(n >= 2, "a table needs at least two seats");
+        Table {
+            forks: RwLock::new((0..n).map(|_| OnceLock::new()).collect()),
+            reaching: Mutex::new(Reaching {
+                philosophers: 0,
+                limit: n - 1,
+            }),
+            place_freed: Condvar::new(),
+        }
+    }
+
+    /// blocks while n - 1 philosophers already reach for their forks
+    fn reach(&self) {
+        let reaching = self.reaching.lock().unwrap();
+        let mut reaching = self
+            .place_freed
+            .wait_while(reaching, |r| r.philosophers >= r.limit)
+            .unwrap();
+        reaching.philosophers += 1;
+    }
+
+    fn done_reaching(&self) {
+        self.reaching.lock().unwrap().philosophers -= 1;
+        self.place_freed.notify_one();
+    }
+
+    /// Seat for one more philosopher with a fork of its own, after the last seat. Waits until
+    /// every meal in progress is over.
+    fn add_fork(&self) -> Seat {
+        let mut forks = self.forks.write().unwrap();
+        forks.push(OnceLock::new());
+        self.reaching.lock().unwrap().limit = forks.len() - 1;
+        self.place_freed.notify_all();
+        forks.len() - 1
+    }
+
+    fn is_allocated(&self, fork: ForkId) -> bool {
+        self.forks.read().unwrap()[fork].get().is_some()
+    }
+
+    fn allocated_forks(&self) -> usize {
+        let forks = self.forks.read().unwrap();
+        forks.iter().filter(|fork| fork.get().is_some()).count()
+    }
+}
+
+/// lets `p` eat `meals` times, returns how often it ate
+fn seat(p: Philosopher, table: &Arc<Table>, meals: usize) -> thread::JoinHandle<usize> {
+    let table = table.clone();
+    thread::spawn(move || {
+        for _ in 0..meals {
+            p.eat(&table);
+        }
+        meals
+    })
+}
+
+fn main() {
+    let names = [
+        "Baruch Spinoza",
+        "Gilles Deleuze",
+        "Karl Marx",
+        "Friedrich Nietzsche",
+    ];
+    let table = Arc::new(Table::with_n_seats(names.len()));
+    let mut handles: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| seat(Philosopher::new(name, i), &table, MEALS))
+        .collect();
+
+    thread::sleep(EATING * 2);
+    let new_seat = table.add_fork();
+    println!(
+        "Michel Foucault sits down at seat {}, {} forks allocated so far.",
+        new_seat,
+        table.allocated_forks()
+    );
+    handles.push(seat(
+        Philosopher::new("Michel Foucault", new_seat),
+        &table,
+        MEALS,
+    ));
+
+    let meals: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    println!(
+        "all done, {} meals at {} forks, the one of Michel Foucault {}",
+        meals,
+        table.allocated_forks(),
+        if table.is_allocated(new_seat) {
+            "among them"
+        } else {
+            "never used"
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn fork_of_a_new_seat_is_allocated() {
+        let table = Arc::new(Table::with_n_seats(4));
+        assert_eq!(table.allocated_forks(), 0);
+        let mut handles: Vec<_> = (0..4)
+            .map(|i| seat(Philosopher::new("Old", i), &table, MEALS))
+            .collect();
+        thread::sleep(EATING);
+        let new_seat = table.add_fork();
+        assert_eq!(new_seat, 4);
+        handles.push(seat(Philosopher::new("New", new_seat), &table, MEALS));
+        let meals: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(meals, 5 * MEALS);
+        assert!(table.is_allocated(new_seat));
+        assert_eq!(table.allocated_forks(), 5);
+    }
+
+    #[test]
+    fn refused_philosopher_waits_until_a_fork_is_added() {
+        let table = Arc::new(Table::with_n_seats(2));
+        // the only one of 2 - 1 allowed
+        table.reach();
+        let (reached, reached_rx) = mpsc::channel();
+        let waiting = {
+            let table = table.clone();
+            thread::spawn(move || {
+                table.reach();
+                reached.send(()).unwrap();
+            })
+        };
+        assert!(reached_rx.recv_timeout(EATING).is_err());
+        table.add_fork();
+        reached_rx.recv_timeout(EATING * 10).unwrap();
+        waiting.join().unwrap();
+        assert_eq!(table.reaching.lock().unwrap().philosophers, 2);
+    }
+}
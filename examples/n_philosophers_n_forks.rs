@@ -0,0 +1,89 @@
+//! Chandy/Misra on a table that is not a ring.
+//!
+//! Five philosophers share seven forks, every fork lies between the two philosophers of a random
+//! pair. Nothing in the algorithm depends on the ring, a philosopher needs every fork it shares
+//! and asks the neighbour on the other side of each missing one. Handing each fork to the
+//! philosopher with the lower id keeps the initial precedence graph acyclic, as in the ring.
+//!
+//! `cargo run --release --example n_philosophers_n_forks -- 7` picks another seed for the table.
+
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{ForkGraph, Philosopher, SimulationConfig};
+
+/// most forks one philosopher of a random table of five can share
+const SEATS: usize = 4;
+
+fn main() {
+    let seed = std::env::args()
+        .nth(1)
+        .map(|s| s.parse().expect("seed"))
+        .unwrap_or(42);
+    let names = [
+        "Baruch Spinoza",
+        "Gilles Deleuze",
+        "Karl Marx",
+        "Friedrich Nietzsche",
+        "Michel Foucault",
+    ];
+    let topology = ForkGraph::random(names.len(), 7, seed).expect("seven forks for five");
+
+    for (i, name) in names.iter().enumerate() {
+        let adjacent: Vec<_> = topology
+            .adjacency(i)
+            .iter()
+            .map(|(fork, p)| format!("fork {} with {}", fork, p))
+            .collect();
+        println!("{} {}: {}", i, name, adjacent.join(", "));
+    }
+
+    let config = SimulationConfig {
+        eating_duration: Duration::from_millis(50),
+        thinking_duration: Duration::from_millis(50),
+        ..SimulationConfig::new(names.iter().map(|n| n.to_string()).collect())
+    };
+    let philosophers: Vec<Philosopher<SEATS>> =
+        topology.build(&config).expect("room for the forks");
+    let philosophers = chandy_misra_table::run_table(philosophers, Duration::from_secs(10))
+        .expect("philosophers left early");
+    for p in philosophers {
+        println!("{} {} ate {} times", p.id, p.name, p.meals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_table_of_five_shares_seven_forks() {
+        let topology = ForkGraph::random(5, 7, 42).unwrap();
+        assert_eq!(topology, ForkGraph::random(5, 7, 42).unwrap());
+        assert_eq!(topology.edges.len(), 7);
+        for p in 0..5 {
+            let adjacent = topology.adjacency(p);
+            assert!(
+                !adjacent.is_empty() && adjacent.len() <= SEATS,
+                "{:?}",
+                adjacent
+            );
+        }
+    }
+
+    #[test]
+    fn everyone_eats_at_a_random_table() {
+        let topology = ForkGraph::random(5, 7, 7).unwrap();
+        let config = SimulationConfig {
+            eating_duration: Duration::from_millis(1),
+            meals: Some(3),
+            ..SimulationConfig::new((0..5).map(|i| format!("philosopher {}", i)).collect())
+        };
+        let philosophers: Vec<Philosopher<SEATS>> = topology.build(&config).unwrap();
+        let philosophers =
+            chandy_misra_table::run_table(philosophers, Duration::from_secs(60)).unwrap();
+        assert!(philosophers.iter().all(|p| p.meals == 3));
+    }
+}
@@ -0,0 +1,54 @@
+//! Names for the philosophers at the table, one name per line in the lists next to this file.
+
+use super::chandy_misra_table::Rng;
+
+const WESTERN: &str = include_str!("western.txt");
+const EASTERN: &str = include_str!("eastern.txt");
+
+/// Unique philosopher names without end, "Philosopher N" once the list is used up
+pub struct NameGenerator {
+    names: Vec<&'static str>,
+    next: usize,
+}
+
+impl NameGenerator {
+    fn from_names(names: Vec<&'static str>) -> Self {
+        Self { names, next: 0 }
+    }
+
+    pub fn western() -> Self {
+        Self::from_names(WESTERN.lines().collect())
+    }
+
+    pub fn eastern() -> Self {
+        Self::from_names(EASTERN.lines().collect())
+    }
+
+    /// "Philosopher 0", "Philosopher 1" and so on, for tables where names do not matter
+    pub fn numbered() -> Self {
+        Self::from_names(vec![])
+    }
+
+    /// both lists shuffled, the same seed gives the same table
+    pub fn random(seed: u64) -> Self {
+        let mut names: Vec<_> = WESTERN.lines().chain(EASTERN.lines()).collect();
+        let mut rng = Rng(seed.max(1));
+        for i in (1..names.len()).rev() {
+            names.swap(i, rng.below(i + 1));
+        }
+        Self::from_names(names)
+    }
+}
+
+impl Iterator for NameGenerator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let name = match self.names.get(self.next) {
+            Some(name) => name.to_string(),
+            None => format!("Philosopher {}", self.next),
+        };
+        self.next += 1;
+        Some(name)
+    }
+}
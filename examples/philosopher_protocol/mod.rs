@@ -0,0 +1,32 @@
+//! The steps of a meal that every solution goes through, however it gets hold of the forks.
+
+/// Request the forks, wait until they can be had, acquire them, eat with them and release them.
+/// A philosopher loops over
+///
+/// ```text
+/// p.request()?;
+/// while !p.can_proceed() {
+///     // wait for the neighbours, answering them
+/// }
+/// p.acquire()?;
+/// p.use_resources()?;
+/// p.release()?;
+/// ```
+pub trait PhilosopherProtocol {
+    /// why a step failed, `std::convert::Infallible` for protocols that cannot fail
+    type Error;
+
+    /// let whoever hands out the forks know they are wanted
+    fn request(&mut self) -> Result<(), Self::Error>;
+
+    /// whether `acquire` gets the forks without waiting for anybody
+    fn can_proceed(&self) -> bool;
+
+    fn acquire(&mut self) -> Result<(), Self::Error>;
+
+    /// eat
+    fn use_resources(&mut self) -> Result<(), Self::Error>;
+
+    /// put the forks down for the neighbours
+    fn release(&mut self) -> Result<(), Self::Error>;
+}
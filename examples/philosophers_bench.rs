@@ -0,0 +1,151 @@
+//! Time to first meal of every philosopher, for each algorithm.
+//!
+//! All philosophers get hungry at the same moment at a table nobody has eaten at yet, the time
+//! until each of them starts eating is measured. Every algorithm runs `ROUNDS` times from cold,
+//! the table shows the median per philosopher in milliseconds.
+
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+pub mod table_harness;
+
+use chandy_misra_table::{ring_of_n, run_table, SimulationConfig};
+use table_harness::{Arbitrator, NMinusOne, ResourceHierarchy};
+
+const PHILOSOPHERS: usize = 5;
+const EATING: Duration = Duration::from_millis(10);
+const ROUNDS: usize = 5;
+
+/// one cold start, time to first meal per philosopher
+type Algorithm = fn() -> Vec<Duration>;
+
+/// Runs `eat` for every philosopher once, all starting together. `eat` has to call the callback
+/// it gets right when philosopher `i` starts eating.
+fn cold_start<F>(eat: F) -> Vec<Duration>
+where
+    F: Fn(usize, &dyn Fn()) + Send + Sync + 'static,
+{
+    let eat = Arc::new(eat);
+    let start = Arc::new(Barrier::new(PHILOSOPHERS));
+    let handles: Vec<_> = (0..PHILOSOPHERS)
+        .map(|i| {
+            let eat = eat.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                start.wait();
+                let hungry_since = Instant::now();
+                let first_meal = Mutex::new(Duration::default());
+                eat(i, &|| *first_meal.lock().unwrap() = hungry_since.elapsed());
+                first_meal.into_inner().unwrap()
+            })
+        })
+        .collect();
+    handles.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+fn resource_hierarchy() -> Vec<Duration> {
+    let table = ResourceHierarchy::new(PHILOSOPHERS);
+    cold_start(move |i, eating| {
+        table.eat(i, || {
+            eating();
+            thread::sleep(EATING);
+        })
+    })
+}
+
+fn arbitrator() -> Vec<Duration> {
+    let table = Arbitrator::new(PHILOSOPHERS);
+    cold_start(move |i, eating| {
+        table.eat(i, || {
+            eating();
+            thread::sleep(EATING);
+        })
+    })
+}
+
+fn n_minus_one() -> Vec<Duration> {
+    let table = NMinusOne::new(PHILOSOPHERS);
+    cold_start(move |i, eating| {
+        table.eat(i, || {
+            eating();
+            thread::sleep(EATING);
+        })
+    })
+}
+
+/// Chandy/Misra as `chandy_misra.rs` runs it, with the forks initially dirty at the lower id, so
+/// philosopher 0 holds both of its forks and eats without a single message
+fn chandy_misra() -> Vec<Duration> {
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        meals: Some(1),
+        ..SimulationConfig::new(
+            (0..PHILOSOPHERS)
+                .map(|i| format!("Philosopher {}", i))
+                .collect(),
+        )
+    };
+    let philosophers = ring_of_n(&config)
+        .and_then(|philosophers| run_table(philosophers, Duration::from_secs(60)))
+        .expect("philosophers left early");
+    // the wait for the first meal, from getting hungry at the start
+    philosophers
+        .iter()
+        .map(|p| p.eating_history.front().copied().unwrap_or_default())
+        .collect()
+}
+
+/// median time to first meal of every philosopher over `ROUNDS` cold starts
+fn bench(run: Algorithm) -> Vec<Duration> {
+    let rounds: Vec<_> = (0..ROUNDS).map(|_| run()).collect();
+    (0..PHILOSOPHERS)
+        .map(|p| {
+            let mut times: Vec<_> = rounds.iter().map(|r| r[p]).collect();
+            times.sort();
+            times[ROUNDS / 2]
+        })
+        .collect()
+}
+
+fn mean(times: &[Duration]) -> Duration {
+    times.iter().sum::<Duration>() / times.len() as u32
+}
+
+fn main() {
+    let algorithms: Vec<(&str, Algorithm)> = vec![
+        ("resource hierarchy", resource_hierarchy),
+        ("arbitrator", arbitrator),
+        ("n - 1", n_minus_one),
+        ("Chandy/Misra", chandy_misra),
+    ];
+
+    print!("{:<20}", "Algorithm");
+    for p in 0..PHILOSOPHERS {
+        print!(" | {:>6}", format!("P{}", p));
+    }
+    println!(" | {:>6}", "mean");
+    let mut results = vec![];
+    for (name, run) in algorithms {
+        let times = bench(run);
+        print!("{:<20}", name);
+        for t in &times {
+            print!(" | {:>6.2}", t.as_secs_f64() * 1000.0);
+        }
+        println!(" | {:>6.2}", mean(&times).as_secs_f64() * 1000.0);
+        results.push((name, times));
+    }
+
+    let times_of = |algorithm| &results.iter().find(|(n, _)| *n == algorithm).unwrap().1;
+    // philosopher 0 starts with both forks in Chandy/Misra
+    let chandy_misra_first = times_of("Chandy/Misra")[0];
+    let n_minus_one_mean = mean(times_of("n - 1"));
+    assert!(
+        chandy_misra_first <= n_minus_one_mean,
+        "Chandy/Misra philosopher 0 waited {:?}, n - 1 philosophers only {:?}",
+        chandy_misra_first,
+        n_minus_one_mean
+    );
+}
@@ -0,0 +1,157 @@
+//! Runs the resource hierarchy, arbitrator, two-phase locking, n - 1 and Chandy/Misra solutions
+//! side by side with 20 philosophers and compares how much they got to eat.
+//!
+//! `cargo run --release --example stress_test -- 5` shortens the default run of 60 seconds.
+
+use std::thread;
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+pub mod table_harness;
+
+use chandy_misra_table::{RingTopologyBuilder, SimulationConfig};
+use table_harness::{Arbitrator, NMinusOne, ResourceHierarchy, Simulation, TwoPhaseLocking};
+
+const PHILOSOPHERS: usize = 20;
+const EATING: Duration = Duration::from_millis(10);
+const THINKING: Duration = Duration::from_millis(10);
+
+struct SimulationReport {
+    algorithm: &'static str,
+    meals: Vec<u64>,
+    /// longest time each philosopher waited for a meal
+    max_starvation: Vec<Duration>,
+}
+
+impl SimulationReport {
+    fn total_meals(&self) -> u64 {
+        self.meals.iter().sum()
+    }
+
+    fn worst_starvation(&self) -> Duration {
+        self.max_starvation
+            .iter()
+            .max()
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// `table_harness::simulate` with the philosophers of this table
+fn simulate<F>(algorithm: &'static str, duration: Duration, eat: F) -> SimulationReport
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let Simulation {
+        meals,
+        max_starvation,
+    } = table_harness::simulate(PHILOSOPHERS, duration, THINKING, eat);
+    SimulationReport {
+        algorithm,
+        meals,
+        max_starvation,
+    }
+}
+
+fn resource_hierarchy(duration: Duration) -> SimulationReport {
+    let table = ResourceHierarchy::new(PHILOSOPHERS);
+    simulate("resource hierarchy", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+fn arbitrator(duration: Duration) -> SimulationReport {
+    let table = Arbitrator::new(PHILOSOPHERS);
+    simulate("arbitrator", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+fn two_phase_locking(duration: Duration) -> SimulationReport {
+    let table = TwoPhaseLocking::new(PHILOSOPHERS);
+    simulate("two-phase locking", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+fn n_minus_one(duration: Duration) -> SimulationReport {
+    let table = NMinusOne::new(PHILOSOPHERS);
+    simulate("n - 1", duration, move |i| {
+        table.eat(i, || thread::sleep(EATING))
+    })
+}
+
+fn chandy_misra(duration: Duration) -> SimulationReport {
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        thinking_duration: THINKING,
+        ..SimulationConfig::new(
+            (0..PHILOSOPHERS)
+                .map(|i| format!("Philosopher {}", i))
+                .collect(),
+        )
+    };
+    let philosophers = RingTopologyBuilder::new(PHILOSOPHERS)
+        .build(&config)
+        .and_then(|philosophers| chandy_misra_table::run_table(philosophers, duration))
+        .expect("philosophers left early");
+    SimulationReport {
+        algorithm: "Chandy/Misra",
+        meals: philosophers.iter().map(|p| p.meals).collect(),
+        // until the end of the meal, as for the other algorithms
+        max_starvation: philosophers
+            .iter()
+            .map(|p| Duration::from_nanos(p.statistics_snapshot().max_starvation_ns) + EATING)
+            .collect(),
+    }
+}
+
+fn main() {
+    let seconds = std::env::args()
+        .nth(1)
+        .map(|s| s.parse().expect("duration in seconds"))
+        .unwrap_or(60);
+    let duration = Duration::from_secs(seconds);
+    println!(
+        "running {} philosophers for {} seconds per algorithm",
+        PHILOSOPHERS, seconds
+    );
+
+    let algorithms: Vec<fn(Duration) -> SimulationReport> = vec![
+        resource_hierarchy,
+        arbitrator,
+        two_phase_locking,
+        n_minus_one,
+        chandy_misra,
+    ];
+    let handles: Vec<_> = algorithms
+        .into_iter()
+        .map(|run| thread::spawn(move || run(duration)))
+        .collect();
+    let reports: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    println!(
+        "{:<20} | {:>8} | {:>8} | {:>8} | {:>14}",
+        "Algorithm", "Meals", "Min", "Max", "Max starvation"
+    );
+    for r in &reports {
+        println!(
+            "{:<20} | {:>8} | {:>8} | {:>8} | {:>12}ms",
+            r.algorithm,
+            r.total_meals(),
+            r.meals.iter().min().unwrap(),
+            r.meals.iter().max().unwrap(),
+            r.worst_starvation().as_millis()
+        );
+    }
+
+    for r in &reports {
+        assert!(r.total_meals() >= 10, "{} barely ate", r.algorithm);
+        assert!(
+            r.worst_starvation() < Duration::from_secs(30),
+            "{} starved a philosopher",
+            r.algorithm
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Seats the philosophers of the examples that compare algorithms. An algorithm only decides how
+//! philosopher `i` gets hold of forks `left_right(i, n)`, the harness does the rest. The
+//! algorithms here are shared by those examples, each `eat` picks up the forks of philosopher
+//! `i`, has the meal and puts them down again.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use semaphore::Semaphore;
+
+/// meals and the longest time hungry of every philosopher
+pub struct Simulation {
+    pub meals: Vec<u64>,
+    /// longest time from getting hungry to the end of a meal
+    pub max_starvation: Vec<Duration>,
+}
+
+/// Lets `philosophers` philosophers call `eat` and think for `thinking` in turns until `duration`
+/// has passed. `eat` has to pick up the forks of philosopher `i`, eat, and put them down again.
+pub fn simulate<F>(
+    philosophers: usize,
+    duration: Duration,
+    thinking: Duration,
+    eat: F,
+) -> Simulation
+where
+    F: Fn(usize) + Send + Sync + 'static,
+{
+    let eat = Arc::new(eat);
+    let deadline = Instant::now() + duration;
+    let handles: Vec<_> = (0..philosophers)
+        .map(|i| {
+            let eat = eat.clone();
+            thread::spawn(move || {
+                let mut meals = 0;
+                let mut max_starvation = Duration::default();
+                let mut hungry_since = Instant::now();
+                while Instant::now() < deadline {
+                    eat(i);
+                    meals += 1;
+                    max_starvation = max_starvation.max(hungry_since.elapsed());
+                    thread::sleep(thinking);
+                    hungry_since = Instant::now();
+                }
+                (meals, max_starvation)
+            })
+        })
+        .collect();
+
+    let (meals, max_starvation) = handles.into_iter().map(|h| h.join().unwrap()).unzip();
+    Simulation {
+        meals,
+        max_starvation,
+    }
+}
+
+/// Philosopher `i` of `n` uses fork `i` as left and fork `i + 1` as right fork
+pub fn left_right(i: usize, n: usize) -> (usize, usize) {
+    (i, (i + 1) % n)
+}
+
+/// A mutex per fork, every philosopher picks up the fork with the lower id first
+pub struct ResourceHierarchy {
+    pub forks: Vec<Mutex<()>>,
+}
+
+impl ResourceHierarchy {
+    pub fn new(n: usize) -> Self {
+        Self {
+            forks: (0..n).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    pub fn eat(&self, i: usize, meal: impl FnOnce()) {
+        let (left, right) = left_right(i, self.forks.len());
+        let _first = self.forks[left.min(right)].lock().unwrap();
+        let _second = self.forks[left.max(right)].lock().unwrap();
+        meal();
+    }
+}
+
+/// A waiter handing out one fork at a time, the last free fork only goes to a right hand. The
+/// same strategy as the arbitrator example.
+pub struct Arbitrator {
+    /// which forks are taken
+    pub waiter: Mutex<Vec<bool>>,
+}
+
+impl Arbitrator {
+    pub fn new(n: usize) -> Self {
+        Self {
+            waiter: Mutex::new(vec![false; n]),
+        }
+    }
+
+    fn take_fork(&self, fork: usize, right_hand: bool) -> bool {
+        let mut forks = self.waiter.lock().unwrap();
+        let available = forks.iter().filter(|x| !*x).count();
+        if !forks[fork] && (right_hand || available > 1) {
+            forks[fork] = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn eat(&self, i: usize, meal: impl FnOnce()) {
+        let (left, right) = left_right(i, self.waiter.lock().unwrap().len());
+        while !self.take_fork(left, false) {
+            thread::yield_now();
+        }
+        while !self.take_fork(right, true) {
+            thread::yield_now();
+        }
+        meal();
+        let mut forks = self.waiter.lock().unwrap();
+        forks[left] = false;
+        forks[right] = false;
+    }
+}
+
+/// A waiter handing out both forks of a philosopher together or none of them
+pub struct TwoPhaseLocking {
+    /// which forks are taken
+    pub waiter: Mutex<Vec<bool>>,
+}
+
+impl TwoPhaseLocking {
+    pub fn new(n: usize) -> Self {
+        Self {
+            waiter: Mutex::new(vec![false; n]),
+        }
+    }
+
+    pub fn eat(&self, i: usize, meal: impl FnOnce()) {
+        let (left, right) = loop {
+            let mut forks = self.waiter.lock().unwrap();
+            let (left, right) = left_right(i, forks.len());
+            if !forks[left] && !forks[right] {
+                forks[left] = true;
+                forks[right] = true;
+                break (left, right);
+            }
+            drop(forks);
+            thread::yield_now();
+        };
+        meal();
+        let mut forks = self.waiter.lock().unwrap();
+        forks[left] = false;
+        forks[right] = false;
+    }
+}
+
+/// A mutex per fork and one seat fewer than philosophers at the table
+pub struct NMinusOne {
+    pub forks: Vec<Mutex<()>>,
+    pub seats: Semaphore<()>,
+}
+
+impl NMinusOne {
+    pub fn new(n: usize) -> Self {
+        Self {
+            forks: (0..n).map(|_| Mutex::new(())).collect(),
+            seats: Semaphore::new(n - 1, ()),
+        }
+    }
+
+    pub fn eat(&self, i: usize, meal: impl FnOnce()) {
+        let (left, right) = left_right(i, self.forks.len());
+        loop {
+            if let Ok(_seat) = self.seats.try_access() {
+                let _left = self.forks[left].lock().unwrap();
+                let _right = self.forks[right].lock().unwrap();
+                meal();
+                return;
+            }
+            thread::yield_now();
+        }
+    }
+}
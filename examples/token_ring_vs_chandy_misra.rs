@@ -0,0 +1,241 @@
+//! A token going round the table against Chandy/Misra, with the same philosophers eating and
+//! thinking for the same time.
+//!
+//! With the token ring only the philosopher holding the token may eat, it passes the token on
+//! after its meal or right away when it is not hungry. Nobody can starve or skip the line and
+//! there is nothing to get wrong, but the meals are served one after the other. With
+//! Chandy/Misra every philosopher that has the two forks it shares with its neighbours eats, so
+//! up to half the table eats at once.
+//!
+//! The thinking times vary from meal to meal. When every philosopher thinks for exactly as long as
+//! a meal takes, Chandy/Misra lines them up behind each other: each one holds the clean fork it was
+//! just given and waits for the other one, and only the head of the line eats.
+//!
+//! All times go through a `SimulationClock`, `cargo run --release --example
+//! token_ring_vs_chandy_misra -- 0.1` runs ten times faster.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod chandy_misra_table;
+mod fairness;
+mod philosopher_protocol;
+
+use chandy_misra_table::{
+    run_steps, DiningPhilosophersError, Philosopher, PhilosopherSteps, RingTopologyBuilder,
+    SimulationClock, SimulationConfig,
+};
+
+const PHILOSOPHERS: usize = 5;
+const EATING: Duration = Duration::from_millis(100);
+const THINKING: Duration = Duration::from_millis(100);
+const RUN: Duration = Duration::from_secs(10);
+
+/// meals and the longest wait for them of every philosopher
+struct Report {
+    algorithm: &'static str,
+    meals: Vec<u64>,
+    longest_wait: Vec<Duration>,
+    run: Duration,
+}
+
+impl Report {
+    fn throughput(&self) -> f64 {
+        self.meals.iter().sum::<u64>() as f64 / self.run.as_secs_f64()
+    }
+
+    /// Jain's fairness index of the meals, see `fairness::jain_index`
+    fn fairness(&self) -> f64 {
+        fairness::jain_index(&self.meals)
+    }
+
+    fn max_starvation(&self) -> Duration {
+        self.longest_wait.iter().copied().max().unwrap_or_default()
+    }
+}
+
+/// Meals and longest wait of one philosopher, the wait in time of the simulation
+struct Tally {
+    meals: u64,
+    longest_wait: Duration,
+}
+
+impl Tally {
+    fn new() -> Self {
+        Self {
+            meals: 0,
+            longest_wait: Duration::ZERO,
+        }
+    }
+
+    fn ate_after(&mut self, hungry_since: Instant, clock: SimulationClock) {
+        self.meals += 1;
+        let wait = hungry_since.elapsed().div_f64(clock.multiplier);
+        self.longest_wait = self.longest_wait.max(wait);
+    }
+}
+
+/// Thinking time of `philosopher` after its `meal`, between half and one and a half `THINKING`.
+/// Philosophers that all think for exactly as long as a meal takes line up behind each other.
+fn thinking(philosopher: usize, meal: u64) -> Duration {
+    let mut x = (philosopher as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ meal;
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    THINKING.mul_f64(0.5 + (x % 1000) as f64 / 1000.0)
+}
+
+fn report(algorithm: &'static str, run: Duration, tallies: Vec<Tally>) -> Report {
+    Report {
+        algorithm,
+        meals: tallies.iter().map(|t| t.meals).collect(),
+        longest_wait: tallies.iter().map(|t| t.longest_wait).collect(),
+        run,
+    }
+}
+
+struct Token;
+
+fn token_ring(clock: SimulationClock) -> Report {
+    let deadline = Instant::now() + clock.scaled(RUN);
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+        (0..PHILOSOPHERS).map(|_| mpsc::channel::<Token>()).unzip();
+    senders[0].send(Token).unwrap();
+    let handles: Vec<_> = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let next = senders[(i + 1) % PHILOSOPHERS].clone();
+            thread::spawn(move || {
+                let mut tally = Tally::new();
+                let mut hungry_since = Instant::now();
+                while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                    if token.recv_timeout(wait).is_err() {
+                        break;
+                    }
+                    if Instant::now() >= hungry_since {
+                        clock.sleep(EATING);
+                        tally.ate_after(hungry_since, clock);
+                        hungry_since = Instant::now() + clock.scaled(thinking(i, tally.meals));
+                    }
+                    // the last one to leave takes the token along
+                    let _ = next.send(Token);
+                }
+                tally
+            })
+        })
+        .collect();
+    let tallies = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    report("token ring", RUN, tallies)
+}
+
+/// A Chandy/Misra philosopher thinking for `thinking` of its next meal after every meal
+struct VaryingThinking(Philosopher);
+
+impl PhilosopherSteps for VaryingThinking {
+    fn philosopher(&mut self) -> &mut Philosopher {
+        &mut self.0
+    }
+
+    fn request(&mut self) -> Result<(), DiningPhilosophersError> {
+        PhilosopherSteps::request(&mut self.0)
+    }
+
+    fn eat(&mut self) -> Result<(), DiningPhilosophersError> {
+        let p = &mut self.0;
+        p.thinking_duration = thinking(p.id.index(), p.meals + 1);
+        PhilosopherSteps::eat(p)
+    }
+
+    fn handle_requests(&mut self) -> Result<(), DiningPhilosophersError> {
+        PhilosopherSteps::handle_requests(&mut self.0)
+    }
+
+    fn run(&mut self) -> Result<(), DiningPhilosophersError> {
+        run_steps(self)
+    }
+}
+
+fn chandy_misra(clock: SimulationClock) -> Report {
+    let config = SimulationConfig {
+        eating_duration: EATING,
+        clock,
+        ..SimulationConfig::new(
+            (0..PHILOSOPHERS)
+                .map(|i| format!("Philosopher {}", i))
+                .collect(),
+        )
+    };
+    let handles: Vec<_> = RingTopologyBuilder::new(PHILOSOPHERS)
+        .build(&config)
+        .expect("a table of five")
+        .into_iter()
+        .map(|p| {
+            thread::spawn(move || {
+                let mut steps = VaryingThinking(p);
+                steps.run_for(clock.scaled(RUN))?;
+                let p = steps.0;
+                let longest_wait = Duration::from_nanos(p.statistics_snapshot().max_starvation_ns);
+                Ok(Tally {
+                    meals: p.meals,
+                    longest_wait: longest_wait.div_f64(clock.multiplier),
+                })
+            })
+        })
+        .collect();
+    let tallies = handles
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .collect::<Result<_, DiningPhilosophersError>>()
+        .expect("philosophers left early");
+    report("Chandy/Misra", RUN, tallies)
+}
+
+fn main() {
+    let clock = SimulationClock::new(
+        std::env::args()
+            .nth(1)
+            .map_or(1.0, |m| m.parse().expect("clock multiplier")),
+    );
+    assert!(clock.multiplier > 0.0, "time has to pass");
+    println!(
+        "{} philosophers eating for {:?} and thinking for {:?}, {:?} per algorithm",
+        PHILOSOPHERS, EATING, THINKING, RUN
+    );
+
+    let reports = [token_ring(clock), chandy_misra(clock)];
+    println!(
+        "{:<12} | {:>10} | {:>19} | {:>14}",
+        "Algorithm", "Meals/s", "Jain Fairness Index", "Max Starvation"
+    );
+    for r in &reports {
+        println!(
+            "{:<12} | {:>10.2} | {:>19.4} | {:>14}",
+            r.algorithm,
+            r.throughput(),
+            r.fairness(),
+            format!("{:.0?}", r.max_starvation())
+        );
+    }
+
+    let [ring, cm] = &reports;
+    // a single meal at a time, however many philosophers are hungry
+    let serialized = 1.0 / EATING.as_secs_f64();
+    assert!(
+        ring.throughput() <= serialized * 1.01,
+        "the token ring served more than one meal at a time"
+    );
+    assert!(
+        ring.meals.iter().all(|&m| m > 0),
+        "somebody starved with the token ring"
+    );
+    assert!(
+        cm.meals.iter().all(|&m| m > 0),
+        "somebody starved with Chandy/Misra"
+    );
+    assert!(
+        cm.throughput() > ring.throughput(),
+        "Chandy/Misra never had two philosophers eating at once"
+    );
+}
@@ -0,0 +1,64 @@
+//! Smallest possible table: two philosophers and two forks.
+//!
+//! Both forks lie between the same two philosophers, the left fork of one is the right fork of
+//! the other. The ring of `RingTopologyBuilder::new(2)` is the generic Chandy/Misra table at its
+//! edge case: every fork starts out at the lower id of the two sharing it, so philosopher 0
+//! holds both and eats first, while philosopher 1 holds none and has to ask. A circular wait
+//! needs at least two forks held by two different philosophers waiting on each other, and the
+//! precedence graph of two philosophers has a single edge that only ever flips after a meal.
+//!
+//! `cargo run --example two_philosophers -- 5` lets each of them eat 5 meals instead of 3.
+
+use std::time::Duration;
+
+pub mod chandy_misra_table;
+mod philosopher_protocol;
+
+use chandy_misra_table::{Philosopher, RingTopologyBuilder, SimulationConfig};
+
+/// Seats Baruch Spinoza and Gilles Deleuze at a ring of two and lets each of them eat `meals`
+/// meals of `eating`, back once both are full
+fn two_philosophers(meals: u64, eating: Duration) -> Vec<Philosopher> {
+    let config = SimulationConfig {
+        eating_duration: eating,
+        meals: Some(meals),
+        ..SimulationConfig::new(vec![
+            "Baruch Spinoza".to_string(),
+            "Gilles Deleuze".to_string(),
+        ])
+    };
+    let philosophers = RingTopologyBuilder::new(2)
+        .build(&config)
+        .expect("a table of two");
+    chandy_misra_table::run_table(philosophers, Duration::from_secs(3600))
+        .expect("philosophers left early")
+}
+
+fn main() {
+    let meals = std::env::args()
+        .nth(1)
+        .map_or(3, |s| s.parse().expect("number of meals"));
+    for p in two_philosophers(meals, Duration::from_millis(1000)) {
+        println!("{} ate {} times", p.name, p.meals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_philosophers_finish_their_meals() {
+        let philosophers = two_philosophers(20, Duration::from_millis(1));
+        assert_eq!(philosophers.len(), 2);
+        assert!(philosophers.iter().all(|p| p.meals == 20));
+    }
+
+    #[test]
+    fn lower_id_starts_with_both_forks() {
+        let config = SimulationConfig::new(vec!["a".to_string(), "b".to_string()]);
+        let philosophers = RingTopologyBuilder::new(2).build(&config).unwrap();
+        assert_eq!(philosophers[0].forks.held().0.len(), 2);
+        assert!(philosophers[1].forks.held().0.is_empty());
+    }
+}